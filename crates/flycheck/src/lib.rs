@@ -12,6 +12,7 @@ use std::{
 
 use crossbeam_channel::{never, select, unbounded, Receiver, Sender};
 use stdx::JodChild;
+use toolchain::{Capability, Env, Profile, RustcWrappers, ToolCommand};
 
 pub use cargo_metadata::diagnostic::{
     Applicability, Diagnostic, DiagnosticCode, DiagnosticLevel, DiagnosticSpan,
@@ -207,6 +208,8 @@ impl FlycheckActor {
         }
     }
     fn check_command(&self) -> Command {
+        // The same cargo and environment as for loading the project, see
+        // `ToolCommand::cargo`.
         let mut cmd = match &self.config {
             FlycheckConfig::CargoCommand {
                 command,
@@ -217,7 +220,7 @@ impl FlycheckActor {
                 extra_args,
                 features,
             } => {
-                let mut cmd = Command::new(toolchain::cargo());
+                let mut cmd = ToolCommand::cargo(Profile::Check);
                 cmd.arg(command);
                 cmd.args(&["--workspace", "--message-format=json", "--manifest-path"])
                     .arg(self.workspace_root.join("Cargo.toml"));
@@ -226,7 +229,12 @@ impl FlycheckActor {
                     cmd.args(&["--target", target.as_str()]);
                 }
                 if *all_targets {
-                    cmd.arg("--all-targets");
+                    match toolchain::ensure_supports(Capability::CheckAllTargets) {
+                        Ok(()) => {
+                            cmd.arg("--all-targets");
+                        }
+                        Err(err) => log::warn!("not checking all targets: {}", err),
+                    }
                 }
                 if *all_features {
                     cmd.arg("--all-features");
@@ -240,16 +248,23 @@ impl FlycheckActor {
                     }
                 }
                 cmd.args(extra_args);
+                // Both layers, as cargo would compose them for the workspace
+                // root, rather than whatever the directory of the server has.
+                let mut env = Env::current();
+                env.set_cwd(self.workspace_root.clone());
+                cmd.rustc_wrappers(&RustcWrappers::from_env(&env));
                 cmd
             }
+            // The check profile is meant for cargo. A custom command, like a
+            // script running several tools, gets the environment as is.
             FlycheckConfig::CustomCommand { command, args } => {
-                let mut cmd = Command::new(command);
+                let mut cmd = ToolCommand::new(command.into());
                 cmd.args(args);
                 cmd
             }
         };
         cmd.current_dir(&self.workspace_root);
-        cmd
+        cmd.to_command()
     }
 
     fn send(&self, check_task: Message) {
@@ -1,11 +1,6 @@
 //! Handles build script specific information
 
-use std::{
-    io::BufReader,
-    path::PathBuf,
-    process::{Command, Stdio},
-    sync::Arc,
-};
+use std::{io::BufReader, path::PathBuf, process::Stdio, sync::Arc};
 
 use anyhow::Result;
 use cargo_metadata::camino::Utf8Path;
@@ -14,6 +9,7 @@ use itertools::Itertools;
 use paths::{AbsPath, AbsPathBuf};
 use rustc_hash::FxHashMap;
 use stdx::JodChild;
+use toolchain::{Profile, ToolCommand};
 
 use crate::{cfg_flag::CfgFlag, CargoConfig};
 
@@ -103,7 +99,8 @@ fn collect_from_workspace(
     packages: &Vec<cargo_metadata::Package>,
     progress: &dyn Fn(String),
 ) -> Result<BuildDataMap> {
-    let mut cmd = Command::new(toolchain::cargo());
+    // This runs the build scripts and builds the proc-macros.
+    let mut cmd = ToolCommand::cargo(Profile::ProcMacro);
     cmd.args(&["check", "--workspace", "--message-format=json", "--manifest-path"])
         .arg(cargo_toml.as_ref());
 
@@ -130,6 +127,7 @@ fn collect_from_workspace(
         }
     }
 
+    let mut cmd = cmd.to_command();
     cmd.stdout(Stdio::piped()).stderr(Stdio::null()).stdin(Stdio::null());
 
     let mut child = cmd.spawn().map(JodChild)?;
@@ -1,19 +1,19 @@
 //! FIXME: write short doc here
 
 use std::path::PathBuf;
-use std::{convert::TryInto, ops, process::Command, sync::Arc};
+use std::{convert::TryInto, ops, sync::Arc};
 
-use anyhow::{Context, Result};
+use anyhow::{bail, format_err, Context, Result};
 use base_db::Edition;
-use cargo_metadata::{CargoOpt, MetadataCommand};
+use cargo_metadata::{Metadata, MetadataCommand};
 use la_arena::{Arena, Idx};
 use paths::{AbsPath, AbsPathBuf};
 use rustc_hash::FxHashMap;
 use serde::Deserialize;
 use serde_json::from_value;
+use toolchain::{Profile, ToolCommand};
 
 use crate::build_data::BuildDataConfig;
-use crate::utf8_stdout;
 
 /// `CargoWorkspace` represents the logical structure of, well, a Cargo
 /// workspace. It pretty closely mirrors `cargo metadata` output.
@@ -183,58 +183,48 @@ impl CargoWorkspace {
         config: &CargoConfig,
         progress: &dyn Fn(String),
     ) -> Result<CargoWorkspace> {
-        let mut meta = MetadataCommand::new();
-        meta.cargo_path(toolchain::cargo());
-        meta.manifest_path(cargo_toml.to_path_buf());
+        let mut cmd = ToolCommand::cargo(Profile::Metadata);
+        cmd.args(&["metadata", "--format-version", "1", "--manifest-path"]);
+        cmd.arg(cargo_toml.as_os_str());
         if config.all_features {
-            meta.features(CargoOpt::AllFeatures);
+            cmd.arg("--all-features");
         } else {
             if config.no_default_features {
-                // FIXME: `NoDefaultFeatures` is mutual exclusive with `SomeFeatures`
-                // https://github.com/oli-obk/cargo_metadata/issues/79
-                meta.features(CargoOpt::NoDefaultFeatures);
+                cmd.arg("--no-default-features");
             }
             if !config.features.is_empty() {
-                meta.features(CargoOpt::SomeFeatures(config.features.clone()));
+                cmd.arg("--features").arg(config.features.join(","));
             }
         }
         if let Some(parent) = cargo_toml.parent() {
-            meta.current_dir(parent.to_path_buf());
+            cmd.current_dir(parent);
         }
         let target = if let Some(target) = config.target.as_ref() {
             Some(target.clone())
         } else {
             // cargo metadata defaults to giving information for _all_ targets.
             // In the absence of a preference from the user, we use the host platform.
-            let mut rustc = Command::new(toolchain::rustc());
-            rustc.current_dir(cargo_toml.parent().unwrap()).arg("-vV");
-            log::debug!("Discovering host platform by {:?}", rustc);
-            match utf8_stdout(rustc) {
-                Ok(stdout) => {
-                    let field = "host: ";
-                    let target = stdout.lines().find_map(|l| l.strip_prefix(field));
-                    if let Some(target) = target {
-                        Some(target.to_string())
-                    } else {
-                        // If we fail to resolve the host platform, it's not the end of the world.
-                        log::info!("rustc -vV did not report host platform, got:\n{}", stdout);
-                        None
-                    }
-                }
+            let meta = match cargo_toml.parent() {
+                Some(dir) => toolchain::rustc_meta_in(&toolchain::rustc(), dir.as_ref()),
+                None => toolchain::rustc_meta(&toolchain::rustc()),
+            };
+            match meta {
+                Ok(meta) => Some(meta.host),
                 Err(e) => {
+                    // If we fail to resolve the host platform, it's not the end of the world.
                     log::warn!("Failed to discover host platform: {}", e);
                     None
                 }
             }
         };
         if let Some(target) = target {
-            meta.other_options(vec![String::from("--filter-platform"), target]);
+            cmd.args(&["--filter-platform", &target]);
         }
 
-        // FIXME: Currently MetadataCommand is not based on parse_stream,
+        // FIXME: Currently `cargo metadata` is not run with `parse_stream`,
         // So we just report it as a whole
         progress("metadata".to_string());
-        let mut meta = meta.exec().with_context(|| {
+        let mut meta = run_cargo_metadata(&cmd).with_context(|| {
             let cwd: Option<AbsPathBuf> =
                 std::env::current_dir().ok().and_then(|p| p.try_into().ok());
 
@@ -368,3 +358,17 @@ impl CargoWorkspace {
         self.packages.iter().filter(|(_, v)| v.name == name).count() == 1
     }
 }
+
+fn run_cargo_metadata(cmd: &ToolCommand) -> Result<Metadata> {
+    let output = cmd.output()?;
+    if !output.status.success() {
+        bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    let stdout = String::from_utf8(output.stdout)?;
+    // Cargo may print warnings to stdout, before the JSON.
+    let json = stdout
+        .lines()
+        .find(|line| line.starts_with('{'))
+        .ok_or_else(|| format_err!("`cargo metadata` printed no JSON"))?;
+    Ok(MetadataCommand::parse(json)?)
+}
@@ -9,14 +9,15 @@ mod rustc_cfg;
 mod build_data;
 
 use std::{
+    convert::TryFrom,
     fs::{read_dir, ReadDir},
     io,
-    process::Command,
 };
 
 use anyhow::{bail, Context, Result};
 use paths::{AbsPath, AbsPathBuf};
 use rustc_hash::FxHashSet;
+use toolchain::ToolCommand;
 
 pub use crate::{
     build_data::{BuildDataCollector, BuildDataResult},
@@ -70,7 +71,7 @@ impl ProjectManifest {
 
         fn find_cargo_toml(path: &AbsPath) -> io::Result<Vec<AbsPathBuf>> {
             match find_in_parent_dirs(path, "Cargo.toml") {
-                Some(it) => Ok(vec![it]),
+                Some(it) => Ok(vec![workspace_root(it)]),
                 None => Ok(find_cargo_toml_in_child_dir(read_dir(path)?)),
             }
         }
@@ -93,6 +94,22 @@ impl ProjectManifest {
             None
         }
 
+        /// The manifest of the workspace `manifest` is a member of, so that
+        /// opening several members loads the workspace once.
+        fn workspace_root(manifest: AbsPathBuf) -> AbsPathBuf {
+            let dir = match manifest.parent() {
+                Some(it) => it,
+                None => return manifest,
+            };
+            match toolchain::locate_workspace_root(dir.as_ref()) {
+                Ok(it) => AbsPathBuf::try_from(it).unwrap_or(manifest),
+                Err(err) => {
+                    log::warn!("can't find the workspace of {}: {}", manifest.display(), err);
+                    manifest
+                }
+            }
+        }
+
         fn find_cargo_toml_in_child_dir(entities: ReadDir) -> Vec<AbsPathBuf> {
             // Only one level down to avoid cycles the easy way and stop a runaway scan with large projects
             entities
@@ -117,14 +134,14 @@ impl ProjectManifest {
     }
 }
 
-fn utf8_stdout(mut cmd: Command) -> Result<String> {
-    let output = cmd.output().with_context(|| format!("{:?} failed", cmd))?;
+fn utf8_stdout(cmd: &ToolCommand) -> Result<String> {
+    let output = cmd.output().with_context(|| format!("`{}` failed", cmd))?;
     if !output.status.success() {
         match String::from_utf8(output.stderr) {
             Ok(stderr) if !stderr.is_empty() => {
-                bail!("{:?} failed, {}\nstderr:\n{}", cmd, output.status, stderr)
+                bail!("`{}` failed, {}\nstderr:\n{}", cmd, output.status, stderr)
             }
-            _ => bail!("{:?} failed, {}", cmd, output.status),
+            _ => bail!("`{}` failed, {}", cmd, output.status),
         }
     }
     let stdout = String::from_utf8(output.stdout)?;
@@ -1,8 +1,8 @@
 //! Runs `rustc --print cfg` to get built-in cfg flags.
 
-use std::process::Command;
+use toolchain::CfgAtom;
 
-use crate::{cfg_flag::CfgFlag, utf8_stdout};
+use crate::cfg_flag::CfgFlag;
 
 pub(crate) fn get(target: Option<&str>) -> Vec<CfgFlag> {
     let _p = profile::span("rustc_cfg::get");
@@ -16,17 +16,11 @@ pub(crate) fn get(target: Option<&str>) -> Vec<CfgFlag> {
         }
     }
 
-    let rustc_cfgs = {
-        let mut cmd = Command::new(toolchain::rustc());
-        cmd.args(&["--print", "cfg", "-O"]);
-        if let Some(target) = target {
-            cmd.args(&["--target", target]);
-        }
-        utf8_stdout(cmd)
-    };
-
-    match rustc_cfgs {
-        Ok(rustc_cfgs) => res.extend(rustc_cfgs.lines().map(|it| it.parse().unwrap())),
+    match toolchain::rustc_cfgs(&toolchain::rustc(), target) {
+        Ok(rustc_cfgs) => res.extend(rustc_cfgs.into_iter().map(|it| match it {
+            CfgAtom::Flag(it) => CfgFlag::Atom(it),
+            CfgAtom::KeyValue { key, value } => CfgFlag::KeyValue { key, value },
+        })),
         Err(e) => log::error!("failed to get rustc cfgs: {:#}", e),
     }
 
@@ -4,11 +4,19 @@
 //! but we can't process `.rlib` and need source code instead. The source code
 //! is typically installed with `rustup component add rust-src` command.
 
-use std::{convert::TryFrom, env, ops, path::PathBuf, process::Command};
+use std::{
+    env,
+    ffi::{OsStr, OsString},
+    ops,
+    path::PathBuf,
+    sync::Arc,
+};
 
 use anyhow::{format_err, Result};
 use la_arena::{Arena, Idx};
+use once_cell::sync::Lazy;
 use paths::{AbsPath, AbsPathBuf};
+use toolchain::{SharedToolchains, ToolCommand, ToolchainId};
 
 use crate::utf8_stdout;
 
@@ -19,6 +27,9 @@ pub struct Sysroot {
 
 pub(crate) type SysrootCrate = Idx<SysrootCrateData>;
 
+static SYSROOTS: Lazy<SharedToolchains<Sysroot, (ToolchainId, Option<OsString>)>> =
+    Lazy::new(Default::default);
+
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct SysrootCrateData {
     pub name: String,
@@ -48,13 +59,31 @@ impl Sysroot {
         self.crates.iter().map(|(id, _data)| id)
     }
 
-    pub fn discover(cargo_toml: &AbsPath) -> Result<Sysroot> {
+    /// Workspaces on the same toolchain get the same `Sysroot`, which is
+    /// only loaded once.
+    pub fn discover(cargo_toml: &AbsPath) -> Result<Arc<Sysroot>> {
         log::debug!("Discovering sysroot for {}", cargo_toml.display());
         let current_dir = cargo_toml.parent().unwrap();
         let sysroot_dir = discover_sysroot_dir(current_dir)?;
-        let sysroot_src_dir = discover_sysroot_src_dir(&sysroot_dir, current_dir)?;
-        let res = Sysroot::load(&sysroot_src_dir)?;
-        Ok(res)
+        let load = || {
+            let sysroot_src_dir = discover_sysroot_src_dir(&sysroot_dir, current_dir)?;
+            Sysroot::load(&sysroot_src_dir)
+        };
+        match ToolchainId::of(sysroot_dir.as_ref()) {
+            // The client environment may set `RUST_SRC_PATH`, which wins
+            // over the sources of the toolchain.
+            Ok(id) => {
+                let rust_src_path =
+                    toolchain::Env::current().var_os("RUST_SRC_PATH").map(OsStr::to_os_string);
+                SYSROOTS.get_or_try_insert_with((id, rust_src_path), load)
+            }
+            Err(_) => load().map(Arc::new),
+        }
+    }
+
+    /// Forgets the shared sysroots no workspace uses anymore.
+    pub fn prune_shared() {
+        SYSROOTS.prune();
     }
 
     pub fn discover_rustc(cargo_toml: &AbsPath) -> Option<AbsPathBuf> {
@@ -118,10 +147,10 @@ impl Sysroot {
 }
 
 fn discover_sysroot_dir(current_dir: &AbsPath) -> Result<AbsPathBuf> {
-    let mut rustc = Command::new(toolchain::rustc());
+    let mut rustc = ToolCommand::new(toolchain::rustc());
     rustc.current_dir(current_dir).args(&["--print", "sysroot"]);
-    log::debug!("Discovering sysroot by {:?}", rustc);
-    let stdout = utf8_stdout(rustc)?;
+    log::debug!("Discovering sysroot by `{}`", rustc);
+    let stdout = utf8_stdout(&rustc)?;
     Ok(AbsPathBuf::assert(PathBuf::from(stdout)))
 }
 
@@ -129,22 +158,23 @@ fn discover_sysroot_src_dir(
     sysroot_path: &AbsPathBuf,
     current_dir: &AbsPath,
 ) -> Result<AbsPathBuf> {
-    if let Ok(path) = env::var("RUST_SRC_PATH") {
-        let path = AbsPathBuf::try_from(path.as_str())
-            .map_err(|path| format_err!("RUST_SRC_PATH must be absolute: {}", path.display()))?;
-        let core = path.join("core");
-        if core.exists() {
+    match toolchain::rust_src_from_env(&toolchain::Env::current(), &toolchain::rustc()) {
+        Some(Ok(path)) => {
             log::debug!("Discovered sysroot by RUST_SRC_PATH: {}", path.display());
-            return Ok(path);
+            return Ok(AbsPathBuf::assert(path));
         }
-        log::debug!("RUST_SRC_PATH is set, but is invalid (no core: {:?}), ignoring", core);
+        Some(Err(err)) => log::warn!("{}, ignoring", err),
+        None => (),
     }
 
     get_rust_src(&sysroot_path)
         .or_else(|| {
-            let mut rustup = Command::new(toolchain::rustup());
-            rustup.current_dir(current_dir).args(&["component", "add", "rust-src"]);
-            utf8_stdout(rustup).ok()?;
+            let mut rustup = toolchain::rustup_component_add("rust-src");
+            rustup.current_dir(current_dir);
+            if let Err(err) = toolchain::run_install("rust-src", &rustup) {
+                log::warn!("{}", err);
+                return None;
+            }
             get_rust_src(&sysroot_path)
         })
         .ok_or_else(|| {
@@ -170,10 +200,11 @@ fn get_rustc_src(sysroot_path: &AbsPath) -> Option<AbsPathBuf> {
 }
 
 fn get_rust_src(sysroot_path: &AbsPath) -> Option<AbsPathBuf> {
-    // Try the new path first since the old one still exists.
-    let rust_src = sysroot_path.join("lib/rustlib/src/rust");
-    log::debug!("Checking sysroot (looking for `library` and `src` dirs): {}", rust_src.display());
-    ["library", "src"].iter().map(|it| rust_src.join(it)).find(|it| it.exists())
+    log::debug!(
+        "Checking sysroot (looking for `library` and `src` dirs): {}",
+        sysroot_path.display()
+    );
+    toolchain::rust_src(sysroot_path.as_ref()).map(AbsPathBuf::assert)
 }
 
 impl SysrootCrateData {
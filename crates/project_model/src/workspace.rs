@@ -2,7 +2,7 @@
 //! metadata` or `rust-project.json`) into representation stored in the salsa
 //! database -- `CrateGraph`.
 
-use std::{collections::VecDeque, fmt, fs, path::Path, process::Command};
+use std::{collections::VecDeque, fmt, fs, path::Path, sync::Arc};
 
 use anyhow::{Context, Result};
 use base_db::{CrateDisplayName, CrateGraph, CrateId, CrateName, Edition, Env, FileId, ProcMacro};
@@ -10,6 +10,7 @@ use cfg::CfgOptions;
 use paths::{AbsPath, AbsPathBuf};
 use proc_macro_api::ProcMacroClient;
 use rustc_hash::{FxHashMap, FxHashSet};
+use toolchain::{Profile, ToolCommand};
 
 use crate::{
     build_data::{BuildData, BuildDataMap, BuildDataResult},
@@ -37,7 +38,7 @@ pub enum ProjectWorkspace {
     /// Project workspace was discovered by running `cargo metadata` and `rustc --print sysroot`.
     Cargo {
         cargo: CargoWorkspace,
-        sysroot: Sysroot,
+        sysroot: Arc<Sysroot>,
         rustc: Option<CargoWorkspace>,
         /// Holds cfg flags for the current target. We get those by running
         /// `rustc --print cfg`.
@@ -47,7 +48,7 @@ pub enum ProjectWorkspace {
         rustc_cfg: Vec<CfgFlag>,
     },
     /// Project workspace was manually specified using a `rust-project.json` file.
-    Json { project: ProjectJson, sysroot: Option<Sysroot>, rustc_cfg: Vec<CfgFlag> },
+    Json { project: ProjectJson, sysroot: Option<Arc<Sysroot>>, rustc_cfg: Vec<CfgFlag> },
 }
 
 impl fmt::Debug for ProjectWorkspace {
@@ -97,11 +98,25 @@ impl ProjectWorkspace {
                 ProjectWorkspace::load_inline(project_json, config.target.as_deref())?
             }
             ProjectManifest::CargoToml(cargo_toml) => {
-                let cargo_version = utf8_stdout({
-                    let mut cmd = Command::new(toolchain::cargo());
-                    cmd.arg("--version");
-                    cmd
-                })?;
+                // The cargo `cargo metadata` runs with, in the same directory.
+                let mut cmd = ToolCommand::cargo(Profile::Metadata);
+                cmd.arg("--version");
+                if let Some(dir) = cargo_toml.parent() {
+                    cmd.current_dir(dir);
+                }
+                let cargo_version = utf8_stdout(&cmd)?;
+
+                // Settle on the target once, so that metadata and cfgs agree.
+                let build_target = cargo_toml.parent().and_then(|dir| {
+                    toolchain::build_target(dir.as_ref(), config.target.as_deref())
+                });
+                if let Some(build_target) = &build_target {
+                    if let Err(err) = build_target.check_installed(&toolchain::rustc()) {
+                        log::warn!("{}", err);
+                    }
+                }
+                let config =
+                    &CargoConfig { target: build_target.map(|it| it.triple), ..config.clone() };
 
                 let cargo = CargoWorkspace::from_cargo_metadata(&cargo_toml, config, progress)
                     .with_context(|| {
@@ -113,7 +128,7 @@ impl ProjectWorkspace {
                     })?;
 
                 let sysroot = if config.no_sysroot {
-                    Sysroot::default()
+                    Arc::default()
                 } else {
                     Sysroot::discover(&cargo_toml).with_context(|| {
                         format!(
@@ -156,7 +171,7 @@ impl ProjectWorkspace {
         target: Option<&str>,
     ) -> Result<ProjectWorkspace> {
         let sysroot = match &project_json.sysroot_src {
-            Some(path) => Some(Sysroot::load(path)?),
+            Some(path) => Some(Arc::new(Sysroot::load(path)?)),
             None => None,
         };
         let rustc_cfg = rustc_cfg::get(target);
@@ -289,7 +304,7 @@ fn project_json_to_crate_graph(
     proc_macro_loader: &dyn Fn(&Path) -> Vec<ProcMacro>,
     load: &mut dyn FnMut(&AbsPath) -> Option<FileId>,
     project: &ProjectJson,
-    sysroot: &Option<Sysroot>,
+    sysroot: &Option<Arc<Sysroot>>,
 ) -> CrateGraph {
     let mut crate_graph = CrateGraph::default();
     let sysroot_deps = sysroot
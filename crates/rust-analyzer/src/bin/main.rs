@@ -193,6 +193,19 @@ fn run_server() -> Result<()> {
         if let Some(json) = initialize_params.initialization_options {
             config.update(json);
         }
+        // The environment is computed with the workspaces, as it may take a
+        // while, and the prefix is validated again then.
+        toolchain::set_command_prefix(config.toolchain_command_prefix());
+        toolchain::set_augment_path(config.toolchain_augment_path());
+        toolchain::set_bypass_rustup_proxies(config.toolchain_bypass_rustup_proxies());
+        match toolchain::UserConfig::load(&toolchain::Env::current()) {
+            Ok(Some(user_config)) => {
+                log::info!("using {}", user_config.file.display());
+                user_config.apply();
+            }
+            Ok(None) => (),
+            Err(err) => log::error!("failed to read the toolchain config: {}", err),
+        }
 
         if config.linked_projects().is_empty() {
             let workspace_roots = initialize_params
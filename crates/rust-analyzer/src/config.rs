@@ -7,7 +7,7 @@
 //! configure the server itself, feature flags are passed into analysis, and
 //! tweak things like automatic insertion of `()` in completions.
 
-use std::{ffi::OsString, iter, path::PathBuf};
+use std::{ffi::OsString, iter, path::PathBuf, time::Duration};
 
 use flycheck::FlycheckConfig;
 use hir::PrefixKind;
@@ -18,9 +18,9 @@ use ide_db::helpers::{
 };
 use lsp_types::{ClientCapabilities, MarkupKind};
 use project_model::{CargoConfig, ProjectJson, ProjectJsonData, ProjectManifest, RustcSource};
-use rustc_hash::FxHashSet;
+use rustc_hash::{FxHashMap, FxHashSet};
 use serde::{de::DeserializeOwned, Deserialize};
-use vfs::AbsPathBuf;
+use vfs::{AbsPath, AbsPathBuf};
 
 use crate::{
     caps::completion_item_edit_resolve, diagnostics::DiagnosticsMapConfig,
@@ -197,6 +197,52 @@ config_data! {
         /// Advanced option, fully override the command rust-analyzer uses for
         /// formatting.
         rustfmt_overrideCommand: Option<Vec<String>> = "null",
+
+        /// Environment variables to use when looking for and running `cargo`,
+        /// `rustc` and other tools, taking precedence over the environment of
+        /// the server. Useful when the server is started with a minimal
+        /// environment, for example without the `PATH` of a login shell.
+        toolchain_env: FxHashMap<String, String> = "{}",
+        /// Whether to start from the environment of the user's login shell,
+        /// with its profiles loaded, when looking for and running tools. The
+        /// other `#rust-analyzer.toolchain.*#` environment settings take
+        /// precedence.
+        toolchain_loginShell: bool = "false",
+        /// Shell command setting up the environment for the tools, like
+        /// `source /opt/rust/env.sh` or `module load rust`. It runs in `sh`
+        /// (`cmd` on Windows) in the workspace root, after the login shell,
+        /// and the variables it sets are added to the environment of the
+        /// tools. The `.env` file, direnv and `#rust-analyzer.toolchain.env#`
+        /// take precedence.
+        toolchain_setupScript: Option<String> = "null",
+        /// Whether to add the variables of the `.env` file in the workspace
+        /// root to the environment of the tools. Variables set in
+        /// `#rust-analyzer.toolchain.env#` take precedence.
+        toolchain_dotenv: bool = "false",
+        /// Whether to add the variables set by the `.envrc` of the workspace
+        /// root, as exported by `direnv`, to the environment of the tools.
+        /// They take precedence over the `.env` file, but not over
+        /// `#rust-analyzer.toolchain.env#`.
+        toolchain_direnv: bool = "false",
+        /// Command to run every tool through, like `["nice", "-n19"]` or
+        /// `["nix", "develop", "-c"]`.
+        toolchain_commandPrefix: Vec<String> = "[]",
+        /// Whether to put the directories of the discovered `cargo` and
+        /// `rustc` first in the `PATH` of the tools, so that build scripts
+        /// and proc-macros running them use the same toolchain.
+        toolchain_augmentPath: bool = "false",
+        /// Whether to run the binaries of the toolchain directly instead of
+        /// through rustup's proxies, which take a while to start.
+        toolchain_bypassRustupProxies: bool = "false",
+        /// How often, in seconds, to check in the background that the tools
+        /// found so far still exist and report the same version, reloading
+        /// the workspaces when they don't. Disabled if `null` or `0`.
+        toolchain_revalidationInterval: Option<usize> = "null",
+        /// How often, in seconds, to check whether the variables affecting
+        /// discovery, the rustup default or the files of `cargo`, `rustc`
+        /// and `rustfmt` changed, reloading the workspaces when they did.
+        /// Disabled if `null` or `0`.
+        toolchain_monitorInterval: Option<usize> = "5",
     }
 }
 
@@ -282,6 +328,65 @@ pub enum RustfmtConfig {
     CustomCommand { command: String, args: Vec<String> },
 }
 
+/// Where the environment of the tools comes from. Computing it may run the
+/// login shell, a setup script and direnv, so it's only done when this
+/// changes, or one of [`ENV_FILE_NAMES`] does.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ToolchainEnvConfig {
+    pub login_shell: bool,
+    pub setup_script: Option<String>,
+    pub dotenv: bool,
+    pub direnv: bool,
+    pub env: FxHashMap<String, String>,
+    pub root_path: AbsPathBuf,
+}
+
+/// The files in the root which [`ToolchainEnvConfig::compute`] reads.
+pub const ENV_FILE_NAMES: &[&str] = &[".env", ".envrc"];
+
+pub fn is_env_file(path: &AbsPath) -> bool {
+    let name = path.file_name().and_then(|it| it.to_str()).unwrap_or_default();
+    ENV_FILE_NAMES.contains(&name)
+}
+
+impl ToolchainEnvConfig {
+    pub fn compute(&self) -> Vec<(String, String)> {
+        let mut env = FxHashMap::default();
+        if self.login_shell {
+            match toolchain::login_shell().map(|it| it.cached_env()) {
+                Some(Ok(vars)) => env.extend(vars),
+                Some(Err(err)) => {
+                    log::error!("failed to capture the login shell environment: {}", err)
+                }
+                None => log::warn!("no login shell found"),
+            }
+        }
+        if let Some(script) = &self.setup_script {
+            let base: Vec<_> = env.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+            match toolchain::run_setup_script(script, self.root_path.as_ref(), &base) {
+                Ok(vars) => env.extend(vars),
+                Err(err) => log::error!("failed to run the setup script: {}", err),
+            }
+        }
+        if self.dotenv {
+            match toolchain::load_dotenv(self.root_path.as_ref()) {
+                Ok(vars) => env.extend(vars),
+                Err(err) => log::error!("failed to load .env: {}", err),
+            }
+        }
+        if self.direnv {
+            match toolchain::direnv_export(self.root_path.as_ref()) {
+                Ok(vars) => env.extend(vars.into_iter().flatten()),
+                Err(err) => log::error!("failed to load the direnv environment: {}", err),
+            }
+        }
+        env.extend(self.env.iter().map(|(k, v)| (k.clone(), v.clone())));
+        let mut res: Vec<_> = env.into_iter().collect();
+        res.sort();
+        res
+    }
+}
+
 /// Configuration for runnable items, such as `main` function or tests.
 #[derive(Debug, Clone)]
 pub struct RunnablesConfig {
@@ -465,6 +570,43 @@ impl Config {
             warnings_as_hint: self.data.diagnostics_warningsAsHint.clone(),
         }
     }
+    pub fn toolchain_env(&self) -> ToolchainEnvConfig {
+        ToolchainEnvConfig {
+            login_shell: self.data.toolchain_loginShell,
+            setup_script: self.data.toolchain_setupScript.clone(),
+            dotenv: self.data.toolchain_dotenv,
+            direnv: self.data.toolchain_direnv,
+            env: self.data.toolchain_env.clone(),
+            root_path: self.root_path.clone(),
+        }
+    }
+    pub fn toolchain_augment_path(&self) -> bool {
+        self.data.toolchain_augmentPath
+    }
+    pub fn toolchain_bypass_rustup_proxies(&self) -> bool {
+        self.data.toolchain_bypassRustupProxies
+    }
+    pub fn toolchain_revalidation_interval(&self) -> Option<Duration> {
+        self.data
+            .toolchain_revalidationInterval
+            .filter(|&it| it > 0)
+            .map(|it| Duration::from_secs(it as u64))
+    }
+    pub fn toolchain_monitor_interval(&self) -> Option<Duration> {
+        self.data
+            .toolchain_monitorInterval
+            .filter(|&it| it > 0)
+            .map(|it| Duration::from_secs(it as u64))
+    }
+    pub fn toolchain_command_prefix(&self) -> Option<toolchain::CommandPrefix> {
+        match toolchain::CommandPrefix::new(self.data.toolchain_commandPrefix.clone()) {
+            Ok(it) => it,
+            Err(err) => {
+                log::error!("ignoring the command prefix: {}", err);
+                None
+            }
+        }
+    }
     pub fn lru_capacity(&self) -> Option<usize> {
         self.data.lruCapacity
     }
@@ -801,6 +943,10 @@ fn field_props(field: &str, ty: &str, doc: &[&str], default: &str) -> serde_json
             "type": "array",
             "items": { "type": "string" },
         },
+        "FxHashMap<String, String>" => set! {
+            "type": "object",
+            "additionalProperties": { "type": "string" },
+        },
         "FxHashSet<String>" => set! {
             "type": "array",
             "items": { "type": "string" },
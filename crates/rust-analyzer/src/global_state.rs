@@ -15,10 +15,11 @@ use project_model::{
     BuildDataCollector, BuildDataResult, CargoWorkspace, ProcMacroClient, ProjectWorkspace, Target,
 };
 use rustc_hash::FxHashMap;
+use toolchain::{Revalidator, ToolchainEvent, ToolchainMonitor};
 use vfs::AnchoredPathBuf;
 
 use crate::{
-    config::Config,
+    config::{is_env_file, Config},
     diagnostics::{CheckFixes, DiagnosticCollection},
     document::DocumentData,
     from_proto,
@@ -71,6 +72,10 @@ pub(crate) struct GlobalState {
     pub(crate) flycheck: Vec<FlycheckHandle>,
     pub(crate) flycheck_sender: Sender<flycheck::Message>,
     pub(crate) flycheck_receiver: Receiver<flycheck::Message>,
+    pub(crate) toolchain_monitor: Option<ToolchainMonitor>,
+    pub(crate) toolchain_revalidator: Option<Revalidator>,
+    pub(crate) toolchain_sender: Sender<ToolchainEvent>,
+    pub(crate) toolchain_receiver: Receiver<ToolchainEvent>,
     pub(crate) config: Arc<Config>,
     pub(crate) analysis_host: AnalysisHost,
     pub(crate) diagnostics: DiagnosticCollection,
@@ -83,6 +88,9 @@ pub(crate) struct GlobalState {
     pub(crate) proc_macro_client: Option<ProcMacroClient>,
     pub(crate) workspaces: Arc<Vec<ProjectWorkspace>>,
     pub(crate) fetch_workspaces_queue: OpQueue<()>,
+    /// Whether the next workspace fetch recomputes the environment of the
+    /// tools.
+    pub(crate) stale_toolchain_env: bool,
     pub(crate) workspace_build_data: Option<BuildDataResult>,
     pub(crate) fetch_build_data_queue: OpQueue<BuildDataCollector>,
     latest_requests: Arc<RwLock<LatestRequests>>,
@@ -118,7 +126,8 @@ impl GlobalState {
 
         let analysis_host = AnalysisHost::new(config.lru_capacity());
         let (flycheck_sender, flycheck_receiver) = unbounded();
-        GlobalState {
+        let (toolchain_sender, toolchain_receiver) = unbounded();
+        let mut res = GlobalState {
             sender,
             req_queue: ReqQueue::default(),
             vfs_config_version: 0,
@@ -127,6 +136,10 @@ impl GlobalState {
             flycheck: Vec::new(),
             flycheck_sender,
             flycheck_receiver,
+            toolchain_monitor: None,
+            toolchain_revalidator: None,
+            toolchain_sender,
+            toolchain_receiver,
             config: Arc::new(config),
             analysis_host,
             diagnostics: Default::default(),
@@ -139,10 +152,14 @@ impl GlobalState {
             proc_macro_client: None,
             workspaces: Arc::new(Vec::new()),
             fetch_workspaces_queue: OpQueue::default(),
+            stale_toolchain_env: true,
             workspace_build_data: None,
             fetch_build_data_queue: OpQueue::default(),
             latest_requests: Default::default(),
-        }
+        };
+        res.reload_toolchain_monitor();
+        res.reload_toolchain_revalidator();
+        res
     }
 
     pub(crate) fn process_changes(&mut self) -> bool {
@@ -164,6 +181,12 @@ impl GlobalState {
                         fs_changes.push((path.to_path_buf(), file.change_kind));
                         has_fs_changes = true;
                     }
+                } else if let Some(path) = vfs.file_path(file.file_id).as_path() {
+                    // Editing a pin or an env file changes the toolchain, not
+                    // the file set.
+                    if toolchain::is_pin_file(path.as_ref()) || is_env_file(path) {
+                        fs_changes.push((path.to_path_buf(), file.change_kind));
+                    }
                 }
 
                 let text = if file.exists() {
@@ -2,10 +2,7 @@
 //! Protocol. The majority of requests are fulfilled by calling into the
 //! `ide` crate.
 
-use std::{
-    io::Write as _,
-    process::{self, Stdio},
-};
+use std::{io::Write as _, process::Stdio};
 
 use ide::{
     AnnotationConfig, FileId, FilePosition, FileRange, HoverAction, HoverGotoTypeData, Query,
@@ -29,6 +26,7 @@ use serde::{Deserialize, Serialize};
 use serde_json::to_value;
 use stdx::{format_to, split_once};
 use syntax::{algo, ast, AstNode, TextRange, TextSize};
+use toolchain::ToolCommand;
 
 use crate::{
     cargo_target_spec::CargoTargetSpec,
@@ -79,6 +77,27 @@ pub(crate) fn handle_analyzer_status(
         let mark = if is_last { "*" } else { " " };
         format_to!(buf, "{}{:4} {:<36}{}ms\n", mark, r.id, r.method, r.duration.as_millis());
     }
+    format_to!(
+        buf,
+        "\ntoolchain discovery:\n{}\n{}",
+        toolchain::discovery_metrics(),
+        toolchain::discovery_timings()
+    );
+    format_to!(buf, "\ntools:\n");
+    for (tool, status) in toolchain::discover_all() {
+        format_to!(buf, "{:<16}{}\n", tool, status);
+    }
+    let repairs = toolchain::repair_suggestions(&toolchain::Env::current(), || {
+        let shell = toolchain::login_shell()?;
+        let vars = shell.cached_env().ok()?;
+        Some((shell, vars))
+    });
+    if !repairs.is_empty() {
+        format_to!(buf, "\nrepairs:\n");
+        for repair in repairs {
+            format_to!(buf, "{}\n", repair);
+        }
+    }
     Ok(buf)
 }
 
@@ -880,9 +899,10 @@ pub(crate) fn handle_formatting(
 
     let line_index = snap.file_line_index(file_id)?;
 
-    let mut rustfmt = match snap.config.rustfmt() {
+    // Through `ToolCommand`, for the command prefix and the client environment.
+    let rustfmt = match snap.config.rustfmt() {
         RustfmtConfig::Rustfmt { extra_args } => {
-            let mut cmd = process::Command::new(toolchain::rustfmt());
+            let mut cmd = ToolCommand::new(toolchain::rustfmt());
             cmd.args(extra_args);
             // try to chdir to the file so we can respect `rustfmt.toml`
             // FIXME: use `rustfmt --config-path` once
@@ -910,14 +930,18 @@ pub(crate) fn handle_formatting(
             cmd
         }
         RustfmtConfig::CustomCommand { command, args } => {
-            let mut cmd = process::Command::new(command);
+            let mut cmd = ToolCommand::new(command.into());
             cmd.args(args);
             cmd
         }
     };
 
-    let mut rustfmt =
-        rustfmt.stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()?;
+    let mut rustfmt = rustfmt
+        .to_command()
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
 
     rustfmt.stdin.as_mut().unwrap().write_all(file.as_bytes())?;
 
@@ -56,6 +56,7 @@ enum Event {
     Task(Task),
     Vfs(vfs::loader::Message),
     Flycheck(flycheck::Message),
+    Toolchain(toolchain::ToolchainEvent),
 }
 
 #[derive(Debug)]
@@ -95,6 +96,7 @@ impl fmt::Debug for Event {
             Event::Task(it) => fmt::Debug::fmt(it, f),
             Event::Vfs(it) => fmt::Debug::fmt(it, f),
             Event::Flycheck(it) => fmt::Debug::fmt(it, f),
+            Event::Toolchain(it) => fmt::Debug::fmt(it, f),
         }
     }
 }
@@ -173,6 +175,9 @@ impl GlobalState {
 
             recv(self.flycheck_receiver) -> task =>
                 Some(Event::Flycheck(task.unwrap())),
+
+            recv(self.toolchain_receiver) -> event =>
+                Some(Event::Toolchain(event.unwrap())),
         }
     }
 
@@ -397,6 +402,14 @@ impl GlobalState {
                     }
                 }
             }
+            Event::Toolchain(event) => {
+                // Sysroot, cfgs and proc-macros all depend on the toolchain,
+                // and are recomputed by reloading the workspaces. Changes
+                // usually come in bursts, like during `rustup update`.
+                log::info!("toolchain changed: {:?}", event);
+                while self.toolchain_receiver.try_recv().is_ok() {}
+                self.fetch_workspaces_request();
+            }
         }
 
         let state_changed = self.process_changes();
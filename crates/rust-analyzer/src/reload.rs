@@ -4,11 +4,14 @@ use std::{mem, sync::Arc};
 use flycheck::{FlycheckConfig, FlycheckHandle};
 use ide::Change;
 use ide_db::base_db::{CrateGraph, SourceRoot, VfsPath};
-use project_model::{BuildDataCollector, BuildDataResult, ProcMacroClient, ProjectWorkspace};
+use project_model::{
+    BuildDataCollector, BuildDataResult, ProcMacroClient, ProjectWorkspace, Sysroot,
+};
+use toolchain::{Revalidator, ToolchainMonitor};
 use vfs::{file_set::FileSetConfig, AbsPath, AbsPathBuf, ChangeKind};
 
 use crate::{
-    config::{Config, FilesWatcher, LinkedProject},
+    config::{is_env_file, Config, FilesWatcher, LinkedProject, ENV_FILE_NAMES},
     global_state::{GlobalState, Status},
     lsp_ext,
     main_loop::Task,
@@ -36,11 +39,35 @@ impl GlobalState {
         if self.config.lru_capacity() != old_config.lru_capacity() {
             self.analysis_host.update_lru_capacity(self.config.lru_capacity());
         }
-        if self.config.linked_projects() != old_config.linked_projects() {
+        // Computing the environment may run the login shell or direnv, so it's
+        // done with the workspaces, off the main thread.
+        if self.config.toolchain_env() != old_config.toolchain_env() {
+            self.stale_toolchain_env = true;
+        }
+        let prefix_changed = toolchain::set_command_prefix(self.config.toolchain_command_prefix());
+        // Not short-circuiting, every setting has to be applied.
+        let augment_path_changed =
+            toolchain::set_augment_path(self.config.toolchain_augment_path());
+        let bypass_changed =
+            toolchain::set_bypass_rustup_proxies(self.config.toolchain_bypass_rustup_proxies());
+        if self.stale_toolchain_env
+            || prefix_changed
+            || augment_path_changed
+            || bypass_changed
+            || self.config.linked_projects() != old_config.linked_projects()
+        {
             self.fetch_workspaces_request()
         } else if self.config.flycheck() != old_config.flycheck() {
             self.reload_flycheck();
         }
+        if self.config.toolchain_monitor_interval() != old_config.toolchain_monitor_interval() {
+            self.reload_toolchain_monitor();
+        }
+        if self.config.toolchain_revalidation_interval()
+            != old_config.toolchain_revalidation_interval()
+        {
+            self.reload_toolchain_revalidator();
+        }
     }
     pub(crate) fn maybe_refresh(&mut self, changes: &[(AbsPathBuf, ChangeKind)]) {
         if !changes.iter().any(|(path, kind)| is_interesting(path, *kind)) {
@@ -60,6 +87,17 @@ impl GlobalState {
                 ", "
             )
         );
+        if changes.iter().any(|(path, _)| is_env_file(path)) {
+            self.stale_toolchain_env = true;
+        }
+        for (path, _) in changes.iter().filter(|(path, _)| toolchain::is_pin_file(path.as_ref())) {
+            // The resolved rustup proxies were picked for the old pin.
+            toolchain::invalidate_tools();
+            if let Some(dir) = path.parent() {
+                let pin = toolchain::toolchain_pin(dir.as_ref());
+                log::info!("toolchain pinned for {}: {:?}", dir.display(), pin);
+            }
+        }
         if self.config.cargo_autoreload() {
             self.fetch_workspaces_request();
         } else {
@@ -73,6 +111,9 @@ impl GlobalState {
             if path.ends_with("Cargo.toml") || path.ends_with("Cargo.lock") {
                 return true;
             }
+            if toolchain::is_pin_file(path.as_ref()) || is_env_file(path) {
+                return true;
+            }
             if change_kind == ChangeKind::Modify {
                 return false;
             }
@@ -155,8 +196,15 @@ impl GlobalState {
         self.task_pool.handle.spawn_with_sender({
             let linked_projects = self.config.linked_projects();
             let cargo_config = self.config.cargo();
+            let config = Arc::clone(&self.config);
+            let refresh_env = mem::replace(&mut self.stale_toolchain_env, false);
 
             move |sender| {
+                if refresh_env && toolchain::set_client_env(config.toolchain_env().compute()) {
+                    // The prefix is validated against the environment.
+                    toolchain::set_command_prefix(config.toolchain_command_prefix());
+                }
+
                 let progress = {
                     let sender = sender.clone();
                     move |msg| {
@@ -253,7 +301,13 @@ impl GlobalState {
                         .flat_map(|it| it.to_roots(workspace_build_data.as_ref()))
                         .filter(|it| it.is_member)
                         .flat_map(|root| {
-                            root.include.into_iter().map(|it| format!("{}/**/*.rs", it.display()))
+                            root.include.into_iter().flat_map(|it| {
+                                vec![
+                                    format!("{}/**/*.rs", it.display()),
+                                    format!("{}/rust-toolchain*", it.display()),
+                                    format!("{}/.env*", it.display()),
+                                ]
+                            })
                         })
                         .map(|glob_pattern| lsp_types::FileSystemWatcher {
                             glob_pattern,
@@ -279,6 +333,8 @@ impl GlobalState {
         let project_folders =
             ProjectFolders::new(&workspaces, &files_config.exclude, workspace_build_data.as_ref());
 
+        // A single server is shared by all workspaces, whatever their
+        // toolchain: it's rust-analyzer itself, not a tool of the toolchain.
         if self.proc_macro_client.is_none() {
             self.proc_macro_client = match self.config.proc_macro_srv() {
                 None => None,
@@ -347,6 +403,7 @@ impl GlobalState {
 
         self.source_root_config = project_folders.source_root_config;
         self.workspaces = Arc::new(workspaces);
+        Sysroot::prune_shared();
         self.workspace_build_data = workspace_build_data;
 
         self.analysis_host.apply_change(change);
@@ -392,6 +449,21 @@ impl GlobalState {
             })
             .collect();
     }
+
+    pub(crate) fn reload_toolchain_monitor(&mut self) {
+        let sender = self.toolchain_sender.clone();
+        self.toolchain_monitor = self.config.toolchain_monitor_interval().map(|interval| {
+            ToolchainMonitor::spawn(interval, Box::new(move |event| sender.send(event).unwrap()))
+        });
+    }
+
+    pub(crate) fn reload_toolchain_revalidator(&mut self) {
+        let sender = self.toolchain_sender.clone();
+        self.toolchain_revalidator =
+            self.config.toolchain_revalidation_interval().map(|interval| {
+                Revalidator::spawn(interval, Box::new(move |event| sender.send(event).unwrap()))
+            });
+    }
 }
 
 #[derive(Default)]
@@ -415,6 +487,23 @@ impl ProjectFolders {
             let file_set_roots: Vec<VfsPath> =
                 root.include.iter().cloned().map(VfsPath::from).collect();
 
+            // Watching a missing file fails, so a newly created pin or env
+            // file is only noticed through the client's watcher.
+            let pins: Vec<AbsPathBuf> = if root.is_member {
+                root.include
+                    .iter()
+                    .flat_map(|dir| {
+                        toolchain::PIN_FILE_NAMES
+                            .iter()
+                            .chain(ENV_FILE_NAMES)
+                            .map(move |it| dir.join(it))
+                    })
+                    .filter(|it| it.is_file())
+                    .collect()
+            } else {
+                Vec::new()
+            };
+
             let entry = {
                 let mut dirs = vfs::loader::Directories::default();
                 dirs.extensions.push("rs".into());
@@ -434,6 +523,11 @@ impl ProjectFolders {
             }
             res.load.push(entry);
 
+            if !pins.is_empty() {
+                res.watch.push(res.load.len());
+                res.load.push(vfs::loader::Entry::Files(pins));
+            }
+
             if root.is_member {
                 local_filesets.push(fsc.len());
             }
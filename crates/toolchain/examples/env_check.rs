@@ -0,0 +1,108 @@
+//! Runs toolchain discovery in simulated environments, to check discovery
+//! changes without setting up broken machines. Run through `cargo xtask
+//! env-check`, an example rather than a part of xtask to keep the
+//! dependencies of xtask down.
+
+use std::{path::PathBuf, process};
+
+use toolchain::{locate_in, Env, ErrorKind};
+
+const TOOLS: &[&str] = &["cargo", "rustc", "rustup", "rustfmt"];
+
+struct Scenario {
+    name: &'static str,
+    env: Env,
+    /// What discovery of `cargo` must result in.
+    cargo: Expect,
+}
+
+enum Expect {
+    Found,
+    Error(ErrorKind),
+    /// Depends on the machine, only printed.
+    Anything,
+}
+
+fn main() {
+    let mut failures = Vec::new();
+    for scenario in scenarios() {
+        println!("{}:", scenario.name);
+        for &tool in TOOLS {
+            let res = locate_in(&scenario.env, tool);
+            match &res {
+                Ok(found) => {
+                    println!("  {:<8} {} ({:?})", tool, found.path.display(), found.provenance)
+                }
+                Err(err) => println!("  {:<8} {}", tool, err),
+            }
+            if tool != "cargo" {
+                continue;
+            }
+            let ok = match (&scenario.cargo, &res) {
+                (Expect::Found, Ok(_)) | (Expect::Anything, _) => true,
+                (Expect::Error(kind), Err(err)) => err.kind() == *kind,
+                _ => false,
+            };
+            if !ok {
+                failures.push(scenario.name);
+            }
+        }
+    }
+
+    println!("rustup health:");
+    let diagnoses = toolchain::diagnose_rustup(&Env::current());
+    if diagnoses.is_empty() {
+        println!("  ok");
+    }
+    for diagnosis in diagnoses {
+        println!("  {}", diagnosis);
+    }
+
+    if !failures.is_empty() {
+        eprintln!("unexpected discovery results in: {}", failures.join(", "));
+        process::exit(1);
+    }
+}
+
+fn scenarios() -> Vec<Scenario> {
+    let current = Env::current();
+    let ci_like = {
+        let mut env = Env::default();
+        for &var in &["PATH", "HOME", "USERPROFILE", "RUSTUP_HOME", "CARGO_HOME"] {
+            if let Some(value) = current.var_os(var) {
+                env.set_var(var, value.to_os_string());
+            }
+        }
+        env
+    };
+    let empty_path = {
+        let mut env = ci_like.clone();
+        env.set_var("PATH", "".into());
+        env
+    };
+    let bogus_cargo = {
+        let mut env = ci_like.clone();
+        env.set_var("CARGO", PathBuf::from("/nonexistent/bin/cargo").into());
+        env
+    };
+    let missing_home = {
+        let mut env = Env::default();
+        env.set_var("PATH", "".into());
+        env
+    };
+    vec![
+        Scenario { name: "current", env: current, cargo: Expect::Found },
+        Scenario { name: "CI-like", env: ci_like, cargo: Expect::Found },
+        Scenario { name: "empty PATH", env: empty_path, cargo: Expect::Anything },
+        Scenario {
+            name: "bogus CARGO",
+            env: bogus_cargo,
+            cargo: Expect::Error(ErrorKind::EnvVarNotExecutable),
+        },
+        Scenario {
+            name: "missing HOME",
+            env: missing_home,
+            cargo: Expect::Error(ErrorKind::NotFound),
+        },
+    ]
+}
@@ -0,0 +1,65 @@
+//! Windows "app execution aliases": the zero-byte stubs in
+//! `%LOCALAPPDATA%\Microsoft\WindowsApps` which open the Microsoft Store
+//! instead of running anything, until the app is installed.
+
+use std::{fs, path::Path};
+
+/// `FILE_ATTRIBUTE_REPARSE_POINT`.
+const REPARSE_POINT: u32 = 0x400;
+
+/// Whether `path` is an app execution alias stub. Such a file exists, so
+/// it would be picked up by a `PATH` search, but running it doesn't run
+/// the tool.
+pub(crate) fn is_app_execution_alias(path: &Path) -> bool {
+    if !cfg!(windows) {
+        return false;
+    }
+    match fs::symlink_metadata(path) {
+        Ok(metadata) => {
+            is_alias_stub(metadata.file_type().is_symlink(), attributes(&metadata), metadata.len())
+        }
+        Err(_) => false,
+    }
+}
+
+/// Symlinks are reparse points too, but real ones. The aliases are reparse
+/// points of their own kind, which report no data.
+fn is_alias_stub(is_symlink: bool, attributes: u32, len: u64) -> bool {
+    !is_symlink && attributes & REPARSE_POINT != 0 && len == 0
+}
+
+/// The explanation for finding an alias in place of `tool`.
+pub(crate) fn explain(tool: &str, path: &Path) -> String {
+    format!(
+        "`{}` is a Windows app execution alias, which opens the Microsoft Store \
+         instead of running {}. Install {} or turn the alias off in \
+         \"Manage app execution aliases\"",
+        path.display(),
+        tool,
+        tool
+    )
+}
+
+#[cfg(windows)]
+fn attributes(metadata: &fs::Metadata) -> u32 {
+    use std::os::windows::fs::MetadataExt;
+    metadata.file_attributes()
+}
+
+#[cfg(not(windows))]
+fn attributes(_metadata: &fs::Metadata) -> u32 {
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_empty_non_symlink_reparse_points_are_stubs() {
+        assert!(is_alias_stub(false, REPARSE_POINT | 0x20, 0));
+        assert!(!is_alias_stub(true, REPARSE_POINT, 0));
+        assert!(!is_alias_stub(false, REPARSE_POINT, 4096));
+        assert!(!is_alias_stub(false, 0x20, 0));
+    }
+}
@@ -0,0 +1,145 @@
+//! The target cargo builds for when `--target` isn't passed: `build.target`.
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    cargo_config::{config_files, config_value, unquote},
+    sysroot, DiscoveryError, ErrorKind,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuildTarget {
+    /// A target triple, or the path of a target specification file.
+    pub triple: String,
+    pub source: TargetSource,
+}
+
+/// Where a [`BuildTarget`] came from, in order of precedence.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TargetSource {
+    /// rust-analyzer's own `cargo.target` setting.
+    Settings,
+    /// `$CARGO_BUILD_TARGET`.
+    EnvVar,
+    /// A cargo configuration file.
+    CargoConfig(PathBuf),
+}
+
+/// Resolves the build target for a workspace in `dir` the way cargo does,
+/// except that the `settings` of rust-analyzer take precedence. `None` means
+/// the host target.
+pub fn build_target(dir: &Path, settings: Option<&str>) -> Option<BuildTarget> {
+    if let Some(triple) = settings {
+        return Some(BuildTarget { triple: triple.to_string(), source: TargetSource::Settings });
+    }
+    if let Ok(triple) = env::var("CARGO_BUILD_TARGET") {
+        return Some(BuildTarget { triple, source: TargetSource::EnvVar });
+    }
+    config_files(dir).into_iter().find_map(|config| {
+        let text = fs::read_to_string(&config).ok()?;
+        let mut triple = parse_build_target(&text)?;
+        if triple.ends_with(".json") && Path::new(&triple).is_relative() {
+            // Relative to the directory containing `.cargo`.
+            let base = config.parent()?.parent()?;
+            triple = base.join(triple).to_string_lossy().into_owned();
+        }
+        Some(BuildTarget { triple, source: TargetSource::CargoConfig(config) })
+    })
+}
+
+impl BuildTarget {
+    /// Whether this is a custom target, described by a JSON file rather than
+    /// built into rustc.
+    pub fn is_spec_file(&self) -> bool {
+        self.triple.ends_with(".json")
+    }
+
+    /// Checks that the standard library for the target is installed for
+    /// `rustc`. Custom targets build their own, so they always pass.
+    pub fn check_installed(&self, rustc: &Path) -> Result<(), DiscoveryError> {
+        if self.is_spec_file() {
+            return Ok(());
+        }
+        let error = |kind| DiscoveryError::new(kind, "rustc").with_path(rustc.to_path_buf());
+        let sysroot = sysroot::sysroot(rustc)
+            .map_err(|err| error(ErrorKind::SpawnFailed).with_detail(err.to_string()))?;
+        if !sysroot::has_target_libs(&sysroot, &self.triple) {
+            return Err(error(ErrorKind::TargetNotInstalled).with_detail(self.triple.clone()));
+        }
+        Ok(())
+    }
+}
+
+/// Finds `build.target` in a cargo configuration file, skipping comments.
+/// For multiple targets, the first one wins.
+fn parse_build_target(text: &str) -> Option<String> {
+    let value = config_value(text, "build.target")?.trim_start_matches('[');
+    let first = value.split(',').next()?.trim_end_matches(']');
+    let triple = unquote(first);
+    if triple.is_empty() {
+        return None;
+    }
+    Some(triple.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testdir::TestDir;
+
+    use super::*;
+
+    #[test]
+    fn parses_target_spellings() {
+        let check = |text: &str| parse_build_target(text);
+        assert_eq!(
+            check("[build]\ntarget = \"wasm32-unknown-unknown\"\n").unwrap(),
+            "wasm32-unknown-unknown"
+        );
+        assert_eq!(check("build.target = 'thumbv7em-none-eabi'").unwrap(), "thumbv7em-none-eabi");
+        assert_eq!(
+            check(
+                "[build]\njobs = 4\ntarget = [\"aarch64-apple-darwin\", \"x86_64-apple-darwin\"]"
+            )
+            .unwrap(),
+            "aarch64-apple-darwin"
+        );
+        assert_eq!(check("[target.x86_64-unknown-linux-gnu]\ntarget = \"nope\""), None);
+        assert_eq!(check("[build]\nrustflags = []"), None);
+        assert_eq!(
+            check("[build]\n# target = \"wasm32-wasi\"\ntarget = [\"riscv32imc-unknown-none-elf\"] # esp\n")
+                .unwrap(),
+            "riscv32imc-unknown-none-elf"
+        );
+    }
+
+    #[test]
+    fn nearest_config_wins() {
+        let tmp = TestDir::new("build-target");
+        let root = tmp.path().to_path_buf();
+        let nested = root.join("member");
+        fs::create_dir_all(root.join(".cargo")).unwrap();
+        fs::create_dir_all(nested.join(".cargo")).unwrap();
+        fs::write(root.join(".cargo/config.toml"), "[build]\ntarget = \"custom.json\"\n").unwrap();
+        fs::write(
+            nested.join(".cargo/config"),
+            "[build]\ntarget = \"riscv32imac-unknown-none-elf\"\n",
+        )
+        .unwrap();
+
+        let target = build_target(&nested, None).unwrap();
+        if target.source != TargetSource::EnvVar {
+            assert_eq!(target.triple, "riscv32imac-unknown-none-elf");
+            assert_eq!(target.source, TargetSource::CargoConfig(nested.join(".cargo/config")));
+
+            let target = build_target(&root, None).unwrap();
+            assert_eq!(Path::new(&target.triple), root.join("custom.json"));
+            assert!(target.is_spec_file());
+        }
+
+        let target = build_target(&nested, Some("x86_64-pc-windows-msvc")).unwrap();
+        assert_eq!(target.source, TargetSource::Settings);
+    }
+}
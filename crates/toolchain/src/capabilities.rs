@@ -0,0 +1,259 @@
+//! Feature detection for flags of the discovered `cargo` and `rustc`.
+//!
+//! Most capabilities are decided by the tool's version alone. When the
+//! version is unknown, some can still be probed for, by looking for the flag
+//! in the output of `--help`. Either way, the answer is computed once and
+//! cached.
+//!
+//! Code which depends on a capability should ask [`ensure_supports`] rather
+//! than compare versions itself.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use once_cell::sync::Lazy;
+use rustc_hash::FxHashMap;
+
+use crate::{utf8_stdout, DiscoveryError, ErrorKind, Fingerprint, Version};
+
+/// A flag or behavior which only some toolchains have.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Capability {
+    /// `cargo check --keep-going`.
+    KeepGoing,
+    /// `cargo check --message-format=json-diagnostic-rendered-ansi`.
+    JsonDiagnosticRenderedAnsi,
+    /// `cargo check --all-targets`.
+    CheckAllTargets,
+    /// `rustc --check-cfg`.
+    CheckCfg,
+    /// `cargo build --timings`.
+    Timings,
+    /// A `rendered` field in the diagnostics of `rustc --error-format=json`.
+    RenderedJson,
+}
+
+#[derive(Debug)]
+pub struct Capabilities {
+    cargo: PathBuf,
+    rustc: PathBuf,
+    cargo_version: Option<Version>,
+    rustc_version: Option<Version>,
+    answers: Mutex<FxHashMap<Capability, bool>>,
+}
+
+struct Requirement {
+    tool: Tool,
+    min_version: (u32, u32, u32),
+    /// Arguments to get a help text, and what to look for in it.
+    probe: Option<(&'static [&'static str], &'static str)>,
+}
+
+#[derive(Clone, Copy)]
+enum Tool {
+    Cargo,
+    Rustc,
+}
+
+impl Capabilities {
+    /// Asks `cargo` and `rustc` for their versions, unless they were asked
+    /// already; further spawns happen only if some capability needs
+    /// probing.
+    pub fn detect(cargo: PathBuf, rustc: PathBuf) -> Capabilities {
+        let cargo_version = cargo_version(&cargo);
+        let rustc_version = crate::rustc_meta(&rustc).ok().map(|it| it.version);
+        Capabilities { cargo, rustc, cargo_version, rustc_version, answers: Default::default() }
+    }
+
+    pub fn cargo_version(&self) -> Option<&Version> {
+        self.cargo_version.as_ref()
+    }
+
+    pub fn rustc_version(&self) -> Option<&Version> {
+        self.rustc_version.as_ref()
+    }
+
+    /// Like [`Capabilities::supports`], but explains what is missing.
+    pub fn ensure_supports(&self, capability: Capability) -> Result<(), DiscoveryError> {
+        if self.supports(capability) {
+            return Ok(());
+        }
+        let requirement = capability.requirement();
+        let (tool, path) = match requirement.tool {
+            Tool::Cargo => ("cargo", &self.cargo),
+            Tool::Rustc => ("rustc", &self.rustc),
+        };
+        let (major, minor, patch) = requirement.min_version;
+        Err(DiscoveryError::new(ErrorKind::Unsupported, tool).with_path(path.clone()).with_detail(
+            format!("`{}`, which needs {} {}.{}.{}", capability.name(), tool, major, minor, patch),
+        ))
+    }
+
+    pub fn supports(&self, capability: Capability) -> bool {
+        if let Some(&answer) = self.answers.lock().unwrap().get(&capability) {
+            return answer;
+        }
+        let answer = self.compute(capability.requirement());
+        self.answers.lock().unwrap().insert(capability, answer);
+        answer
+    }
+
+    fn compute(&self, requirement: Requirement) -> bool {
+        let (path, version) = match requirement.tool {
+            Tool::Cargo => (&self.cargo, &self.cargo_version),
+            Tool::Rustc => (&self.rustc, &self.rustc_version),
+        };
+        let (major, minor, patch) = requirement.min_version;
+        match (version, requirement.probe) {
+            (Some(version), _) => version.at_least(major, minor, patch),
+            (None, Some((args, needle))) => help_mentions(path, args, needle),
+            (None, None) => false,
+        }
+    }
+}
+
+/// Checks whether the discovered `cargo` and `rustc` have `capability`.
+///
+/// The answers are shared by all callers, and recomputed once the discovered
+/// tools change, see [`crate::invalidate_tools`], or their [`Fingerprint`]s
+/// do, like when a rustup proxy switches toolchains.
+pub fn ensure_supports(capability: Capability) -> Result<(), DiscoveryError> {
+    static SHARED: Lazy<Mutex<Option<Detected>>> = Lazy::new(Default::default);
+
+    let (cargo, rustc) = (crate::cargo(), crate::rustc());
+    let fingerprints = (Fingerprint::of(&cargo).ok(), Fingerprint::of(&rustc).ok());
+    let capabilities = {
+        let mut shared = SHARED.lock().unwrap();
+        match &*shared {
+            Some((key, it)) if *key == fingerprints && it.cargo == cargo && it.rustc == rustc => {
+                Arc::clone(it)
+            }
+            _ => {
+                let res = Arc::new(Capabilities::detect(cargo, rustc));
+                *shared = Some((fingerprints, Arc::clone(&res)));
+                res
+            }
+        }
+    };
+    capabilities.ensure_supports(capability)
+}
+
+impl Capability {
+    pub const ALL: &'static [Capability] = &[
+        Capability::KeepGoing,
+        Capability::JsonDiagnosticRenderedAnsi,
+        Capability::CheckAllTargets,
+        Capability::CheckCfg,
+        Capability::Timings,
+        Capability::RenderedJson,
+    ];
+
+    /// How the capability is spelled in logs and errors, like
+    /// `cargo check --keep-going`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Capability::KeepGoing => "cargo check --keep-going",
+            Capability::JsonDiagnosticRenderedAnsi => {
+                "cargo check --message-format=json-diagnostic-rendered-ansi"
+            }
+            Capability::CheckAllTargets => "cargo check --all-targets",
+            Capability::CheckCfg => "rustc --check-cfg",
+            Capability::Timings => "cargo --timings",
+            Capability::RenderedJson => "rustc --error-format=json includes rendered",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Capability> {
+        Capability::ALL.iter().copied().find(|it| it.name() == name)
+    }
+
+    fn requirement(self) -> Requirement {
+        let (tool, min_version, probe) = match self {
+            Capability::KeepGoing => {
+                (Tool::Cargo, (1, 74, 0), Some((&["check", "--help"][..], "--keep-going")))
+            }
+            Capability::JsonDiagnosticRenderedAnsi => (Tool::Cargo, (1, 40, 0), None),
+            Capability::CheckAllTargets => {
+                (Tool::Cargo, (1, 22, 0), Some((&["check", "--help"][..], "--all-targets")))
+            }
+            Capability::CheckCfg => (Tool::Rustc, (1, 80, 0), None),
+            Capability::Timings => {
+                (Tool::Cargo, (1, 60, 0), Some((&["build", "--help"][..], "--timings")))
+            }
+            Capability::RenderedJson => (Tool::Rustc, (1, 24, 0), None),
+        };
+        Requirement { tool, min_version, probe }
+    }
+}
+
+/// The capabilities of `cargo` and `rustc`, with their fingerprints.
+type Detected = ((Option<Fingerprint>, Option<Fingerprint>), Arc<Capabilities>);
+
+/// Runs `cargo --version`. Like for [`crate::rustc_meta`], the result is
+/// cached per [`Fingerprint`].
+fn cargo_version(cargo: &Path) -> Option<Version> {
+    static CACHE: Lazy<Mutex<FxHashMap<Fingerprint, Version>>> = Lazy::new(Default::default);
+
+    let fingerprint = Fingerprint::of(cargo).ok();
+    if let Some(fingerprint) = &fingerprint {
+        if let Some(version) = CACHE.lock().unwrap().get(fingerprint) {
+            return Some(version.clone());
+        }
+    }
+
+    let mut cmd = crate::interpreter::command(cargo);
+    cmd.arg("--version");
+    let version = Version::parse_output(&utf8_stdout(cmd).ok()?)?;
+
+    if let Some(fingerprint) = fingerprint {
+        CACHE.lock().unwrap().insert(fingerprint, version.clone());
+    }
+    Some(version)
+}
+
+fn help_mentions(path: &Path, args: &[&str], needle: &str) -> bool {
+    let mut cmd = crate::interpreter::command(path);
+    cmd.args(args);
+    match utf8_stdout(cmd) {
+        Ok(help) => help.contains(needle),
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn answers_are_cached() {
+        let capabilities = Capabilities::detect(crate::cargo(), crate::rustc());
+        assert!(capabilities.cargo_version().is_some());
+        let first = capabilities.supports(Capability::KeepGoing);
+        assert_eq!(capabilities.answers.lock().unwrap().get(&Capability::KeepGoing), Some(&first));
+        assert_eq!(capabilities.supports(Capability::KeepGoing), first);
+    }
+
+    #[test]
+    fn capabilities_have_names() {
+        for &capability in Capability::ALL {
+            assert_eq!(Capability::from_name(capability.name()), Some(capability));
+        }
+        assert_eq!(Capability::from_name("cargo --frobnicate"), None);
+    }
+
+    #[test]
+    fn missing_capability_is_explained() {
+        let capabilities = Capabilities {
+            cargo: "cargo".into(),
+            rustc: "rustc".into(),
+            cargo_version: Version::parse("1.20.0"),
+            rustc_version: Version::parse("1.20.0"),
+            answers: Default::default(),
+        };
+        let err = capabilities.ensure_supports(Capability::CheckCfg).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Unsupported);
+        assert_eq!(err.detail(), Some("`rustc --check-cfg`, which needs rustc 1.80.0"));
+    }
+}
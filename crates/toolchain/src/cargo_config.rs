@@ -0,0 +1,97 @@
+//! Just enough of cargo's configuration files for discovery: where they are,
+//! and the values of simple keys.
+
+use std::path::{Path, PathBuf};
+
+use stdx::split_once;
+
+/// The configuration files cargo reads for `dir`, most specific first.
+pub(crate) fn config_files(dir: &Path) -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = dir.ancestors().map(|it| it.join(".cargo")).collect();
+    dirs.extend(home::cargo_home().ok());
+    dirs.dedup();
+    let mut res = Vec::new();
+    for dir in dirs {
+        // `config` is the legacy name; cargo prefers `config.toml` if both exist.
+        let file = ["config.toml", "config"].iter().map(|it| dir.join(it)).find(|it| it.is_file());
+        res.extend(file);
+    }
+    res
+}
+
+/// Finds the raw value of the dotted `key`, like `build.target`, in the text
+/// of a configuration file.
+///
+/// This is a line-based scan rather than a TOML parser, which is enough for
+/// the usual spellings: `target = ...` in a `[build]` table, or
+/// `build.target = ...` outside of any table. Quotes around table names and
+/// keys are ignored, and so are comments.
+pub(crate) fn config_value<'a>(text: &'a str, key: &str) -> Option<&'a str> {
+    let mut table = String::new();
+    for line in text.lines() {
+        let line = strip_comment(line).trim();
+        if line.starts_with('[') {
+            table = unquote_key(line.trim_matches(|c| c == '[' || c == ']'));
+            continue;
+        }
+        let (k, value) = match split_once(line, '=') {
+            Some(it) => it,
+            None => continue,
+        };
+        let k = unquote_key(k);
+        let full_key = if table.is_empty() { k } else { format!("{}.{}", table, k) };
+        if full_key == key {
+            return Some(value.trim());
+        }
+    }
+    None
+}
+
+/// `line` without its trailing `# comment`, if any. A `#` in a string
+/// doesn't start a comment.
+pub(crate) fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    for (idx, c) in line.char_indices() {
+        match (quote, c) {
+            (None, '#') => return &line[..idx],
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (Some(q), _) if q == c => quote = None,
+            _ => (),
+        }
+    }
+    line
+}
+
+/// The contents of a TOML string, or the value itself if it's unquoted.
+pub(crate) fn unquote(value: &str) -> &str {
+    value.trim().trim_matches(|c| c == '"' || c == '\'')
+}
+
+fn unquote_key(key: &str) -> String {
+    key.split('.').map(unquote).collect::<Vec<_>>().join(".")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_keys_in_tables_and_dotted() {
+        let text = "[source.\"crates-io\"]\nreplace-with = \"vendored-sources\"\n\n\
+                    [source.vendored-sources]\ndirectory = 'vendor'\n";
+        assert_eq!(
+            config_value(text, "source.crates-io.replace-with"),
+            Some("\"vendored-sources\"")
+        );
+        assert_eq!(
+            config_value(text, "source.vendored-sources.directory").map(unquote),
+            Some("vendor")
+        );
+        assert_eq!(config_value("net.offline = true", "net.offline"), Some("true"));
+        assert_eq!(config_value("[net]\nretry = 2", "net.offline"), None);
+        let commented = "[build] # the defaults\n# target = \"wasm32-unknown-unknown\"\n\
+                         target = \"x86_64-unknown-linux-gnu\" # the host\n";
+        assert_eq!(config_value(commented, "build.target"), Some("\"x86_64-unknown-linux-gnu\""));
+        assert_eq!(config_value("[env]\nCOLOR = \"#fff\"", "env.COLOR"), Some("\"#fff\""));
+    }
+}
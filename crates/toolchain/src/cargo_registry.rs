@@ -0,0 +1,135 @@
+//! Whether cargo can fetch crates for a workspace: online, offline by
+//! configuration, or using vendored sources.
+
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    cargo_config::{config_files, config_value, unquote},
+    install::network_failure,
+    DiscoveryError, Env, ErrorKind, ToolCommand,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RegistryState {
+    /// crates.io answers.
+    Online,
+    /// Cargo is told not to use the network, by `CARGO_NET_OFFLINE` or
+    /// `net.offline` in a configuration file.
+    Offline,
+    /// crates.io is replaced by a local directory, like the one made by
+    /// `cargo vendor`.
+    Vendored { dir: PathBuf },
+    /// Cargo would use the network, but can't reach crates.io.
+    Unreachable(DiscoveryError),
+}
+
+impl RegistryState {
+    /// Whether features which make cargo download crates can be expected to
+    /// work.
+    pub fn allows_fetching(&self) -> bool {
+        matches!(self, RegistryState::Online)
+    }
+}
+
+impl fmt::Display for RegistryState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegistryState::Online => write!(f, "online"),
+            RegistryState::Offline => write!(f, "offline"),
+            RegistryState::Vendored { dir } => write!(f, "vendored ({})", dir.display()),
+            RegistryState::Unreachable(err) => write!(f, "unreachable: {}", err),
+        }
+    }
+}
+
+/// Classifies the registry access of a workspace in `dir`.
+///
+/// The configuration decides, if it says cargo is offline or vendored.
+/// Otherwise this asks crates.io for a search result, which goes through the
+/// network and takes a moment.
+pub fn registry_state(dir: &Path) -> RegistryState {
+    let env = Env::current();
+    if let Some(state) = configured_state(&env, dir) {
+        return state;
+    }
+    let mut cmd = ToolCommand::new(crate::cargo());
+    cmd.args(["search", "--limit", "1", "--color", "never", "serde"]).current_dir(dir);
+    let error = |kind| DiscoveryError::new(kind, "cargo").with_path(cmd.program().to_path_buf());
+    crate::registry::count_spawn();
+    let output = match cmd.output() {
+        Ok(it) => it,
+        Err(err) => {
+            return RegistryState::Unreachable(
+                error(ErrorKind::SpawnFailed).with_detail(err.to_string()),
+            )
+        }
+    };
+    if output.status.success() {
+        return RegistryState::Online;
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+    let kind = network_failure(&stderr).unwrap_or(ErrorKind::ServerUnreachable);
+    RegistryState::Unreachable(error(kind).with_detail(stderr))
+}
+
+fn configured_state(env: &Env, dir: &Path) -> Option<RegistryState> {
+    if env.var("CARGO_NET_OFFLINE") == Some("true") {
+        return Some(RegistryState::Offline);
+    }
+    let configs: Vec<(PathBuf, String)> = config_files(dir)
+        .into_iter()
+        .filter_map(|it| Some((it.clone(), fs::read_to_string(it).ok()?)))
+        .collect();
+    // Like cargo, the most specific file which sets a key wins.
+    let lookup = |key: &str| {
+        configs.iter().find_map(|(file, text)| Some((file, unquote(config_value(text, key)?))))
+    };
+
+    if let Some((_, "true")) = lookup("net.offline") {
+        return Some(RegistryState::Offline);
+    }
+    let (_, replacement) = lookup("source.crates-io.replace-with")?;
+    let (file, dir) = lookup(&format!("source.{}.directory", replacement))?;
+    // Relative to the directory containing `.cargo`.
+    let base = file.parent()?.parent()?;
+    Some(RegistryState::Vendored { dir: base.join(dir) })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use crate::testdir::TestDir;
+
+    use super::*;
+
+    #[test]
+    fn vendored_and_offline_configurations() {
+        let tmp = TestDir::new("registry");
+        let root = tmp.path().to_path_buf();
+        let member = root.join("member");
+        fs::create_dir_all(root.join(".cargo")).unwrap();
+        fs::create_dir_all(member.join(".cargo")).unwrap();
+        fs::write(
+            root.join(".cargo/config.toml"),
+            "[source.crates-io]\nreplace-with = \"vendored-sources\"\n\n\
+             [source.vendored-sources]\ndirectory = \"vendor\"\n",
+        )
+        .unwrap();
+        fs::write(member.join(".cargo/config.toml"), "[net]\noffline = true\n").unwrap();
+
+        let env = Env::default();
+        assert_eq!(
+            configured_state(&env, &root),
+            Some(RegistryState::Vendored { dir: root.join("vendor") })
+        );
+        assert_eq!(configured_state(&env, &member), Some(RegistryState::Offline));
+
+        let mut env = Env::default();
+        env.set_var("CARGO_NET_OFFLINE", "true".into());
+        assert_eq!(configured_state(&env, &env::temp_dir()), Some(RegistryState::Offline));
+    }
+}
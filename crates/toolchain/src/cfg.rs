@@ -0,0 +1,80 @@
+//! Runs `rustc --print cfg` to get the built-in cfg atoms of a target.
+
+use std::{io, path::Path, sync::Mutex};
+
+use once_cell::sync::Lazy;
+use rustc_hash::FxHashMap;
+use stdx::split_once;
+
+use crate::{utf8_stdout, Fingerprint};
+
+/// A single line of `rustc --print cfg` output, like `unix` or
+/// `target_os="linux"`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum CfgAtom {
+    Flag(String),
+    KeyValue { key: String, value: String },
+}
+
+impl CfgAtom {
+    fn parse(line: &str) -> Option<CfgAtom> {
+        let line = line.trim();
+        if line.is_empty() {
+            return None;
+        }
+        let res = match split_once(line, '=') {
+            Some((key, value)) => {
+                let value = value.strip_prefix('"')?.strip_suffix('"')?;
+                CfgAtom::KeyValue { key: key.to_string(), value: value.to_string() }
+            }
+            None => CfgAtom::Flag(line.to_string()),
+        };
+        Some(res)
+    }
+}
+
+/// Returns the cfgs `rustc` enables for `target` (the host, if `None`).
+///
+/// The cfgs are queried as for an optimized build, so `debug_assertions` is
+/// never included. Successful answers are cached per [`Fingerprint`] of
+/// `rustc`.
+pub fn rustc_cfgs(rustc: &Path, target: Option<&str>) -> io::Result<Vec<CfgAtom>> {
+    type Cache = FxHashMap<(Fingerprint, Option<String>), Vec<CfgAtom>>;
+    static CACHE: Lazy<Mutex<Cache>> = Lazy::new(Default::default);
+
+    let key = Fingerprint::of(rustc).ok().map(|it| (it, target.map(String::from)));
+    if let Some(key) = &key {
+        if let Some(cfgs) = CACHE.lock().unwrap().get(key) {
+            return Ok(cfgs.clone());
+        }
+    }
+
+    let mut cmd = crate::interpreter::command(rustc);
+    cmd.args(["--print", "cfg", "-O"]);
+    if let Some(target) = target {
+        cmd.args(["--target", target]);
+    }
+    let stdout = utf8_stdout(cmd)?;
+    let cfgs: Vec<CfgAtom> = stdout.lines().filter_map(CfgAtom::parse).collect();
+
+    if let Some(key) = key {
+        CACHE.lock().unwrap().insert(key, cfgs.clone());
+    }
+    Ok(cfgs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_cfg_lines() {
+        assert_eq!(CfgAtom::parse("unix"), Some(CfgAtom::Flag("unix".to_string())));
+        assert_eq!(
+            CfgAtom::parse("target_os=\"linux\"\r"),
+            Some(CfgAtom::KeyValue { key: "target_os".to_string(), value: "linux".to_string() })
+        );
+        assert_eq!(CfgAtom::parse("target_os=linux"), None);
+        assert_eq!(CfgAtom::parse(""), None);
+    }
+}
@@ -0,0 +1,590 @@
+//! Construction of commands which run a discovered tool.
+
+use std::{
+    env,
+    ffi::{OsStr, OsString},
+    fmt, io,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use stdx::split_once;
+
+use crate::{
+    interpreter,
+    prefix::command_prefix,
+    rustup_proxy::{self, ResolvedProxy},
+    CommandPrefix, Env, RustcWrappers, ToolchainSpec,
+};
+
+/// A builder for a [`Command`] running a Rust tool.
+///
+/// Unlike `Command`, it keeps the program, arguments and environment changes
+/// inspectable until [`ToolCommand::to_command`], and it keeps an audit trail
+/// of the environment variables changed by policy rather than explicitly.
+#[derive(Debug, Clone)]
+pub struct ToolCommand {
+    program: PathBuf,
+    args: Vec<OsString>,
+    /// `None` removes the variable.
+    envs: Vec<(OsString, Option<OsString>)>,
+    current_dir: Option<PathBuf>,
+    audit: Vec<EnvChange>,
+}
+
+/// An environment variable changed by a [`ToolCommand`] policy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnvChange {
+    pub var: String,
+    /// The value the process environment had, if any.
+    pub inherited: Option<OsString>,
+    /// The value the command will see, `None` if removed.
+    pub value: Option<OsString>,
+    pub reason: String,
+}
+
+/// What to do with `RUSTC_BOOTSTRAP`, which makes a stable toolchain accept
+/// unstable flags.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RustcBootstrap {
+    /// Keep whatever the environment says.
+    Inherit,
+    /// Set `RUSTC_BOOTSTRAP=1`.
+    Allow,
+    /// Remove `RUSTC_BOOTSTRAP`, even if the environment sets it.
+    Forbid,
+}
+
+/// Environment adjustments for a kind of invocation, undoing environment
+/// variables which are known to break it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Profile {
+    /// `cargo metadata`, which doesn't need to compile anything: wrappers like
+    /// `sccache` only slow it down or make it fail. Both layers of
+    /// [`RustcWrappers`] are disabled, including ones set in cargo
+    /// configuration files.
+    Metadata,
+    /// `cargo check` and friends. Removes `CARGO_ENCODED_RUSTFLAGS` leaked
+    /// from a parent cargo, which would override `RUSTFLAGS`, and repeats in
+    /// `RUSTFLAGS` which change nothing, like a `-C target-cpu=native` added
+    /// again by a shell profile which was sourced twice.
+    ///
+    /// Only the last of the repeated flags is kept, and only for flags where
+    /// the last one wins anyway. Flags which add up, like `-C link-arg`, `-l`
+    /// or `-L`, are kept as they are.
+    Check,
+    /// Builds of proc-macros and build scripts, which must not pick up GTK or
+    /// Qt plugins meant for the editor process.
+    ProcMacro,
+}
+
+const GUI_VARS: &[&str] = &[
+    "GTK_PATH",
+    "GTK_MODULES",
+    "GTK_EXE_PREFIX",
+    "GTK_IM_MODULE_FILE",
+    "GDK_PIXBUF_MODULE_FILE",
+    "GIO_MODULE_DIR",
+    "QT_PLUGIN_PATH",
+    "QT_QPA_PLATFORM_PLUGIN_PATH",
+    "QML2_IMPORT_PATH",
+];
+
+static AUGMENT_PATH: AtomicBool = AtomicBool::new(false);
+
+/// Makes the commands built by [`ToolCommand`] find the discovered tools
+/// first in `PATH`, so that build scripts and proc-macros which run `cargo`
+/// or `rustc` themselves pick the same toolchain. Off by default.
+///
+/// Returns whether the setting changed.
+pub fn set_augment_path(enabled: bool) -> bool {
+    let changed = AUGMENT_PATH.swap(enabled, Ordering::Relaxed) != enabled;
+    if changed {
+        crate::invalidate_tools();
+    }
+    changed
+}
+
+impl ToolCommand {
+    pub fn new(program: PathBuf) -> ToolCommand {
+        ToolCommand {
+            program,
+            args: Vec::new(),
+            envs: Vec::new(),
+            current_dir: None,
+            audit: Vec::new(),
+        }
+    }
+
+    /// A command with `profile` applied, see [`ToolCommand::sanitize`].
+    pub fn with_profile(program: PathBuf, profile: Profile) -> ToolCommand {
+        let mut res = ToolCommand::new(program);
+        res.sanitize(profile);
+        res
+    }
+
+    /// The discovered `cargo`, with `profile` applied. All cargo invocations
+    /// should start here, so that they agree on which cargo runs, and in
+    /// which environment.
+    pub fn cargo(profile: Profile) -> ToolCommand {
+        ToolCommand::with_profile(crate::cargo(), profile)
+    }
+
+    /// A command running `tool` (like `rustfmt`) from `toolchain` (like
+    /// `nightly`) through `rustup run`, which takes precedence over
+    /// `rust-toolchain` files and directory overrides.
+    ///
+    /// Arguments added later are passed to `tool`.
+    pub fn rustup_run(toolchain: &ToolchainSpec, tool: &str) -> ToolCommand {
+        let mut res = ToolCommand::new(crate::rustup());
+        res.args(["run", &toolchain.to_string(), tool]);
+        res
+    }
+
+    /// A command running `rustc` the way cargo does, through the chain of
+    /// `wrappers`.
+    ///
+    /// Arguments added later are passed to `rustc`.
+    pub fn rustc(
+        rustc: PathBuf,
+        wrappers: &RustcWrappers,
+        is_workspace_member: bool,
+    ) -> ToolCommand {
+        let mut chain = wrappers.chain(is_workspace_member).into_iter();
+        match chain.next() {
+            Some(outermost) => {
+                let mut res = ToolCommand::new(outermost.to_path_buf());
+                res.args(chain).arg(rustc);
+                res
+            }
+            None => ToolCommand::new(rustc),
+        }
+    }
+
+    pub fn arg(&mut self, arg: impl AsRef<OsStr>) -> &mut ToolCommand {
+        self.args.push(arg.as_ref().to_os_string());
+        self
+    }
+
+    pub fn args<I, S>(&mut self, args: I) -> &mut ToolCommand
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        self.args.extend(args.into_iter().map(|it| it.as_ref().to_os_string()));
+        self
+    }
+
+    pub fn env(&mut self, key: impl AsRef<OsStr>, value: impl AsRef<OsStr>) -> &mut ToolCommand {
+        self.set_env(key.as_ref(), Some(value.as_ref().to_os_string()));
+        self
+    }
+
+    pub fn env_remove(&mut self, key: impl AsRef<OsStr>) -> &mut ToolCommand {
+        self.set_env(key.as_ref(), None);
+        self
+    }
+
+    pub fn current_dir(&mut self, dir: impl AsRef<Path>) -> &mut ToolCommand {
+        self.current_dir = Some(dir.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn rustc_bootstrap(&mut self, policy: RustcBootstrap) -> &mut ToolCommand {
+        let value = match policy {
+            RustcBootstrap::Inherit => return self,
+            RustcBootstrap::Allow => Some(OsString::from("1")),
+            RustcBootstrap::Forbid => None,
+        };
+        self.apply_policy("RUSTC_BOOTSTRAP", value, format!("RUSTC_BOOTSTRAP policy: {:?}", policy))
+    }
+
+    /// Makes cargo run `rustc` through exactly `wrappers`, whatever the
+    /// environment and the configuration files of the directory cargo runs
+    /// in say. A missing layer is set to an empty value, which cargo reads as
+    /// no wrapper.
+    pub fn rustc_wrappers(&mut self, wrappers: &RustcWrappers) -> &mut ToolCommand {
+        self.rustc_wrappers_in(&Env::current(), wrappers, "rustc wrappers".to_string())
+    }
+
+    fn rustc_wrappers_in(
+        &mut self,
+        env: &Env,
+        wrappers: &RustcWrappers,
+        reason: String,
+    ) -> &mut ToolCommand {
+        let layers = [
+            ("RUSTC_WRAPPER", &wrappers.wrapper),
+            ("RUSTC_WORKSPACE_WRAPPER", &wrappers.workspace_wrapper),
+        ];
+        for &(var, wrapper) in layers.iter() {
+            let value = wrapper.as_ref().map_or_else(OsString::new, |it| it.clone().into());
+            self.apply_policy_in(env, var, Some(value), reason.clone());
+        }
+        self
+    }
+
+    /// Adjusts the environment for `profile`. Only variables which are
+    /// actually set in the process environment are touched, except for the
+    /// wrappers of [`Profile::Metadata`], and every change is recorded in the
+    /// [`ToolCommand::audit`].
+    pub fn sanitize(&mut self, profile: Profile) -> &mut ToolCommand {
+        self.sanitize_in(&Env::current(), profile)
+    }
+
+    /// [`ToolCommand::sanitize`], for a command which inherits `env`.
+    pub(crate) fn sanitize_in(&mut self, env: &Env, profile: Profile) -> &mut ToolCommand {
+        let reason = format!("{:?} profile", profile);
+        if profile == Profile::Metadata {
+            self.rustc_wrappers_in(env, &RustcWrappers::default(), reason.clone());
+        }
+        let removed: &[&str] = match profile {
+            Profile::Metadata => &[],
+            Profile::Check => &["CARGO_ENCODED_RUSTFLAGS"],
+            Profile::ProcMacro => GUI_VARS,
+        };
+        for &var in removed {
+            if env.var_os(var).is_some() {
+                self.apply_policy_in(env, var, None, reason.clone());
+            }
+        }
+        if profile == Profile::Check {
+            if let Some(flags) = env.var("RUSTFLAGS") {
+                let deduped = dedup_rustflags(flags);
+                if deduped != flags {
+                    self.apply_policy_in(env, "RUSTFLAGS", Some(deduped.into()), reason);
+                }
+            }
+        }
+        self
+    }
+
+    pub fn program(&self) -> &Path {
+        &self.program
+    }
+
+    pub fn get_args(&self) -> &[OsString] {
+        &self.args
+    }
+
+    /// The environment variables changed by policies, in order of
+    /// application.
+    pub fn audit(&self) -> &[EnvChange] {
+        &self.audit
+    }
+
+    /// The command to run, with the prefix from
+    /// [`crate::set_command_prefix`] in front.
+    pub fn to_command(&self) -> Command {
+        self.to_command_with(command_prefix().as_ref())
+    }
+
+    /// Runs the command to completion in the [`crate::ExecutionEnvironment`],
+    /// like [`Command::output`].
+    pub fn output(&self) -> io::Result<Output> {
+        crate::execution::execution_environment().output(&mut self.to_command())
+    }
+
+    fn to_command_with(&self, prefix: Option<&CommandPrefix>) -> Command {
+        let mut bypassed = None;
+        let mut cmd = match prefix {
+            Some(prefix) => {
+                let mut cmd = interpreter::command(prefix.program());
+                cmd.args(prefix.args()).arg(&self.program);
+                cmd
+            }
+            None => {
+                bypassed = self.resolve_proxy();
+                match &bypassed {
+                    Some(it) => interpreter::command(&it.path),
+                    None => interpreter::command(&self.program),
+                }
+            }
+        };
+        cmd.args(&self.args);
+        for (key, value) in &self.envs {
+            match value {
+                Some(value) => cmd.env(key, value),
+                None => cmd.env_remove(key),
+            };
+        }
+        // A wrapper reached through cargo may run discovery too.
+        crate::recursion::mark(&mut cmd);
+        if AUGMENT_PATH.load(Ordering::Relaxed) {
+            let path = match self.envs.iter().find(|(key, _)| key == "PATH") {
+                Some((_, value)) => value.clone(),
+                None => inherited("PATH"),
+            };
+            // A command which removes `PATH` on purpose keeps it removed.
+            if let Some(path) = path {
+                let dirs = crate::registry::discovered_dirs();
+                if let Some(it) = augmented_path(&dirs, &path) {
+                    cmd.env("PATH", it);
+                }
+            }
+        }
+        if let Some(it) = bypassed {
+            cmd.env("RUSTUP_TOOLCHAIN", it.toolchain);
+        }
+        if let Some(dir) = &self.current_dir {
+            cmd.current_dir(dir);
+        }
+        cmd
+    }
+
+    /// See [`crate::set_bypass_rustup_proxies`].
+    fn resolve_proxy(&self) -> Option<ResolvedProxy> {
+        let dir = match &self.current_dir {
+            Some(it) => it.clone(),
+            None => env::current_dir().ok()?,
+        };
+        let rustup_toolchain = match self.envs.iter().find(|(key, _)| key == "RUSTUP_TOOLCHAIN") {
+            Some((_, value)) => value.clone(),
+            None => inherited("RUSTUP_TOOLCHAIN"),
+        };
+        rustup_proxy::resolve(&self.program, &dir, rustup_toolchain.as_deref())
+    }
+
+    pub(crate) fn apply_policy(
+        &mut self,
+        var: &str,
+        value: Option<OsString>,
+        reason: String,
+    ) -> &mut ToolCommand {
+        self.apply_policy_in(&Env::current(), var, value, reason)
+    }
+
+    fn apply_policy_in(
+        &mut self,
+        env: &Env,
+        var: &str,
+        value: Option<OsString>,
+        reason: String,
+    ) -> &mut ToolCommand {
+        let inherited = env.var_os(var).map(OsStr::to_os_string);
+        self.set_env(OsStr::new(var), value.clone());
+        self.audit.push(EnvChange { var: var.to_string(), inherited, value, reason });
+        self
+    }
+
+    fn set_env(&mut self, key: &OsStr, value: Option<OsString>) {
+        self.envs.retain(|(it, _)| it != key);
+        self.envs.push((key.to_os_string(), value));
+    }
+}
+
+/// The value a command would inherit, from the client environment or from
+/// the process.
+fn inherited(var: &str) -> Option<OsString> {
+    match crate::env::client_env().into_iter().find(|(key, _)| key == var) {
+        Some((_, value)) => Some(value.into()),
+        None => env::var_os(var),
+    }
+}
+
+/// `path` with `dirs` in front, and removed from the rest. `None` if the
+/// value can't be represented, like with a `:` in a directory on Unix.
+fn augmented_path(dirs: &[PathBuf], path: &OsStr) -> Option<OsString> {
+    let rest = env::split_paths(path).filter(|it| !dirs.contains(it));
+    env::join_paths(dirs.iter().cloned().chain(rest)).ok()
+}
+
+/// Flags of rustc which take their value as the next word.
+const TAKES_VALUE: &[&str] = &[
+    "-C",
+    "-Z",
+    "-L",
+    "-l",
+    "-A",
+    "-W",
+    "-D",
+    "-F",
+    "--force-warn",
+    "--cfg",
+    "--cap-lints",
+    "--target",
+];
+
+/// Codegen options which add up rather than replace each other.
+const ADDITIVE_CODEGEN: &[&str] =
+    &["link-arg", "link-args", "pre-link-arg", "pre-link-args", "llvm-args", "passes", "remark"];
+
+/// Removes the flags of `RUSTFLAGS` which are repeated later, for flags where
+/// the last one wins, see [`Profile::Check`]. Flags with a separate value,
+/// like `-C opt-level=2`, count as one.
+fn dedup_rustflags(flags: &str) -> String {
+    let mut units: Vec<(String, Option<String>)> = Vec::new();
+    let mut words = flags.split_whitespace();
+    while let Some(word) = words.next() {
+        let unit = match words.clone().next() {
+            Some(value) if TAKES_VALUE.contains(&word) => {
+                words.next();
+                format!("{} {}", word, value)
+            }
+            _ => word.to_string(),
+        };
+        let key = replaceable(&unit);
+        units.push((unit, key));
+    }
+    let mut res: Vec<&str> = Vec::new();
+    for (idx, (unit, key)) in units.iter().enumerate() {
+        let repeated = key.is_some() && units[idx + 1..].iter().any(|(_, it)| it == key);
+        if !repeated {
+            res.push(unit);
+        }
+    }
+    res.join(" ")
+}
+
+/// `unit` spelled with a separate value, like `-C opt-level=2` for
+/// `-Copt-level=2`, if repeating it changes nothing.
+fn replaceable(unit: &str) -> Option<String> {
+    let (flag, value) = match split_once(unit, ' ') {
+        Some(it) => it,
+        None if !unit.starts_with("--") && unit.len() > 2 && unit.is_char_boundary(2) => {
+            unit.split_at(2)
+        }
+        None => return None,
+    };
+    let replaceable = match flag {
+        "-A" | "-W" | "-D" | "-F" | "--force-warn" | "--cfg" | "--cap-lints" | "--target" => true,
+        "-C" => {
+            let option = value.split('=').next().unwrap_or(value);
+            !ADDITIVE_CODEGEN.contains(&option)
+        }
+        _ => false,
+    };
+    if replaceable {
+        Some(format!("{} {}", flag, value))
+    } else {
+        None
+    }
+}
+
+impl fmt::Display for ToolCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.program.display())?;
+        for arg in &self.args {
+            write!(f, " {}", arg.to_string_lossy())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env_of(cmd: &ToolCommand, var: &str) -> Option<Option<OsString>> {
+        cmd.to_command()
+            .get_envs()
+            .find(|(key, _)| *key == var)
+            .map(|(_, value)| value.map(OsStr::to_os_string))
+    }
+
+    #[test]
+    fn bootstrap_policies() {
+        let mut cmd = ToolCommand::new("cargo".into());
+        cmd.arg("check").rustc_bootstrap(RustcBootstrap::Inherit);
+        assert_eq!(env_of(&cmd, "RUSTC_BOOTSTRAP"), None);
+        assert!(cmd.audit().is_empty());
+
+        cmd.rustc_bootstrap(RustcBootstrap::Allow);
+        assert_eq!(env_of(&cmd, "RUSTC_BOOTSTRAP"), Some(Some("1".into())));
+
+        cmd.rustc_bootstrap(RustcBootstrap::Forbid);
+        assert_eq!(env_of(&cmd, "RUSTC_BOOTSTRAP"), Some(None));
+
+        let audit: Vec<_> = cmd.audit().iter().map(|it| it.reason.as_str()).collect();
+        assert_eq!(audit, ["RUSTC_BOOTSTRAP policy: Allow", "RUSTC_BOOTSTRAP policy: Forbid"]);
+        assert_eq!(cmd.to_string(), "cargo check");
+    }
+
+    #[test]
+    fn metadata_profile_strips_wrappers() {
+        let mut env = Env::default();
+        env.set_var("RUSTC_WRAPPER", "sccache".into());
+        let mut cmd = ToolCommand::new("cargo".into());
+        cmd.sanitize_in(&env, Profile::Metadata);
+        assert_eq!(env_of(&cmd, "RUSTC_WRAPPER"), Some(Some("".into())));
+        assert_eq!(env_of(&cmd, "RUSTC_WORKSPACE_WRAPPER"), Some(Some("".into())));
+        let change = cmd.audit().iter().find(|it| it.var == "RUSTC_WRAPPER").unwrap();
+        assert_eq!(change.inherited, Some("sccache".into()));
+        assert_eq!(change.reason, "Metadata profile");
+    }
+
+    #[test]
+    fn check_profile_dedups_rustflags() {
+        let mut env = Env::default();
+        env.set_var("RUSTFLAGS", "-C target-cpu=native -Dwarnings -C target-cpu=native".into());
+        let mut cmd = ToolCommand::new("cargo".into());
+        cmd.sanitize_in(&env, Profile::Check);
+        assert_eq!(env_of(&cmd, "RUSTFLAGS"), Some(Some("-Dwarnings -C target-cpu=native".into())));
+
+        assert_eq!(
+            dedup_rustflags("-C opt-level=2 -Copt-level=3 -C opt-level=2 -D warnings"),
+            "-Copt-level=3 -C opt-level=2 -D warnings"
+        );
+        assert_eq!(
+            dedup_rustflags("-C link-arg=-lfoo -L lib -C link-arg=-lfoo -L lib"),
+            "-C link-arg=-lfoo -L lib -C link-arg=-lfoo -L lib"
+        );
+        assert_eq!(dedup_rustflags("--cfg foo --cfg bar --cfg foo"), "--cfg bar --cfg foo");
+        assert_eq!(dedup_rustflags("-C"), "-C");
+    }
+
+    #[test]
+    fn rustc_runs_through_wrapper_chain() {
+        let wrappers = RustcWrappers {
+            wrapper: Some("sccache".into()),
+            workspace_wrapper: Some("clippy-driver".into()),
+        };
+        let mut cmd = ToolCommand::rustc("rustc".into(), &wrappers, true);
+        cmd.arg("-vV");
+        assert_eq!(cmd.to_string(), "sccache clippy-driver rustc -vV");
+        let cmd = ToolCommand::rustc("rustc".into(), &wrappers, false);
+        assert_eq!(cmd.to_string(), "sccache rustc");
+        let cmd = ToolCommand::rustc("rustc".into(), &RustcWrappers::default(), true);
+        assert_eq!(cmd.to_string(), "rustc");
+    }
+
+    #[test]
+    fn cargo_gets_the_whole_wrapper_chain() {
+        let wrappers = RustcWrappers { wrapper: Some("sccache".into()), workspace_wrapper: None };
+        let mut cmd = ToolCommand::new("cargo".into());
+        cmd.arg("check").rustc_wrappers(&wrappers);
+        assert_eq!(env_of(&cmd, "RUSTC_WRAPPER"), Some(Some("sccache".into())));
+        assert_eq!(env_of(&cmd, "RUSTC_WORKSPACE_WRAPPER"), Some(Some("".into())));
+        assert_eq!(cmd.audit().len(), 2);
+    }
+
+    #[test]
+    fn discovered_dirs_go_first_in_path() {
+        let dirs = [PathBuf::from("/opt/rust/bin"), PathBuf::from("/home/me/.cargo/bin")];
+        let path = env::join_paths(["/usr/bin", "/home/me/.cargo/bin", "/bin"]).unwrap();
+        let res = augmented_path(&dirs, &path).unwrap();
+        let res: Vec<PathBuf> = env::split_paths(&res).collect();
+        let expected = ["/opt/rust/bin", "/home/me/.cargo/bin", "/usr/bin", "/bin"];
+        assert_eq!(res, expected.iter().map(PathBuf::from).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn prefix_goes_first() {
+        let rustc = crate::rustc().to_string_lossy().into_owned();
+        let prefix = CommandPrefix::new(vec![rustc.clone(), "-c".to_string()]).unwrap();
+        let mut cmd = ToolCommand::new("cargo".into());
+        cmd.arg("check");
+        let cmd = cmd.to_command_with(prefix.as_ref());
+        assert_eq!(Path::new(cmd.get_program()), Path::new(&rustc));
+        let args: Vec<_> = cmd.get_args().collect();
+        assert_eq!(args, ["-c", "cargo", "check"]);
+    }
+
+    #[test]
+    fn rustup_run_pins_toolchain() {
+        let mut cmd = ToolCommand::rustup_run(&ToolchainSpec::parse("nightly"), "rustfmt");
+        cmd.arg("--check");
+        assert_eq!(cmd.get_args(), ["run", "nightly", "rustfmt", "--check"]);
+    }
+}
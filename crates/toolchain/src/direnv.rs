@@ -0,0 +1,69 @@
+//! The environment direnv sets up for a directory with an `.envrc`, which is
+//! how nix and similar setups provide their toolchains.
+
+use std::{io, path::Path};
+
+use crate::{locate::lookup, ToolCommand};
+
+/// Runs `direnv export json` in `dir`, returning the variables the `.envrc`
+/// sets. `None` if there's no `.envrc`, or direnv isn't installed.
+///
+/// Variables the `.envrc` unsets are not returned, as there's no way to
+/// remove them from the environment of the tools. An `.envrc` which isn't
+/// allowed with `direnv allow` sets nothing.
+pub fn direnv_export(dir: &Path) -> io::Result<Option<Vec<(String, String)>>> {
+    if !crate::execution::execution_environment().is_file(&dir.join(".envrc")) {
+        return Ok(None);
+    }
+    let direnv = match lookup("direnv") {
+        Some(it) => it,
+        None => return Ok(None),
+    };
+    let mut cmd = ToolCommand::new(direnv);
+    cmd.args(["export", "json"]).current_dir(dir);
+    crate::registry::count_spawn();
+    let output = cmd.output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("direnv failed: {}", stderr.trim()),
+        ));
+    }
+    parse(&output.stdout).map(Some)
+}
+
+/// The output is empty if nothing changes, and an object of variable names
+/// to values otherwise, with `null` for unset variables.
+fn parse(stdout: &[u8]) -> io::Result<Vec<(String, String)>> {
+    if stdout.iter().all(u8::is_ascii_whitespace) {
+        return Ok(Vec::new());
+    }
+    let vars: serde_json::Map<String, serde_json::Value> = serde_json::from_slice(stdout)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    let mut res: Vec<(String, String)> = vars
+        .into_iter()
+        // direnv's own bookkeeping, meaningless to the tools.
+        .filter(|(key, _)| !key.starts_with("DIRENV_"))
+        .filter_map(|(key, value)| Some((key, value.as_str()?.to_string())))
+        .collect();
+    res.sort();
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_export_json() {
+        let stdout =
+            br#"{"DIRENV_DIFF":"eJy","PATH":"/nix/store/abc-rust/bin:/usr/bin","OLD":null}"#;
+        assert_eq!(
+            parse(stdout).unwrap(),
+            [("PATH".to_string(), "/nix/store/abc-rust/bin:/usr/bin".to_string())]
+        );
+        assert_eq!(parse(b"\n").unwrap(), []);
+        assert!(parse(b"direnv: error").is_err());
+    }
+}
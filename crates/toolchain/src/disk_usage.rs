@@ -0,0 +1,216 @@
+//! How much disk space the installed toolchains take, to point out the ones
+//! which can go.
+
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use stdx::split_once;
+
+use crate::{locate::default_toolchain, Env, SuggestedCommand, ToolchainSpec};
+
+/// A toolchain directory under `$RUSTUP_HOME/toolchains`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ToolchainUsage {
+    /// The directory name, like `nightly-2021-05-10-x86_64-unknown-linux-gnu`.
+    pub name: String,
+    pub path: PathBuf,
+    /// Everything in the directory, including files no component claims.
+    pub bytes: u64,
+    pub components: Vec<ComponentUsage>,
+    pub is_default: bool,
+}
+
+/// The files rustup installed for a component, like `rust-src`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ComponentUsage {
+    /// The name with the target, like `rust-std-wasm32-unknown-unknown`.
+    pub name: String,
+    pub bytes: u64,
+}
+
+/// Dated nightlies which are neither the default nor the newest one, and the
+/// space they take.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StaleToolchains {
+    pub toolchains: Vec<String>,
+    pub bytes: u64,
+}
+
+/// The toolchains rustup installed, sorted by name. This walks every file of
+/// every toolchain, so it takes a moment.
+pub fn toolchain_usage(env: &Env) -> Vec<ToolchainUsage> {
+    let rustup_home = match env.rustup_home() {
+        Some(it) => it,
+        None => return Vec::new(),
+    };
+    let default = default_toolchain(&rustup_home);
+    let entries = match fs::read_dir(rustup_home.join("toolchains")) {
+        Ok(it) => it,
+        Err(_) => return Vec::new(),
+    };
+    let mut res: Vec<ToolchainUsage> = entries
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            // Linked toolchains are somebody else's directory.
+            if !entry.file_type().ok()?.is_dir() {
+                return None;
+            }
+            let name = entry.file_name().to_str()?.to_string();
+            let path = entry.path();
+            let is_default = default.as_deref().map_or(false, |it| is_same_toolchain(&name, it));
+            Some(ToolchainUsage {
+                bytes: dir_size(&path),
+                components: component_usage(&path),
+                name,
+                path,
+                is_default,
+            })
+        })
+        .collect();
+    res.sort_by(|a, b| a.name.cmp(&b.name));
+    res
+}
+
+/// `default` may be a short name, like `stable`, while toolchain directories
+/// always have the host triple.
+fn is_same_toolchain(dir_name: &str, default: &str) -> bool {
+    dir_name == default || dir_name.starts_with(&format!("{}-", default))
+}
+
+/// Sums up the files each `lib/rustlib/manifest-<component>` lists.
+fn component_usage(toolchain: &Path) -> Vec<ComponentUsage> {
+    let rustlib = toolchain.join("lib/rustlib");
+    let components = fs::read_to_string(rustlib.join("components")).unwrap_or_default();
+    components
+        .lines()
+        .map(|name| {
+            let manifest =
+                fs::read_to_string(rustlib.join(format!("manifest-{}", name))).unwrap_or_default();
+            let bytes = manifest
+                .lines()
+                .filter_map(|line| {
+                    let path = toolchain.join(split_once(line, ':')?.1);
+                    match split_once(line, ':')?.0 {
+                        "file" => Some(fs::symlink_metadata(&path).ok()?.len()),
+                        "dir" => Some(dir_size(&path)),
+                        _ => None,
+                    }
+                })
+                .sum();
+            ComponentUsage { name: name.to_string(), bytes }
+        })
+        .collect()
+}
+
+fn dir_size(path: &Path) -> u64 {
+    let entries = match fs::read_dir(path) {
+        Ok(it) => it,
+        Err(_) => return 0,
+    };
+    entries
+        .filter_map(Result::ok)
+        .map(|entry| match entry.file_type() {
+            Ok(it) if it.is_dir() => dir_size(&entry.path()),
+            // Symlinks count as themselves, not as their target.
+            Ok(_) => entry.metadata().map_or(0, |it| it.len()),
+            Err(_) => 0,
+        })
+        .sum()
+}
+
+/// Picks the [`StaleToolchains`] out of `usage`, if there are any.
+pub fn stale_toolchains(usage: &[ToolchainUsage]) -> Option<StaleToolchains> {
+    let mut dated: Vec<&ToolchainUsage> =
+        usage.iter().filter(|it| nightly_date(&it.name).is_some()).collect();
+    dated.sort_by_key(|it| nightly_date(&it.name));
+    // Keep the newest one, it's likely pinned by some project.
+    dated.pop();
+    let stale: Vec<&ToolchainUsage> = dated.into_iter().filter(|it| !it.is_default).collect();
+    if stale.is_empty() {
+        return None;
+    }
+    Some(StaleToolchains {
+        toolchains: stale.iter().map(|it| it.name.clone()).collect(),
+        bytes: stale.iter().map(|it| it.bytes).sum(),
+    })
+}
+
+/// The `YYYY-MM-DD` of `nightly-YYYY-MM-DD-<host>`.
+fn nightly_date(name: &str) -> Option<String> {
+    let spec = ToolchainSpec::parse(name);
+    match spec.channel() {
+        Some("nightly") => spec.date().map(String::from),
+        _ => None,
+    }
+}
+
+impl StaleToolchains {
+    pub fn suggestions(&self) -> Vec<SuggestedCommand> {
+        vec![SuggestedCommand {
+            description: "Uninstall the stale toolchains".to_string(),
+            command: format!("rustup toolchain uninstall {}", self.toolchains.join(" ")),
+        }]
+    }
+}
+
+impl fmt::Display for StaleToolchains {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = if self.toolchains.len() == 1 { "y" } else { "ies" };
+        write!(f, "{} stale nightl{} use {}", self.toolchains.len(), s, human_size(self.bytes))
+    }
+}
+
+fn human_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["B", "KB", "MB", "GB", "TB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1000.0 && unit + 1 < UNITS.len() {
+        size /= 1000.0;
+        unit += 1;
+    }
+    match unit {
+        0 => format!("{} B", bytes),
+        _ => format!("{:.1} {}", size, UNITS[unit]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testdir::TestDir;
+
+    use super::*;
+
+    #[test]
+    fn stale_nightlies_are_reported() {
+        let tmp = TestDir::new("disk-usage");
+        let home = tmp.path().to_path_buf();
+        let host = "x86_64-unknown-linux-gnu";
+        for &(date, size) in &[("2021-01-01", 1000), ("2021-02-01", 2000), ("2021-03-01", 4000)] {
+            let rustlib = home.join(format!("toolchains/nightly-{}-{}/lib/rustlib", date, host));
+            fs::create_dir_all(rustlib.join("src")).unwrap();
+            fs::write(rustlib.join("src/lib.rs"), vec![b' '; size]).unwrap();
+            fs::write(rustlib.join("components"), "rust-src\n").unwrap();
+            fs::write(rustlib.join("manifest-rust-src"), "dir:lib/rustlib/src\n").unwrap();
+        }
+        fs::create_dir_all(home.join(format!("toolchains/stable-{}", host))).unwrap();
+        fs::write(home.join("settings.toml"), "default_toolchain = \"nightly-2021-01-01\"\n")
+            .unwrap();
+
+        let mut env = Env::default();
+        env.set_var("RUSTUP_HOME", home.clone().into());
+        let usage = toolchain_usage(&env);
+        assert_eq!(usage.len(), 4);
+        assert!(usage[0].is_default);
+        assert_eq!(usage[1].components, [ComponentUsage { name: "rust-src".into(), bytes: 2000 }]);
+
+        let stale = stale_toolchains(&usage).unwrap();
+        assert_eq!(stale.toolchains, [format!("nightly-2021-02-01-{}", host)]);
+        assert_eq!(stale.to_string(), "1 stale nightly use 2.0 KB");
+        assert_eq!(
+            stale.suggestions()[0].command,
+            format!("rustup toolchain uninstall nightly-2021-02-01-{}", host)
+        );
+    }
+}
@@ -0,0 +1,100 @@
+//! `.env` files, as used by dotenv and friends to give a project the
+//! variables its build scripts and macros expect, like `DATABASE_URL`.
+
+use std::{fs, io, path::Path};
+
+use stdx::split_once;
+
+/// Reads `dir/.env`, returning its variables in order. A missing file has no
+/// variables.
+///
+/// References to other variables, like `${HOME}`, are not expanded.
+pub fn load_dotenv(dir: &Path) -> io::Result<Vec<(String, String)>> {
+    match fs::read_to_string(dir.join(".env")) {
+        Ok(text) => Ok(parse(&text)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(err),
+    }
+}
+
+/// Understands `KEY=value` lines, optionally starting with `export`, with
+/// single quoted values taken literally and double quoted ones allowing
+/// `\n`, `\"` and `\\` escapes. Lines which make no sense are skipped.
+fn parse(text: &str) -> Vec<(String, String)> {
+    let mut res = Vec::new();
+    for line in text.lines() {
+        let line = line.trim();
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        if line.starts_with('#') {
+            continue;
+        }
+        let (key, value) = match split_once(line, '=') {
+            Some(it) => it,
+            None => continue,
+        };
+        let key = key.trim();
+        if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            continue;
+        }
+        res.push((key.to_string(), parse_value(value.trim())));
+    }
+    res
+}
+
+fn parse_value(value: &str) -> String {
+    if let Some(rest) = value.strip_prefix('\'') {
+        return rest.split('\'').next().unwrap_or_default().to_string();
+    }
+    if let Some(rest) = value.strip_prefix('"') {
+        let mut res = String::new();
+        let mut chars = rest.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '"' => break,
+                '\\' => match chars.next() {
+                    Some('n') => res.push('\n'),
+                    Some(c) => res.push(c),
+                    None => break,
+                },
+                c => res.push(c),
+            }
+        }
+        return res;
+    }
+    // A comment needs whitespace before it, `#` may be part of a value.
+    match value.find(" #") {
+        Some(idx) => value[..idx].trim_end().to_string(),
+        None => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_common_spellings() {
+        let text = r#"
+# Local database
+DATABASE_URL=postgres://localhost/app # dev only
+export RUST_LOG=debug
+GREETING="hello\n\"world\""
+LITERAL='no \n escapes'
+COLOR=#fff
+not a variable
+"#;
+        assert_eq!(
+            parse(text),
+            [
+                ("DATABASE_URL", "postgres://localhost/app"),
+                ("RUST_LOG", "debug"),
+                ("GREETING", "hello\n\"world\""),
+                ("LITERAL", "no \\n escapes"),
+                ("COLOR", "#fff"),
+            ]
+            .iter()
+            .map(|&(k, v)| (k.to_string(), v.to_string()))
+            .collect::<Vec<_>>()
+        );
+    }
+}
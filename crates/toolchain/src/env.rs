@@ -0,0 +1,211 @@
+//! A snapshot of the environment discovery runs in.
+
+use std::{
+    collections::BTreeMap,
+    env,
+    ffi::{OsStr, OsString},
+    path::PathBuf,
+    sync::Mutex,
+};
+
+use once_cell::sync::Lazy;
+
+/// The environment variables and working directory discovery looks at.
+///
+/// [`Env::current`] captures the process environment, but an `Env` can just
+/// as well be built by hand, to replay the environment from a bug report or
+/// to use the one sent by an editor.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Env {
+    vars: BTreeMap<String, OsString>,
+    cwd: Option<PathBuf>,
+    /// Only set by [`Env::current`], where the OS may know the home directory
+    /// even if `HOME` is unset.
+    home_dir: Option<PathBuf>,
+}
+
+/// Sets the environment variables forwarded by the editor, like the `PATH` of
+/// the user's login shell. They take precedence over the environment of the
+/// server process, both for discovery and for the commands built by
+/// [`crate::ToolCommand`].
+///
+/// Returns whether the variables changed, not counting the ones which only
+/// describe the login session, like `SSH_AUTH_SOCK`. These are still passed
+/// to the tools.
+pub fn set_client_env(vars: Vec<(String, String)>) -> bool {
+    let mut client_env = CLIENT_ENV.lock().unwrap();
+    let changed = !same_vars(&client_env, &vars);
+    *client_env = vars;
+    if changed {
+        crate::invalidate_tools();
+    }
+    changed
+}
+
+/// Variables which differ between login sessions without changing what the
+/// tools do, like the socket of the ssh agent.
+const SESSION_VARS: &[&str] = &[
+    "SSH_AGENT_PID",
+    "SSH_AUTH_SOCK",
+    "GPG_AGENT_INFO",
+    "DBUS_SESSION_BUS_ADDRESS",
+    "XDG_SESSION_ID",
+    "WINDOWID",
+    "TERM_SESSION_ID",
+];
+
+fn same_vars(old: &[(String, String)], new: &[(String, String)]) -> bool {
+    let relevant = |vars: &[(String, String)]| -> Vec<(String, String)> {
+        let mut res: Vec<_> =
+            vars.iter().filter(|(key, _)| !SESSION_VARS.contains(&key.as_str())).cloned().collect();
+        res.sort();
+        res
+    };
+    relevant(old) == relevant(new)
+}
+
+static CLIENT_ENV: Lazy<Mutex<Vec<(String, String)>>> = Lazy::new(Default::default);
+
+pub(crate) fn client_env() -> Vec<(String, String)> {
+    CLIENT_ENV.lock().unwrap().clone()
+}
+
+impl Env {
+    /// The process environment, with the variables from [`set_client_env`]
+    /// on top.
+    pub fn current() -> Env {
+        let mut vars: BTreeMap<String, OsString> = env::vars_os()
+            .filter_map(|(key, value)| Some((normalize(key.to_str()?), value)))
+            .collect();
+        for (key, value) in client_env() {
+            vars.insert(normalize(&key), value.into());
+        }
+        Env { vars, cwd: env::current_dir().ok(), home_dir: home::home_dir() }
+    }
+
+    pub fn var_os(&self, key: &str) -> Option<&OsStr> {
+        self.vars.get(&normalize(key)).map(OsString::as_os_str)
+    }
+
+    /// The value of `key`, if it's set and valid unicode.
+    pub fn var(&self, key: &str) -> Option<&str> {
+        self.var_os(key)?.to_str()
+    }
+
+    pub fn set_var(&mut self, key: &str, value: OsString) {
+        self.vars.insert(normalize(key), value);
+    }
+
+    pub fn remove_var(&mut self, key: &str) {
+        self.vars.remove(&normalize(key));
+    }
+
+    pub fn vars(&self) -> impl Iterator<Item = (&str, &OsStr)> {
+        self.vars.iter().map(|(key, value)| (key.as_str(), value.as_os_str()))
+    }
+
+    pub fn cwd(&self) -> Option<&PathBuf> {
+        self.cwd.as_ref()
+    }
+
+    pub fn set_cwd(&mut self, cwd: PathBuf) {
+        self.cwd = Some(cwd);
+    }
+
+    /// The entries of `PATH`, with relative ones resolved against the working
+    /// directory.
+    pub fn path_dirs(&self) -> Vec<PathBuf> {
+        let paths = self.var_os("PATH").unwrap_or_default();
+        env::split_paths(paths).map(|it| self.absolute(it)).collect()
+    }
+
+    pub(crate) fn home_dir(&self) -> Option<PathBuf> {
+        let var = if cfg!(windows) { "USERPROFILE" } else { "HOME" };
+        match self.var_os(var).filter(|it| !it.is_empty()) {
+            Some(home) => Some(self.absolute(home.into())),
+            None => self.home_dir.clone(),
+        }
+    }
+
+    /// `$RUSTUP_HOME`, defaulting to `~/.rustup`, like rustup does.
+    pub(crate) fn rustup_home(&self) -> Option<PathBuf> {
+        match self.var_os("RUSTUP_HOME").filter(|it| !it.is_empty()) {
+            Some(it) => Some(self.absolute(it.into())),
+            None => Some(self.home_dir()?.join(".rustup")),
+        }
+    }
+
+    /// `$CARGO_HOME`, defaulting to `~/.cargo`, like cargo does.
+    pub(crate) fn cargo_home(&self) -> Option<PathBuf> {
+        match self.var_os("CARGO_HOME").filter(|it| !it.is_empty()) {
+            Some(it) => Some(self.absolute(it.into())),
+            None => Some(self.home_dir()?.join(".cargo")),
+        }
+    }
+
+    pub(crate) fn absolute(&self, path: PathBuf) -> PathBuf {
+        match &self.cwd {
+            Some(cwd) if path.is_relative() => cwd.join(path),
+            _ => path,
+        }
+    }
+}
+
+/// Variable names are case-insensitive on Windows.
+fn normalize(key: &str) -> String {
+    if cfg!(windows) {
+        key.to_ascii_uppercase()
+    } else {
+        key.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn client_env_wins() {
+        set_client_env(vec![("RA_TOOLCHAIN_TEST_CLIENT_VAR".to_string(), "client".to_string())]);
+        let env = Env::current();
+        let spawned = if cfg!(unix) {
+            let mut cmd = crate::interpreter::command(std::path::Path::new("/bin/sh"));
+            cmd.args(["-c", "printf %s \"$RA_TOOLCHAIN_TEST_CLIENT_VAR\""]);
+            Some(crate::utf8_stdout(cmd).unwrap())
+        } else {
+            None
+        };
+        set_client_env(Vec::new());
+        assert_eq!(env.var("RA_TOOLCHAIN_TEST_CLIENT_VAR"), Some("client"));
+        if let Some(spawned) = spawned {
+            assert_eq!(spawned, "client");
+        }
+        assert_eq!(Env::current().var("RA_TOOLCHAIN_TEST_CLIENT_VAR"), None);
+    }
+
+    #[test]
+    fn session_vars_are_not_changes() {
+        let vars = |agent: &str, path: &str| {
+            vec![
+                ("SSH_AGENT_PID".to_string(), agent.to_string()),
+                ("PATH".to_string(), path.to_string()),
+            ]
+        };
+        assert!(same_vars(&vars("1234", "/usr/bin"), &vars("5678", "/usr/bin")));
+        assert!(!same_vars(&vars("1234", "/usr/bin"), &vars("1234", "/opt/bin")));
+    }
+
+    #[test]
+    fn paths_are_resolved_against_cwd() {
+        let mut env = Env::default();
+        env.set_cwd("/work".into());
+        let path = env::join_paths(["/usr/bin", "tools"]).unwrap();
+        env.set_var("PATH", path);
+        env.set_var("RUSTUP_HOME", "rustup".into());
+        assert_eq!(env.path_dirs(), [PathBuf::from("/usr/bin"), PathBuf::from("/work/tools")]);
+        assert_eq!(env.rustup_home(), Some(PathBuf::from("/work/rustup")));
+
+        env.remove_var("RUSTUP_HOME");
+        assert_eq!(env.rustup_home(), None);
+    }
+}
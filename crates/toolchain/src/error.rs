@@ -0,0 +1,210 @@
+//! The error type of discovery, with stable codes.
+//!
+//! Codes never change meaning once assigned, so that editors can link them to
+//! troubleshooting docs and tests can check for the exact failure. When a
+//! new kind of failure is added, it gets the next free code.
+//!
+//! The messages themselves are rendered through a [`MessageCatalog`].
+
+use std::{error, fmt, path::PathBuf};
+
+use crate::MessageCatalog;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiscoveryError {
+    kind: ErrorKind,
+    tool: String,
+    path: Option<PathBuf>,
+    var: Option<String>,
+    detail: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    /// `E0101`: an override like `$CARGO` is set, but doesn't point at an
+    /// executable file.
+    EnvVarNotExecutable,
+    /// `E0102`: the tool was found nowhere.
+    NotFound,
+    /// `E0103`: the executable could not be started.
+    SpawnFailed,
+    /// `E0104`: the executable ran, but exited with an error.
+    BadExitStatus,
+    /// `E0105`: the executable ran, but its output made no sense.
+    UnexpectedOutput,
+    /// `E0106`: the standard library for the build target is missing from the
+    /// sysroot. The target is the detail of the error.
+    TargetNotInstalled,
+    /// `E0107`: the executable is damaged in a way rustup is known to leave
+    /// it, see [`crate::RustupDiagnosis`]. The diagnosis is the detail.
+    RustupCorrupted,
+    /// `E0108`: a download failed at the HTTP(S) proxy, like a refused
+    /// connection or missing proxy credentials.
+    ProxyFailed,
+    /// `E0109`: a download failed because the server could not be reached.
+    ServerUnreachable,
+    /// `E0110`: a directory the tool has to write to is read-only.
+    NotWritable,
+    /// `E0111`: the standard library sources are not where `RUST_SRC_PATH`
+    /// says.
+    InvalidRustSrc,
+    /// `E0112`: the executable leads back to the running process, like a
+    /// `$RUSTC` wrapper which runs rust-analyzer.
+    WrapperCycle,
+    /// `E0113`: the executable works, but is another tool than expected,
+    /// according to its `--version`.
+    WrongTool,
+    /// `E0114`: the tool is too old for a flag or behavior, see
+    /// [`crate::ensure_supports`]. The capability is the detail.
+    Unsupported,
+    /// `E0115`: a platform tool rustc needs to link, like `link.exe`, is
+    /// missing. The tool is the detail, see [`crate::Prerequisite`].
+    MissingPrerequisite,
+    /// `E0116`: the discovery itself panicked, which is a bug. The panic
+    /// message is the detail.
+    DiscoveryPanicked,
+}
+
+impl ErrorKind {
+    pub const ALL: &'static [ErrorKind] = &[
+        ErrorKind::EnvVarNotExecutable,
+        ErrorKind::NotFound,
+        ErrorKind::SpawnFailed,
+        ErrorKind::BadExitStatus,
+        ErrorKind::UnexpectedOutput,
+        ErrorKind::TargetNotInstalled,
+        ErrorKind::RustupCorrupted,
+        ErrorKind::ProxyFailed,
+        ErrorKind::ServerUnreachable,
+        ErrorKind::NotWritable,
+        ErrorKind::InvalidRustSrc,
+        ErrorKind::WrapperCycle,
+        ErrorKind::WrongTool,
+        ErrorKind::Unsupported,
+        ErrorKind::MissingPrerequisite,
+        ErrorKind::DiscoveryPanicked,
+    ];
+
+    pub fn code(self) -> &'static str {
+        match self {
+            ErrorKind::EnvVarNotExecutable => "E0101",
+            ErrorKind::NotFound => "E0102",
+            ErrorKind::SpawnFailed => "E0103",
+            ErrorKind::BadExitStatus => "E0104",
+            ErrorKind::UnexpectedOutput => "E0105",
+            ErrorKind::TargetNotInstalled => "E0106",
+            ErrorKind::RustupCorrupted => "E0107",
+            ErrorKind::ProxyFailed => "E0108",
+            ErrorKind::ServerUnreachable => "E0109",
+            ErrorKind::NotWritable => "E0110",
+            ErrorKind::InvalidRustSrc => "E0111",
+            ErrorKind::WrapperCycle => "E0112",
+            ErrorKind::WrongTool => "E0113",
+            ErrorKind::Unsupported => "E0114",
+            ErrorKind::MissingPrerequisite => "E0115",
+            ErrorKind::DiscoveryPanicked => "E0116",
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ErrorKind::EnvVarNotExecutable => "EnvVarNotExecutable",
+            ErrorKind::NotFound => "NotFound",
+            ErrorKind::SpawnFailed => "SpawnFailed",
+            ErrorKind::BadExitStatus => "BadExitStatus",
+            ErrorKind::UnexpectedOutput => "UnexpectedOutput",
+            ErrorKind::TargetNotInstalled => "TargetNotInstalled",
+            ErrorKind::RustupCorrupted => "RustupCorrupted",
+            ErrorKind::ProxyFailed => "ProxyFailed",
+            ErrorKind::ServerUnreachable => "ServerUnreachable",
+            ErrorKind::NotWritable => "NotWritable",
+            ErrorKind::InvalidRustSrc => "InvalidRustSrc",
+            ErrorKind::WrapperCycle => "WrapperCycle",
+            ErrorKind::WrongTool => "WrongTool",
+            ErrorKind::Unsupported => "Unsupported",
+            ErrorKind::MissingPrerequisite => "MissingPrerequisite",
+            ErrorKind::DiscoveryPanicked => "DiscoveryPanicked",
+        }
+    }
+}
+
+impl DiscoveryError {
+    pub(crate) fn new(kind: ErrorKind, tool: &str) -> DiscoveryError {
+        DiscoveryError { kind, tool: tool.to_string(), path: None, var: None, detail: None }
+    }
+
+    pub(crate) fn with_path(mut self, path: PathBuf) -> DiscoveryError {
+        self.path = Some(path);
+        self
+    }
+
+    pub(crate) fn with_var(mut self, var: String) -> DiscoveryError {
+        self.var = Some(var);
+        self
+    }
+
+    pub(crate) fn with_detail(mut self, detail: String) -> DiscoveryError {
+        self.detail = Some(detail);
+        self
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    pub fn code(&self) -> &'static str {
+        self.kind.code()
+    }
+
+    pub fn tool(&self) -> &str {
+        &self.tool
+    }
+
+    pub fn path(&self) -> Option<&PathBuf> {
+        self.path.as_ref()
+    }
+
+    pub fn var(&self) -> Option<&str> {
+        self.var.as_deref()
+    }
+
+    /// Extra information, like the stderr of a failed process.
+    pub fn detail(&self) -> Option<&str> {
+        self.detail.as_deref()
+    }
+}
+
+impl fmt::Display for DiscoveryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = self.render(&MessageCatalog::default());
+        write!(f, "{} {}: {}", self.code(), self.kind.name(), message)
+    }
+}
+
+impl error::Error for DiscoveryError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes_are_unique() {
+        let mut codes: Vec<_> = ErrorKind::ALL.iter().map(|it| it.code()).collect();
+        codes.sort_unstable();
+        codes.dedup();
+        assert_eq!(codes.len(), ErrorKind::ALL.len());
+    }
+
+    #[test]
+    fn display_includes_code() {
+        let err = DiscoveryError::new(ErrorKind::EnvVarNotExecutable, "cargo")
+            .with_var("CARGO".to_string())
+            .with_path("/nope/cargo".into());
+        assert_eq!(
+            err.to_string(),
+            "E0101 EnvVarNotExecutable: $CARGO is set to `/nope/cargo`, which is not an executable"
+        );
+        let err = DiscoveryError::new(ErrorKind::NotFound, "rustfmt");
+        assert_eq!(err.to_string(), "E0102 NotFound: could not find rustfmt");
+    }
+}
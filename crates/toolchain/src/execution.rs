@@ -0,0 +1,190 @@
+//! Where executables live and how they are spawned.
+//!
+//! Discovery asks an [`ExecutionEnvironment`] rather than the local machine,
+//! so that tools inside a container, on a remote host or in a sandbox can be
+//! found and run by implementing the trait, without discovery knowing about
+//! the backend.
+//!
+//! The trait covers looking for executables and at their files, for
+//! [`crate::Validation::MetadataOnly`], [`crate::Validation::Header`],
+//! overrides like `$CARGO` and fingerprints, and the commands which run to
+//! completion: validation, the queries like `rustc -vV`, and
+//! [`crate::ToolCommand::output`], which `cargo metadata`, installs, direnv
+//! and the health check use. The rest stays on the local machine:
+//!
+//! - reading other files, like rustup's settings, or when diagnosing a
+//!   broken rustup installation;
+//! - the checks which are about the local machine by nature, like for
+//!   Windows app execution aliases and for a tool which is the running
+//!   server;
+//! - processes which are streamed or killed on a timeout, like
+//!   check-on-save and the login shell.
+
+use std::{
+    cell::RefCell,
+    ffi::OsString,
+    fmt, fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    process::{Command, Output},
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use once_cell::sync::Lazy;
+
+use crate::{slow_fs, validate::is_executable};
+
+/// What [`ExecutionEnvironment::metadata`] tells about a path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileMetadata {
+    pub is_file: bool,
+    /// Whether the file can be spawned as is, always `true` on Windows.
+    pub is_executable: bool,
+    pub len: u64,
+    pub modified: Option<SystemTime>,
+}
+
+pub trait ExecutionEnvironment: fmt::Debug + Send + Sync {
+    /// Whether `path` is a file, as seen by the processes this environment
+    /// spawns.
+    fn is_file(&self, path: &Path) -> bool;
+
+    /// The names of the entries of the directory at `path`.
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<OsString>>;
+
+    /// When the directory at `path` last changed, to tell whether a listing
+    /// of it is stale. Backends which can't tell fail, and directories are
+    /// listed again whenever a tool isn't found in them.
+    fn modified(&self, path: &Path) -> io::Result<SystemTime> {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("modification time of {} unknown", path.display()),
+        ))
+    }
+
+    /// `path` with symbolic links resolved, used to avoid probing the same
+    /// file twice. Backends which can't tell keep the path as is.
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        Ok(path.to_path_buf())
+    }
+
+    /// The metadata of the file at `path`, following symbolic links.
+    fn metadata(&self, path: &Path) -> io::Result<FileMetadata>;
+
+    /// Up to the first `len` bytes of the file at `path`.
+    fn read_prefix(&self, path: &Path, len: usize) -> io::Result<Vec<u8>>;
+
+    /// Runs `cmd` to completion, like [`Command::output`]. Its program,
+    /// arguments, environment and working directory are all readable through
+    /// the `get_*` methods of `Command`.
+    fn output(&self, cmd: &mut Command) -> io::Result<Output>;
+}
+
+/// The machine the server runs on, the default.
+#[derive(Debug, Default)]
+pub struct LocalEnvironment;
+
+impl ExecutionEnvironment for LocalEnvironment {
+    /// Gives up on directories which don't answer in time, see
+    /// [`crate::set_stat_timeout`].
+    fn is_file(&self, path: &Path) -> bool {
+        slow_fs::is_file(path)
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<OsString>> {
+        slow_fs::read_dir(path)
+    }
+
+    fn modified(&self, path: &Path) -> io::Result<SystemTime> {
+        slow_fs::modified(path)
+    }
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        slow_fs::canonicalize(path)
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<FileMetadata> {
+        let metadata = fs::metadata(path)?;
+        Ok(FileMetadata {
+            is_file: metadata.is_file(),
+            is_executable: is_executable(&metadata),
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+        })
+    }
+
+    fn read_prefix(&self, path: &Path, len: usize) -> io::Result<Vec<u8>> {
+        let mut res = Vec::new();
+        fs::File::open(path)?.take(len as u64).read_to_end(&mut res)?;
+        Ok(res)
+    }
+
+    fn output(&self, cmd: &mut Command) -> io::Result<Output> {
+        cmd.output()
+    }
+}
+
+static CURRENT: Lazy<Mutex<Arc<dyn ExecutionEnvironment>>> =
+    Lazy::new(|| Mutex::new(Arc::new(LocalEnvironment)));
+
+/// Makes discovery look for and run tools in `env`. The tools discovered so
+/// far are forgotten.
+pub fn set_execution_environment(env: Arc<dyn ExecutionEnvironment>) {
+    *CURRENT.lock().unwrap() = env;
+    crate::invalidate_tools();
+}
+
+thread_local! {
+    /// Takes precedence over [`CURRENT`] on this thread, see
+    /// [`with_scoped`].
+    static SCOPED: RefCell<Option<Arc<dyn ExecutionEnvironment>>> = RefCell::new(None);
+}
+
+pub(crate) fn execution_environment() -> Arc<dyn ExecutionEnvironment> {
+    match SCOPED.with(|it| it.borrow().clone()) {
+        Some(it) => it,
+        None => CURRENT.lock().unwrap().clone(),
+    }
+}
+
+/// Runs `f` with discovery on this thread using `env`, leaving the other
+/// threads alone. The paths memoized by [`crate::cargo`] and friends are
+/// shared by all threads, so `f` should stick to [`crate::locate_in`].
+#[cfg(any(test, feature = "test-harness"))]
+pub(crate) fn with_scoped<T>(env: Arc<dyn ExecutionEnvironment>, f: impl FnOnce() -> T) -> T {
+    struct Restore(Option<Arc<dyn ExecutionEnvironment>>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            let previous = self.0.take();
+            SCOPED.with(|it| *it.borrow_mut() = previous);
+        }
+    }
+
+    let previous = SCOPED.with(|it| it.borrow_mut().replace(env));
+    let _restore = Restore(previous);
+    f()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    #[test]
+    fn local_environment_sees_local_files() {
+        let local = LocalEnvironment;
+        let exe = env::current_exe().unwrap();
+        assert!(local.is_file(&exe));
+        let entries = local.read_dir(exe.parent().unwrap()).unwrap();
+        assert!(entries.contains(&exe.file_name().unwrap().to_os_string()));
+        let metadata = local.metadata(&exe).unwrap();
+        assert!(metadata.is_file && metadata.is_executable);
+        assert_eq!(metadata.len, fs::metadata(&exe).unwrap().len());
+        assert_eq!(local.read_prefix(&exe, 2).unwrap().len(), 2);
+
+        let output = local.output(Command::new(crate::rustc()).arg("--version")).unwrap();
+        assert!(output.status.success());
+    }
+}
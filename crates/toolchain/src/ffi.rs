@@ -0,0 +1,136 @@
+//! A C ABI for editors which aren't written in Rust, behind the `ffi`
+//! feature.
+//!
+//! The crate is a plain `lib`. To get a library to link against, build it
+//! with `cargo rustc -p toolchain --features ffi --crate-type cdylib` (or
+//! `staticlib`). The C declarations are:
+//!
+//! ```c
+//! char *ra_toolchain_locate(const char *tool);
+//! char *ra_toolchain_report(void);
+//! void ra_toolchain_string_free(char *s);
+//! ```
+//!
+//! Every returned string is owned by the caller and has to be released with
+//! `ra_toolchain_string_free`. `NULL` means failure; panics don't cross the
+//! boundary.
+
+use std::{
+    ffi::{CStr, CString},
+    os::raw::c_char,
+    panic, ptr,
+};
+
+use serde_json::json;
+
+use crate::{discover_all, DiscoveryError, ToolStatus};
+
+/// Locates `tool`, a NUL-terminated name like `cargo`, and returns its path.
+/// `NULL` if it isn't found, or if `tool` isn't valid UTF-8.
+///
+/// # Safety
+///
+/// `tool` has to be `NULL` or point to a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn ra_toolchain_locate(tool: *const c_char) -> *mut c_char {
+    if tool.is_null() {
+        return ptr::null_mut();
+    }
+    let tool = match CStr::from_ptr(tool).to_str() {
+        Ok(it) => it.to_string(),
+        Err(_) => return ptr::null_mut(),
+    };
+    guarded(|| {
+        let found = crate::locate(&tool).ok()?;
+        Some(found.path.to_string_lossy().into_owned())
+    })
+}
+
+/// Runs [`discover_all`] and returns the result as a JSON object, keyed by
+/// tool name:
+///
+/// ```json
+/// { "cargo": { "found": true, "path": "...", "version": "1.52.0", "error": null } }
+/// ```
+///
+/// Errors are objects with a `code` like `E0101` and a `message`.
+#[no_mangle]
+pub extern "C" fn ra_toolchain_report() -> *mut c_char {
+    guarded(|| Some(report().to_string()))
+}
+
+/// Releases a string returned by this API. Does nothing for `NULL`.
+///
+/// # Safety
+///
+/// `s` has to be `NULL` or a string returned by this API, which isn't used
+/// afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn ra_toolchain_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+fn report() -> serde_json::Value {
+    let tools: serde_json::Map<_, _> =
+        discover_all().into_iter().map(|(tool, it)| (tool.to_string(), status_json(&it))).collect();
+    tools.into()
+}
+
+fn status_json(status: &ToolStatus) -> serde_json::Value {
+    json!({
+        "found": status.found,
+        "path": status.path.as_ref().map(|it| it.to_string_lossy().into_owned()),
+        "version": status.version.as_ref().map(|it| it.to_string()),
+        "error": status.error.as_ref().map(error_json),
+    })
+}
+
+fn error_json(err: &DiscoveryError) -> serde_json::Value {
+    json!({ "code": err.code(), "message": err.to_string() })
+}
+
+/// Runs `f`, turning its result into a C string, and failures, panics
+/// included, into `NULL`.
+fn guarded(f: impl FnOnce() -> Option<String>) -> *mut c_char {
+    let res = panic::catch_unwind(panic::AssertUnwindSafe(f)).ok().flatten();
+    match res.and_then(|it| CString::new(it).ok()) {
+        Some(it) => it.into_raw(),
+        None => ptr::null_mut(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ErrorKind;
+
+    use super::*;
+
+    fn take(s: *mut c_char) -> Option<String> {
+        if s.is_null() {
+            return None;
+        }
+        let res = unsafe { CStr::from_ptr(s) }.to_str().unwrap().to_string();
+        unsafe { ra_toolchain_string_free(s) };
+        Some(res)
+    }
+
+    #[test]
+    fn locates_through_c_strings() {
+        let cargo = CString::new("cargo").unwrap();
+        let path = take(unsafe { ra_toolchain_locate(cargo.as_ptr()) }).unwrap();
+        assert!(path.contains("cargo"), "{}", path);
+
+        let missing = CString::new("surely-not-a-real-rust-tool").unwrap();
+        assert_eq!(take(unsafe { ra_toolchain_locate(missing.as_ptr()) }), None);
+        assert_eq!(take(unsafe { ra_toolchain_locate(ptr::null()) }), None);
+    }
+
+    #[test]
+    fn errors_have_codes() {
+        let err = DiscoveryError::new(ErrorKind::NotFound, "cargo");
+        assert_eq!(error_json(&err)["code"], ErrorKind::NotFound.code());
+        assert_eq!(take(guarded(|| panic!("boom"))), None);
+    }
+}
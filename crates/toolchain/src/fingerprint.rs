@@ -0,0 +1,109 @@
+//! Cheap identity of an executable, used as a cache key for the answers we
+//! get by spawning it.
+
+use std::{
+    env, io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::{execution::execution_environment, toolchain_pin, Env, ToolchainPin};
+
+/// Identifies an executable by its canonical path, size and modification
+/// time, so that a cached answer is dropped once the binary is replaced.
+///
+/// A rustup proxy stays the same file when the toolchain behind it changes,
+/// so `RUSTUP_TOOLCHAIN` and the `rust-toolchain` file for the directory the
+/// executable runs in are part of the fingerprint too. Directory overrides
+/// set with `rustup override` are not tracked.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Fingerprint {
+    path: PathBuf,
+    len: u64,
+    modified: Option<SystemTime>,
+    rustup_toolchain: Option<String>,
+    pin: Option<ToolchainPin>,
+}
+
+impl Fingerprint {
+    /// Fingerprints `executable`, looking it up in `PATH` if it is a bare
+    /// name like `rustc`, for running it in the working directory of the
+    /// server.
+    pub fn of(executable: &Path) -> io::Result<Fingerprint> {
+        let mut res = Fingerprint::without_pin(executable)?;
+        res.pin = env::current_dir().ok().and_then(|it| toolchain_pin(&it));
+        Ok(res)
+    }
+
+    /// Like [`Fingerprint::of`], for running `executable` in `dir`, like the
+    /// root of a workspace.
+    pub fn of_in(executable: &Path, dir: &Path) -> io::Result<Fingerprint> {
+        let mut res = Fingerprint::without_pin(executable)?;
+        res.pin = toolchain_pin(dir);
+        Ok(res)
+    }
+
+    fn without_pin(executable: &Path) -> io::Result<Fingerprint> {
+        let env = Env::current();
+        let path = match executable.parent() {
+            Some(parent) if parent != Path::new("") => executable.to_path_buf(),
+            _ => crate::locate::lookup_in_path(&env, &executable.to_string_lossy()).ok_or_else(
+                || {
+                    io::Error::new(
+                        io::ErrorKind::NotFound,
+                        format!("{} is not in PATH", executable.display()),
+                    )
+                },
+            )?,
+        };
+        let exec_env = execution_environment();
+        let path = exec_env.canonicalize(&path)?;
+        let metadata = exec_env.metadata(&path)?;
+        Ok(Fingerprint {
+            path,
+            len: metadata.len,
+            modified: metadata.modified,
+            // Through `Env`, so that a toolchain forwarded by the editor
+            // changes the fingerprint too.
+            rustup_toolchain: env.var("RUSTUP_TOOLCHAIN").map(str::to_string),
+            pin: None,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::testdir::TestDir;
+
+    use super::*;
+
+    #[test]
+    fn fingerprint_is_stable() {
+        let exe = env::current_exe().unwrap();
+        let fingerprint = Fingerprint::of(&exe).unwrap();
+        assert_eq!(fingerprint, Fingerprint::of(&exe).unwrap());
+        assert_eq!(fingerprint.path(), exe.canonicalize().unwrap());
+    }
+
+    #[test]
+    fn fingerprint_follows_the_pin_of_the_dir() {
+        let tmp = TestDir::new("fingerprint");
+        let exe = env::current_exe().unwrap();
+        let unpinned = Fingerprint::of_in(&exe, tmp.path()).unwrap();
+        fs::write(tmp.path().join("rust-toolchain"), "nightly\n").unwrap();
+        let pinned = Fingerprint::of_in(&exe, tmp.path()).unwrap();
+        assert_ne!(pinned, unpinned);
+        assert_eq!(pinned.path(), unpinned.path());
+    }
+
+    #[test]
+    fn missing_executable_has_no_fingerprint() {
+        assert!(Fingerprint::of(Path::new("surely-not-a-real-rust-tool")).is_err());
+    }
+}
@@ -0,0 +1,315 @@
+//! A virtual machine for testing discovery: files and scripted fake
+//! executables which only exist in memory, and generated `PATH` layouts to
+//! check the invariants of discovery against.
+//!
+//! Enabled by the `test-harness` feature. There's no property testing
+//! library in the dependency tree, so layouts come from a seeded generator:
+//! a failing case is reproduced by its seed, which [`check_layouts`] prints.
+
+use std::{
+    ffi::OsString,
+    io,
+    path::{Path, PathBuf},
+    process::{Command, ExitStatus, Output},
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use rustc_hash::FxHashMap;
+
+use crate::{execution, Env, ExecutionEnvironment, FileMetadata};
+
+/// What a fake executable does when run, whatever the arguments.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FakeExecutable {
+    pub exit_code: i32,
+    pub stdout: String,
+    pub stderr: String,
+    pub delay: Duration,
+}
+
+impl FakeExecutable {
+    /// Prints `<name> <version>` to `--version`, like the real tools.
+    pub fn tool(name: &str, version: &str) -> FakeExecutable {
+        FakeExecutable { stdout: format!("{} {}\n", name, version), ..FakeExecutable::default() }
+    }
+}
+
+/// An [`ExecutionEnvironment`] with only the files it was given.
+#[derive(Debug, Default)]
+pub struct VirtualEnvironment {
+    /// `None` for files which are not executables.
+    files: Mutex<FxHashMap<PathBuf, Option<FakeExecutable>>>,
+    runs: Mutex<Vec<(PathBuf, Vec<OsString>)>>,
+}
+
+impl VirtualEnvironment {
+    pub fn add_file(&self, path: impl Into<PathBuf>) {
+        self.files.lock().unwrap().insert(path.into(), None);
+    }
+
+    pub fn add_executable(&self, path: impl Into<PathBuf>, executable: FakeExecutable) {
+        self.files.lock().unwrap().insert(path.into(), Some(executable));
+    }
+
+    /// The programs run so far, with their arguments.
+    pub fn runs(&self) -> Vec<(PathBuf, Vec<OsString>)> {
+        self.runs.lock().unwrap().clone()
+    }
+
+    /// Runs `f` with discovery on this thread looking at `self` only, see
+    /// [`ExecutionEnvironment`].
+    pub fn scope<T>(self: &Arc<Self>, f: impl FnOnce() -> T) -> T {
+        execution::with_scoped(Arc::clone(self) as Arc<dyn ExecutionEnvironment>, f)
+    }
+}
+
+impl ExecutionEnvironment for VirtualEnvironment {
+    fn is_file(&self, path: &Path) -> bool {
+        self.files.lock().unwrap().contains_key(path)
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<OsString>> {
+        let files = self.files.lock().unwrap();
+        let mut res: Vec<OsString> = files
+            .keys()
+            .filter_map(|it| it.strip_prefix(path).ok()?.components().next())
+            .map(|it| it.as_os_str().to_os_string())
+            .collect();
+        if res.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "no such directory"));
+        }
+        res.sort();
+        res.dedup();
+        Ok(res)
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<FileMetadata> {
+        match self.files.lock().unwrap().get(path) {
+            Some(executable) => Ok(FileMetadata {
+                is_file: true,
+                is_executable: executable.is_some(),
+                len: 0,
+                modified: None,
+            }),
+            None => Err(io::Error::new(io::ErrorKind::NotFound, "no such file")),
+        }
+    }
+
+    /// Fake executables read as `#!` scripts, the other files as empty.
+    fn read_prefix(&self, path: &Path, len: usize) -> io::Result<Vec<u8>> {
+        match self.files.lock().unwrap().get(path) {
+            Some(Some(_)) => Ok(b"#!".iter().copied().take(len).collect()),
+            Some(None) => Ok(Vec::new()),
+            None => Err(io::Error::new(io::ErrorKind::NotFound, "no such file")),
+        }
+    }
+
+    fn output(&self, cmd: &mut Command) -> io::Result<Output> {
+        let program = PathBuf::from(cmd.get_program());
+        let args = cmd.get_args().map(|it| it.to_os_string()).collect();
+        self.runs.lock().unwrap().push((program.clone(), args));
+        let executable = match self.files.lock().unwrap().get(&program) {
+            Some(Some(it)) => it.clone(),
+            Some(None) => {
+                return Err(io::Error::new(io::ErrorKind::PermissionDenied, "not executable"))
+            }
+            None => return Err(io::Error::new(io::ErrorKind::NotFound, "no such file")),
+        };
+        thread::sleep(executable.delay);
+        Ok(Output {
+            status: exit_status(executable.exit_code),
+            stdout: executable.stdout.into_bytes(),
+            stderr: executable.stderr.into_bytes(),
+        })
+    }
+}
+
+#[cfg(unix)]
+fn exit_status(code: i32) -> ExitStatus {
+    use std::os::unix::process::ExitStatusExt;
+    ExitStatus::from_raw(code << 8)
+}
+
+#[cfg(windows)]
+fn exit_status(code: i32) -> ExitStatus {
+    use std::os::windows::process::ExitStatusExt;
+    ExitStatus::from_raw(code as u32)
+}
+
+/// A `PATH` of virtual directories, some of which contain `tool`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathLayout {
+    pub tool: String,
+    /// In `PATH` order, possibly with repeated entries.
+    pub dirs: Vec<PathBuf>,
+    /// The directories which contain `tool`.
+    pub with_tool: Vec<PathBuf>,
+    pub home: PathBuf,
+    /// Whether `~/.cargo/bin` contains `tool`.
+    pub in_cargo_bin: bool,
+}
+
+impl PathLayout {
+    /// A layout of up to six `PATH` entries out of a pool of four
+    /// directories, so that repeated entries are common.
+    pub fn generate(tool: &str, seed: u64) -> PathLayout {
+        let mut rng = Rng(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1);
+        let root = PathBuf::from(if cfg!(windows) { "C:\\virtual" } else { "/virtual" });
+        let pool: Vec<PathBuf> = (0..4).map(|i| root.join(format!("dir{}", i))).collect();
+        let with_tool = pool.iter().filter(|_| rng.below(3) == 0).cloned().collect();
+        let n_dirs = rng.below(7);
+        let dirs = (0..n_dirs).map(|_| pool[rng.below(4) as usize].clone()).collect();
+        PathLayout {
+            tool: tool.to_string(),
+            dirs,
+            with_tool,
+            home: root.join("home"),
+            in_cargo_bin: rng.below(2) == 0,
+        }
+    }
+
+    pub fn cargo_bin(&self) -> PathBuf {
+        self.home.join(".cargo").join("bin")
+    }
+
+    fn executable_name(&self) -> String {
+        format!("{}{}", self.tool, std::env::consts::EXE_SUFFIX)
+    }
+
+    /// The virtual machine with the layout's files, each tool printing
+    /// `<tool> 1.0.0`.
+    pub fn environment(&self) -> Arc<VirtualEnvironment> {
+        let res = VirtualEnvironment::default();
+        let cargo_bin = self.cargo_bin();
+        let dirs = self.with_tool.iter().chain(Some(&cargo_bin).filter(|_| self.in_cargo_bin));
+        for dir in dirs {
+            res.add_executable(
+                dir.join(self.executable_name()),
+                FakeExecutable::tool(&self.tool, "1.0.0"),
+            );
+        }
+        Arc::new(res)
+    }
+
+    /// The [`Env`] to discover in: the layout's `PATH` and home directory,
+    /// and nothing else.
+    pub fn env(&self) -> Env {
+        let mut res = Env::default();
+        let path = std::env::join_paths(&self.dirs).expect("virtual directories are valid in PATH");
+        res.set_var("PATH", path);
+        res.set_var(if cfg!(windows) { "USERPROFILE" } else { "HOME" }, self.home.clone().into());
+        res
+    }
+
+    /// Where discovery should find the tool: the first `PATH` entry which
+    /// has it, then `~/.cargo/bin`.
+    pub fn expected(&self) -> Option<PathBuf> {
+        let dir = self.dirs.iter().find(|it| self.with_tool.contains(it)).cloned();
+        let dir = dir.or_else(|| Some(self.cargo_bin()).filter(|_| self.in_cargo_bin))?;
+        Some(dir.join(self.executable_name()))
+    }
+}
+
+/// Runs `check` on the layouts generated from the seeds `0..cases`,
+/// reporting the seed of the first one which panics.
+pub fn check_layouts(tool: &str, cases: u64, check: impl Fn(&PathLayout)) {
+    for seed in 0..cases {
+        let layout = PathLayout::generate(tool, seed);
+        let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| check(&layout)));
+        if let Err(err) = res {
+            eprintln!("failed for seed {}: {:#?}", seed, layout);
+            std::panic::resume_unwind(err);
+        }
+    }
+}
+
+/// xorshift64*, good enough to shuffle layouts around.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 32
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        locate_in, validate_executable, ErrorKind, Expectations, Fingerprint, Provenance,
+        Validation,
+    };
+
+    use super::*;
+
+    #[test]
+    fn discovery_picks_first_candidate() {
+        check_layouts("cargo", 200, |layout| {
+            let env = layout.environment();
+            let res = env.scope(|| locate_in(&layout.env(), "cargo"));
+            match layout.expected() {
+                Some(expected) => {
+                    let found = res.unwrap();
+                    assert_eq!(found.path, expected);
+                    let provenance = if layout.dirs.iter().any(|it| expected.starts_with(it)) {
+                        Provenance::Path
+                    } else {
+                        Provenance::CargoBin
+                    };
+                    assert_eq!(found.provenance, provenance);
+                }
+                None => assert_eq!(res.unwrap_err().kind(), ErrorKind::NotFound),
+            }
+            // Nothing is run to find a tool in `PATH`.
+            assert!(env.runs().is_empty());
+        });
+    }
+
+    #[test]
+    fn fake_executables_are_validated() {
+        let env = Arc::new(VirtualEnvironment::default());
+        let cargo = PathBuf::from("/virtual/bin/cargo");
+        env.add_executable(&cargo, FakeExecutable::tool("cargo", "1.52.0"));
+        let outcome = env.scope(|| validate_executable(&cargo, &Expectations::new("cargo")));
+        assert_eq!(outcome.version.unwrap().to_string(), "1.52.0");
+        assert_eq!(env.runs(), [(cargo.clone(), vec![OsString::from("--version")])]);
+
+        let broken = FakeExecutable {
+            exit_code: 101,
+            stderr: "oops".to_string(),
+            ..FakeExecutable::default()
+        };
+        env.add_executable(&cargo, broken);
+        let outcome = env.scope(|| validate_executable(&cargo, &Expectations::new("cargo")));
+        assert_eq!(outcome.error.unwrap().kind(), ErrorKind::BadExitStatus);
+    }
+
+    #[test]
+    fn files_are_checked_in_the_environment() {
+        let env = Arc::new(VirtualEnvironment::default());
+        let cargo = PathBuf::from("/virtual/bin/cargo");
+        let readme = PathBuf::from("/virtual/bin/README");
+        env.add_executable(&cargo, FakeExecutable::default());
+        env.add_file(&readme);
+        let validate = |path: &Path, validation| {
+            let expectations =
+                Expectations { validation: Some(validation), ..Expectations::new("cargo") };
+            env.scope(|| validate_executable(path, &expectations)).error.map(|it| it.kind())
+        };
+        assert_eq!(validate(&cargo, Validation::MetadataOnly), None);
+        assert_eq!(validate(&cargo, Validation::Header), None);
+        assert_eq!(validate(&readme, Validation::MetadataOnly), Some(ErrorKind::SpawnFailed));
+        assert!(env.runs().is_empty());
+
+        let fingerprint = env.scope(|| Fingerprint::of(&cargo)).unwrap();
+        assert_eq!(fingerprint.path(), cargo);
+    }
+}
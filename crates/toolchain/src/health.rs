@@ -0,0 +1,165 @@
+//! Checks that the discovered toolchain works beyond `--version`, by using it
+//! on a throwaway project.
+
+use std::{env, fmt, fs, io, path::Path, process};
+
+use crate::{
+    missing_prerequisite, registry_state, stale_toolchains, toolchain_usage, DiscoveryError, Env,
+    ErrorKind, Prerequisite, Profile, RegistryState, StaleToolchains, TargetDir, ToolCommand,
+};
+
+/// The outcome of [`health_check`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HealthReport {
+    pub checks: Vec<HealthCheck>,
+    /// For the working directory. Being offline doesn't make the toolchain
+    /// unhealthy, so this isn't one of the `checks`.
+    pub registry: Option<RegistryState>,
+    pub stale_toolchains: Option<StaleToolchains>,
+    /// The platform tool the `linker` check found missing.
+    pub missing_prerequisite: Option<Prerequisite>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HealthCheck {
+    pub name: &'static str,
+    /// `None` if the check passed.
+    pub error: Option<DiscoveryError>,
+}
+
+impl HealthReport {
+    pub fn is_healthy(&self) -> bool {
+        self.checks.iter().all(|it| it.error.is_none())
+    }
+}
+
+impl fmt::Display for HealthReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for check in &self.checks {
+            match &check.error {
+                Some(err) => writeln!(f, "{}: {}", check.name, err)?,
+                None => writeln!(f, "{}: ok", check.name)?,
+            }
+        }
+        if let Some(prerequisite) = self.missing_prerequisite {
+            for suggestion in prerequisite.install_suggestion() {
+                writeln!(f, "  {}", suggestion)?;
+            }
+        }
+        if let Some(registry) = &self.registry {
+            writeln!(f, "registry: {}", registry)?;
+        }
+        if let Some(stale) = &self.stale_toolchains {
+            writeln!(f, "disk usage: {}", stale)?;
+            for suggestion in stale.suggestions() {
+                writeln!(f, "  {}", suggestion)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Runs `cargo metadata` on, and builds, a hello world project in a temporary
+/// directory, and checks that `CARGO_HOME` is writable and that the platform
+/// linker is installed, see [`Prerequisite`]. Also classifies the
+/// registry access of the working directory, see [`registry_state`], and
+/// looks for [`StaleToolchains`].
+///
+/// This catches what only shows up once a build runs, like a missing linker
+/// or a sysroot the compiler can't use. It builds, so it takes a while and
+/// shouldn't run on startup; the network is only used to check crates.io.
+pub fn health_check() -> HealthReport {
+    let mut checks = Vec::new();
+    let env = Env::current();
+    let cargo = crate::cargo();
+
+    let cargo_home = env.cargo_home().map(|path| TargetDir { path, var: "CARGO_HOME" });
+    if let (Some(cargo_home), Some(cwd)) = (cargo_home, env.cwd()) {
+        let error = cargo_home.is_read_only(cwd).then(|| {
+            DiscoveryError::new(ErrorKind::NotWritable, "cargo")
+                .with_path(cargo_home.path)
+                .with_var("CARGO_HOME".to_string())
+        });
+        checks.push(HealthCheck { name: "CARGO_HOME", error });
+    }
+
+    // Checked before building, which fails with a less helpful error.
+    let prerequisite = missing_prerequisite(&env);
+    let error = prerequisite.map(|it| {
+        DiscoveryError::new(ErrorKind::MissingPrerequisite, "rustc").with_detail(it.to_string())
+    });
+    checks.push(HealthCheck { name: "linker", error });
+
+    let dir = env::temp_dir().join(format!("ra-health-check-{}", process::id()));
+    match create_project(&dir) {
+        Ok(()) => {
+            let metadata = run_cargo(
+                &cargo,
+                &dir,
+                Profile::Metadata,
+                &["metadata", "--format-version", "1", "--no-deps", "--offline"],
+            );
+            let failed = metadata.is_err();
+            checks.push(HealthCheck { name: "cargo metadata", error: metadata.err() });
+            // Building can't work if cargo doesn't understand the project.
+            if !failed {
+                let build = run_cargo(&cargo, &dir, Profile::Check, &["build", "--offline"]);
+                checks.push(HealthCheck { name: "build", error: build.err() });
+            }
+        }
+        Err(err) => {
+            let error = DiscoveryError::new(ErrorKind::NotWritable, "cargo")
+                .with_path(dir.clone())
+                .with_detail(err.to_string());
+            checks.push(HealthCheck { name: "temporary project", error: Some(error) });
+        }
+    }
+    let _ = fs::remove_dir_all(&dir);
+
+    let registry = env.cwd().map(|it| registry_state(it));
+    let stale_toolchains = stale_toolchains(&toolchain_usage(&env));
+    HealthReport { checks, registry, stale_toolchains, missing_prerequisite: prerequisite }
+}
+
+fn create_project(dir: &Path) -> io::Result<()> {
+    let _ = fs::remove_dir_all(dir);
+    fs::create_dir_all(dir.join("src"))?;
+    fs::write(
+        dir.join("Cargo.toml"),
+        "[package]\nname = \"health-check\"\nversion = \"0.0.0\"\nedition = \"2018\"\n\n[workspace]\n",
+    )?;
+    fs::write(dir.join("src/main.rs"), "fn main() {}\n")
+}
+
+fn run_cargo(
+    cargo: &Path,
+    dir: &Path,
+    profile: Profile,
+    args: &[&str],
+) -> Result<(), DiscoveryError> {
+    let error = |kind| DiscoveryError::new(kind, "cargo").with_path(cargo.to_path_buf());
+    let mut cmd = ToolCommand::with_profile(cargo.to_path_buf(), profile);
+    cmd.args(args).current_dir(dir).env("CARGO_TARGET_DIR", dir.join("target"));
+    let output =
+        cmd.output().map_err(|err| error(ErrorKind::SpawnFailed).with_detail(err.to_string()))?;
+    if output.status.success() {
+        return Ok(());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+    let detail = if stderr.is_empty() { output.status.to_string() } else { stderr };
+    Err(error(ErrorKind::BadExitStatus).with_detail(detail))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[ignore = "builds a crate, which needs a linker and takes a while"]
+    fn current_toolchain_is_healthy() {
+        let report = health_check();
+        assert!(report.is_healthy(), "{}", report);
+        let names: Vec<_> = report.checks.iter().map(|it| it.name).collect();
+        assert_eq!(&names[names.len() - 2..], ["cargo metadata", "build"]);
+    }
+}
@@ -0,0 +1,374 @@
+//! Installing missing tools: the commands the user can run, and the rustup
+//! invocations we run ourselves.
+
+use std::{env, ffi::OsString, fmt, fs, sync::Mutex};
+
+use once_cell::sync::Lazy;
+use stdx::split_once;
+
+use crate::{DiscoveryError, Env, ErrorKind, ToolCommand};
+
+/// A command which fixes a missing tool, for the user to copy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SuggestedCommand {
+    pub description: String,
+    pub command: String,
+}
+
+impl fmt::Display for SuggestedCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: `{}`", self.description, self.command)
+    }
+}
+
+/// Ways to install `tool` on the current platform, the preferred one first.
+/// Empty if we know of none.
+///
+/// `tool` is an executable name like `cargo` or `rustfmt`, or `rust-src` for
+/// the standard library sources.
+pub fn install_suggestion(tool: &str) -> Vec<SuggestedCommand> {
+    suggestions_for(tool, &Platform::current())
+}
+
+/// Where rustup downloads from, for networks which can't reach
+/// `static.rust-lang.org`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Mirrors {
+    /// Toolchains and components, `RUSTUP_DIST_SERVER`.
+    pub dist_server: Option<String>,
+    /// Rustup itself, `RUSTUP_UPDATE_ROOT`.
+    pub update_root: Option<String>,
+}
+
+impl Mirrors {
+    /// The mirrors set through the environment.
+    pub fn from_env(env: &Env) -> Mirrors {
+        let var = |name| env.var(name).filter(|it| !it.is_empty()).map(str::to_string);
+        Mirrors { dist_server: var("RUSTUP_DIST_SERVER"), update_root: var("RUSTUP_UPDATE_ROOT") }
+    }
+
+    /// `self`, with the unset mirrors taken from `other`.
+    pub fn or(self, other: Mirrors) -> Mirrors {
+        Mirrors {
+            dist_server: self.dist_server.or(other.dist_server),
+            update_root: self.update_root.or(other.update_root),
+        }
+    }
+
+    fn apply(&self, cmd: &mut ToolCommand) {
+        let vars =
+            [("RUSTUP_DIST_SERVER", &self.dist_server), ("RUSTUP_UPDATE_ROOT", &self.update_root)];
+        for (var, value) in vars.iter() {
+            if let Some(value) = value {
+                cmd.apply_policy(var, Some(OsString::from(value)), "mirror configuration".into());
+            }
+        }
+    }
+}
+
+/// The HTTP(S) proxy for downloads. Unset fields fall back to the usual
+/// environment variables, which is also where rustup looks.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Proxy {
+    /// `HTTP_PROXY`.
+    pub http: Option<String>,
+    /// `HTTPS_PROXY`.
+    pub https: Option<String>,
+    /// `NO_PROXY`: comma separated hosts to connect to directly.
+    pub no_proxy: Option<String>,
+}
+
+impl Proxy {
+    /// The proxy set through the environment. The lowercase variables win, as
+    /// they do for curl.
+    pub fn from_env(env: &Env) -> Proxy {
+        let var = |name: &str| {
+            let value = env.var(&name.to_ascii_lowercase()).or_else(|| env.var(name))?;
+            Some(value.to_string()).filter(|it| !it.is_empty())
+        };
+        Proxy { http: var("HTTP_PROXY"), https: var("HTTPS_PROXY"), no_proxy: var("NO_PROXY") }
+    }
+
+    /// `self`, with the unset fields taken from `other`.
+    pub fn or(self, other: Proxy) -> Proxy {
+        Proxy {
+            http: self.http.or(other.http),
+            https: self.https.or(other.https),
+            no_proxy: self.no_proxy.or(other.no_proxy),
+        }
+    }
+
+    /// Sets both spellings of each variable, as tools disagree on which one
+    /// they read.
+    fn apply(&self, cmd: &mut ToolCommand) {
+        let vars = [
+            ("HTTP_PROXY", &self.http),
+            ("HTTPS_PROXY", &self.https),
+            ("NO_PROXY", &self.no_proxy),
+        ];
+        for (var, value) in vars.iter() {
+            if let Some(value) = value {
+                let value = OsString::from(value);
+                cmd.apply_policy(var, Some(value.clone()), "proxy configuration".into());
+                if !cfg!(windows) {
+                    let lower = var.to_ascii_lowercase();
+                    cmd.apply_policy(&lower, Some(value), "proxy configuration".into());
+                }
+            }
+        }
+    }
+}
+
+static MIRRORS: Lazy<Mutex<Mirrors>> = Lazy::new(Default::default);
+static PROXY: Lazy<Mutex<Proxy>> = Lazy::new(Default::default);
+
+/// Sets the mirrors used by the installs we run. They take precedence over
+/// the environment, which is used for the mirrors left unset.
+pub fn set_mirrors(mirrors: Mirrors) {
+    *MIRRORS.lock().unwrap() = mirrors;
+}
+
+/// The mirrors in effect, see [`set_mirrors`].
+pub fn mirrors() -> Mirrors {
+    MIRRORS.lock().unwrap().clone().or(Mirrors::from_env(&Env::current()))
+}
+
+/// Sets the proxy used by the installs we run, see [`Proxy`].
+pub fn set_proxy(proxy: Proxy) {
+    *PROXY.lock().unwrap() = proxy;
+}
+
+/// The proxy in effect, see [`set_proxy`].
+pub fn proxy() -> Proxy {
+    PROXY.lock().unwrap().clone().or(Proxy::from_env(&Env::current()))
+}
+
+/// `rustup component add <component>`, downloading from the configured
+/// [`mirrors`] through the configured [`proxy`].
+pub fn rustup_component_add(component: &str) -> ToolCommand {
+    let mut res = ToolCommand::new(crate::rustup());
+    res.args(["component", "add", component]);
+    mirrors().apply(&mut res);
+    proxy().apply(&mut res);
+    res
+}
+
+/// Runs an install like [`rustup_component_add`] for `tool`. Downloads which
+/// fail at the proxy are reported as [`ErrorKind::ProxyFailed`], downloads
+/// which can't reach the server as [`ErrorKind::ServerUnreachable`].
+pub fn run_install(tool: &str, cmd: &ToolCommand) -> Result<(), DiscoveryError> {
+    let error = |kind| DiscoveryError::new(kind, tool).with_path(cmd.program().to_path_buf());
+    let output =
+        cmd.output().map_err(|err| error(ErrorKind::SpawnFailed).with_detail(err.to_string()))?;
+    if output.status.success() {
+        return Ok(());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+    let kind = network_failure(&stderr).unwrap_or(ErrorKind::BadExitStatus);
+    let detail = if stderr.is_empty() { output.status.to_string() } else { stderr };
+    Err(error(kind).with_detail(detail))
+}
+
+/// Recognizes the errors of curl and reqwest, the download backends of cargo
+/// and rustup, by their messages: a crate named `proxy` is not a proxy
+/// failure.
+pub(crate) fn network_failure(stderr: &str) -> Option<ErrorKind> {
+    const PROXY: &[&str] = &[
+        "received http code 407 from proxy",
+        "couldn't resolve proxy name",
+        "proxy authentication required",
+        "connect tunnel failed",
+        "unsuccessful tunnel",
+    ];
+    const UNREACHABLE: &[&str] = &[
+        "couldn't resolve host name",
+        "could not resolve host",
+        "couldn't connect to server",
+        "failed to connect to",
+        "connection refused",
+        "connection reset",
+        "timeout was reached",
+        "operation timed out",
+        "dns error",
+        "network is unreachable",
+        "error sending request for url",
+        "spurious network error",
+    ];
+    let stderr = stderr.to_ascii_lowercase();
+    if PROXY.iter().any(|it| stderr.contains(it)) {
+        return Some(ErrorKind::ProxyFailed);
+    }
+    if UNREACHABLE.iter().any(|it| stderr.contains(it)) {
+        return Some(ErrorKind::ServerUnreachable);
+    }
+    None
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Platform {
+    Linux { distro: Vec<String> },
+    MacOs,
+    Windows,
+    Other,
+}
+
+impl Platform {
+    pub(crate) fn current() -> Platform {
+        match env::consts::OS {
+            "linux" => {
+                let os_release = fs::read_to_string("/etc/os-release").unwrap_or_default();
+                Platform::Linux { distro: distro_ids(&os_release) }
+            }
+            "macos" => Platform::MacOs,
+            "windows" => Platform::Windows,
+            _ => Platform::Other,
+        }
+    }
+}
+
+/// `ID` followed by `ID_LIKE` from `os-release`, so that derivatives get the
+/// commands of the distribution they are based on.
+fn distro_ids(os_release: &str) -> Vec<String> {
+    let mut res = Vec::new();
+    for &key in &["ID", "ID_LIKE"] {
+        let value = os_release.lines().find_map(|line| {
+            let (k, v) = split_once(line, '=')?;
+            (k.trim() == key).then(|| v.trim().trim_matches('"').to_string())
+        });
+        res.extend(value.iter().flat_map(|it| it.split_whitespace()).map(str::to_string));
+    }
+    res
+}
+
+fn suggestions_for(tool: &str, platform: &Platform) -> Vec<SuggestedCommand> {
+    let mut res = Vec::new();
+    match tool {
+        "rust-src" | "rustfmt" | "rust-analyzer" | "llvm-tools-preview" => {
+            push(&mut res, "Install the rustup component", format!("rustup component add {}", tool))
+        }
+        "cargo-clippy" | "clippy-driver" => {
+            push(&mut res, "Install the rustup component", "rustup component add clippy".into())
+        }
+        "cargo-miri" => push(
+            &mut res,
+            "Install miri for the nightly toolchain",
+            "rustup +nightly component add miri".into(),
+        ),
+        "cargo-nextest" => push(
+            &mut res,
+            "Install cargo-nextest from crates.io",
+            "cargo install cargo-nextest --locked".into(),
+        ),
+        "cargo" | "rustc" | "rustup" | "rustdoc" => install_rustup(&mut res, platform),
+        _ => (),
+    }
+    res
+}
+
+fn install_rustup(acc: &mut Vec<SuggestedCommand>, platform: &Platform) {
+    match platform {
+        Platform::MacOs => push(
+            acc,
+            "Install rustup with Homebrew",
+            "brew install rustup-init && rustup-init".into(),
+        ),
+        Platform::Windows => {
+            push(acc, "Install rustup with winget", "winget install Rustlang.Rustup".into())
+        }
+        Platform::Linux { distro } => {
+            let package_manager = distro.iter().find_map(|id| match id.as_str() {
+                "debian" | "ubuntu" => Some("sudo apt install rustup"),
+                "fedora" | "rhel" => Some("sudo dnf install rustup"),
+                "arch" => Some("sudo pacman -S rustup"),
+                "opensuse" | "suse" => Some("sudo zypper install rustup"),
+                "alpine" => Some("sudo apk add rustup"),
+                _ => None,
+            });
+            if let Some(command) = package_manager {
+                push(acc, "Install rustup from the distribution packages", command.into());
+            }
+        }
+        Platform::Other => (),
+    }
+    if *platform != Platform::Windows {
+        push(
+            acc,
+            "Install rustup with the official installer",
+            "curl --proto '=https' --tlsv1.2 -sSf https://sh.rustup.rs | sh".into(),
+        );
+    }
+}
+
+pub(crate) fn push(acc: &mut Vec<SuggestedCommand>, description: &str, command: String) {
+    acc.push(SuggestedCommand { description: description.to_string(), command })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn commands(tool: &str, platform: &Platform) -> Vec<String> {
+        suggestions_for(tool, platform).into_iter().map(|it| it.command).collect()
+    }
+
+    #[test]
+    fn suggestions_depend_on_platform() {
+        assert_eq!(commands("rust-src", &Platform::Windows), ["rustup component add rust-src"]);
+        assert_eq!(commands("cargo", &Platform::Windows), ["winget install Rustlang.Rustup"]);
+        assert_eq!(
+            commands("rustc", &Platform::MacOs)[0],
+            "brew install rustup-init && rustup-init"
+        );
+
+        let mint = distro_ids("NAME=\"Linux Mint\"\nID=linuxmint\nID_LIKE=\"ubuntu debian\"\n");
+        assert_eq!(mint, ["linuxmint", "ubuntu", "debian"]);
+        let linux = Platform::Linux { distro: mint };
+        assert_eq!(commands("cargo", &linux)[0], "sudo apt install rustup");
+        assert_eq!(commands("cargo", &linux).len(), 2);
+
+        assert!(commands("some-tool", &linux).is_empty());
+    }
+
+    #[test]
+    fn configured_mirrors_win_over_environment() {
+        let mut env = Env::default();
+        env.set_var("RUSTUP_DIST_SERVER", "https://env.example".into());
+        env.set_var("RUSTUP_UPDATE_ROOT", "https://env.example/rustup".into());
+        let configured =
+            Mirrors { dist_server: Some("https://corp.example".into()), update_root: None };
+        let mirrors = configured.or(Mirrors::from_env(&env));
+        assert_eq!(mirrors.dist_server.as_deref(), Some("https://corp.example"));
+        assert_eq!(mirrors.update_root.as_deref(), Some("https://env.example/rustup"));
+
+        let mut cmd = ToolCommand::new("rustup".into());
+        mirrors.apply(&mut cmd);
+        let vars: Vec<_> = cmd.audit().iter().map(|it| it.var.as_str()).collect();
+        assert_eq!(vars, ["RUSTUP_DIST_SERVER", "RUSTUP_UPDATE_ROOT"]);
+    }
+
+    #[test]
+    fn proxy_comes_from_either_spelling() {
+        let mut env = Env::default();
+        env.set_var("https_proxy", "http://lower:3128".into());
+        env.set_var("HTTPS_PROXY", "http://upper:3128".into());
+        env.set_var("NO_PROXY", "localhost".into());
+        let proxy = Proxy::from_env(&env);
+        if !cfg!(windows) {
+            assert_eq!(proxy.https.as_deref(), Some("http://lower:3128"));
+        }
+        assert_eq!(proxy.http, None);
+        assert_eq!(proxy.no_proxy.as_deref(), Some("localhost"));
+    }
+
+    #[test]
+    fn proxy_failures_are_told_apart() {
+        let proxy = "error: could not download file from 'https://static.rust-lang.org/dist/channel-rust-stable.toml.sha256'\n\
+                     info: caused by: Received HTTP code 407 from proxy after CONNECT";
+        assert_eq!(network_failure(proxy), Some(ErrorKind::ProxyFailed));
+        let unreachable = "info: caused by: [6] Couldn't resolve host name (Could not resolve host: static.rust-lang.org)";
+        assert_eq!(network_failure(unreachable), Some(ErrorKind::ServerUnreachable));
+        assert_eq!(network_failure("error: toolchain 'foo' is not installed"), None);
+        let resolution = "error: failed to select a version for the requirement `proxy = \"^407\"`";
+        assert_eq!(network_failure(resolution), None);
+    }
+}
@@ -0,0 +1,164 @@
+//! Running scripts which the platform can't run on its own.
+//!
+//! `$CARGO` or `$RUSTC` may point to a wrapper script. On Windows only
+//! `.exe`, `.com`, `.bat` and `.cmd` files can be spawned, so a `.sh` or
+//! `.py` script needs its interpreter spelled out; on Unix the same goes for
+//! a script without the executable bit. The interpreter comes from the `#!`
+//! line if there is one, and from the extension otherwise.
+
+use std::{
+    fs,
+    io::{BufRead, BufReader, Read},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Interpreter {
+    pub(crate) program: PathBuf,
+    /// Arguments which go before the script.
+    pub(crate) args: Vec<String>,
+}
+
+/// A command running `path`, through its interpreter if it needs one, with
+/// the variables from [`crate::set_client_env`] on top of the process
+/// environment. Every spawn of a tool starts here, so that none of them runs
+/// in the environment the server was started with only.
+pub(crate) fn command(path: &Path) -> Command {
+    let mut res = match interpreter_for(path) {
+        Some(interpreter) => {
+            let mut cmd = Command::new(interpreter.program);
+            cmd.args(interpreter.args).arg(path);
+            cmd
+        }
+        None => Command::new(path),
+    };
+    res.envs(crate::env::client_env());
+    res
+}
+
+/// The interpreter `path` has to be run with, `None` if it can be spawned
+/// as is, or if we don't know how to run it.
+pub(crate) fn interpreter_for(path: &Path) -> Option<Interpreter> {
+    if !needs_interpreter(path) {
+        return None;
+    }
+    let file = fs::File::open(path).ok()?;
+    let mut first_line = String::new();
+    // Not text, so there is no `#!` line.
+    if BufReader::new(file.take(1024)).read_line(&mut first_line).is_err() {
+        first_line.clear();
+    }
+    let extension = path.extension().and_then(|it| it.to_str());
+    interpreter_from(&first_line, extension, cfg!(windows))
+}
+
+#[cfg(windows)]
+fn needs_interpreter(path: &Path) -> bool {
+    let extension = path.extension().and_then(|it| it.to_str()).map(str::to_ascii_lowercase);
+    !matches!(extension.as_deref(), Some("exe") | Some("com") | Some("bat") | Some("cmd"))
+        && path.is_file()
+}
+
+#[cfg(not(windows))]
+fn needs_interpreter(path: &Path) -> bool {
+    match fs::metadata(path) {
+        Ok(metadata) => metadata.is_file() && !crate::validate::is_executable(&metadata),
+        Err(_) => false,
+    }
+}
+
+fn interpreter_from(
+    first_line: &str,
+    extension: Option<&str>,
+    windows: bool,
+) -> Option<Interpreter> {
+    if let Some(shebang) = first_line.strip_prefix("#!") {
+        let mut words = shebang.split_whitespace();
+        let program = words.next()?;
+        let mut args: Vec<String> = words.map(str::to_string).collect();
+        if !windows {
+            return Some(Interpreter { program: program.into(), args });
+        }
+        // `/usr/bin/env` and `/bin/sh` don't exist on Windows, the
+        // interpreter has to be found in `PATH`.
+        let mut program = Path::new(program).file_stem()?.to_string_lossy().into_owned();
+        if program == "env" {
+            args.retain(|it| it != "-S");
+            if args.is_empty() {
+                return None;
+            }
+            program = args.remove(0);
+        }
+        return Some(Interpreter { program: program.into(), args });
+    }
+
+    let (program, args): (&str, &[&str]) = match extension?.to_ascii_lowercase().as_str() {
+        "sh" => ("sh", &[]),
+        "bash" => ("bash", &[]),
+        "py" if windows => ("python", &[]),
+        "py" => ("python3", &[]),
+        "ps1" if windows => ("powershell", &["-NoProfile", "-ExecutionPolicy", "Bypass", "-File"]),
+        "ps1" => ("pwsh", &["-NoProfile", "-File"]),
+        _ => return None,
+    };
+    Some(Interpreter {
+        program: program.into(),
+        args: args.iter().map(|it| it.to_string()).collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(first_line: &str, extension: Option<&str>, windows: bool) -> Option<String> {
+        let it = interpreter_from(first_line, extension, windows)?;
+        let mut res = it.program.display().to_string();
+        for arg in it.args {
+            res.push(' ');
+            res.push_str(&arg);
+        }
+        Some(res)
+    }
+
+    #[test]
+    fn interpreter_comes_from_shebang() {
+        assert_eq!(check("#!/bin/sh\n", Some("sh"), false).as_deref(), Some("/bin/sh"));
+        assert_eq!(check("#!/bin/bash -e\n", None, true).as_deref(), Some("bash -e"));
+        assert_eq!(
+            check("#!/usr/bin/env -S python3 -u\n", Some("py"), true).as_deref(),
+            Some("python3 -u")
+        );
+        assert_eq!(
+            check("#!/usr/bin/env python3\n", None, false).as_deref(),
+            Some("/usr/bin/env python3")
+        );
+        assert_eq!(check("#!/usr/bin/env\n", None, true), None);
+    }
+
+    #[test]
+    fn interpreter_comes_from_extension() {
+        assert_eq!(check("exec cargo \"$@\"\n", Some("sh"), true).as_deref(), Some("sh"));
+        assert_eq!(check("", Some("PY"), true).as_deref(), Some("python"));
+        assert_eq!(check("", Some("py"), false).as_deref(), Some("python3"));
+        assert_eq!(
+            check("", Some("ps1"), true).as_deref(),
+            Some("powershell -NoProfile -ExecutionPolicy Bypass -File")
+        );
+        assert_eq!(check("@echo off\n", Some("cmd"), false), None);
+        assert_eq!(check("", None, true), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn scripts_without_executable_bit_run_through_interpreter() {
+        let tmp = crate::testdir::TestDir::new("interpreter");
+        let path = tmp.path().join("cargo.sh");
+        fs::write(&path, "#!/bin/sh\necho cargo 1.52.0\n").unwrap();
+
+        let output = command(&path).output().unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "cargo 1.52.0");
+        assert_eq!(interpreter_for(Path::new("/surely/not/a/script.sh")), None);
+    }
+}
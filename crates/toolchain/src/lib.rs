@@ -1,66 +1,169 @@
 //! Discovery of `cargo` & `rustc` executables.
-use std::{env, iter, path::PathBuf};
 
+mod app_alias;
+mod build_target;
+mod capabilities;
+mod cargo_config;
+mod cargo_registry;
+mod cfg;
+mod command;
+mod direnv;
+mod disk_usage;
+mod dotenv;
+mod env;
+mod error;
+mod execution;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+mod fingerprint;
+#[cfg(any(test, feature = "test-harness"))]
+pub mod harness;
+mod health;
+mod install;
+mod interpreter;
+mod locate;
+mod login_shell;
+mod messages;
+mod miri;
+mod monitor;
+mod names;
+mod nextest;
+mod path_index;
+mod pin;
+mod prefix;
+mod prerequisites;
+mod recursion;
+mod registry;
+mod repair;
+mod revalidate;
+mod rust_src;
+mod rustc_meta;
+mod rustup_health;
+mod rustup_proxy;
+mod setup_script;
+mod shared;
+mod shim;
+mod slow_fs;
+mod status;
+mod sysroot;
+mod target_list;
+#[cfg(test)]
+mod testdir;
+mod timings;
+mod toolchain;
+mod toolchain_spec;
+mod tool_kind;
+mod user_config;
+mod validate;
+mod version;
+mod wasm;
+mod workspace;
+mod wrapper;
+
+use std::{
+    io,
+    path::PathBuf,
+    process::{Command, Output},
+};
+
+pub use crate::{
+    build_target::{build_target, BuildTarget, TargetSource},
+    capabilities::{ensure_supports, Capabilities, Capability},
+    cargo_registry::{registry_state, RegistryState},
+    cfg::{rustc_cfgs, CfgAtom},
+    command::{set_augment_path, EnvChange, Profile, RustcBootstrap, ToolCommand},
+    direnv::direnv_export,
+    disk_usage::{
+        stale_toolchains, toolchain_usage, ComponentUsage, StaleToolchains, ToolchainUsage,
+    },
+    dotenv::load_dotenv,
+    env::{set_client_env, Env},
+    error::{DiscoveryError, ErrorKind},
+    execution::{set_execution_environment, ExecutionEnvironment, FileMetadata, LocalEnvironment},
+    fingerprint::Fingerprint,
+    health::{health_check, HealthCheck, HealthReport},
+    install::{
+        install_suggestion, mirrors, proxy, run_install, rustup_component_add, set_mirrors,
+        set_proxy, Mirrors, Proxy, SuggestedCommand,
+    },
+    locate::{locate, locate_in, FoundExecutable, Provenance},
+    login_shell::{login_shell, LoginShell, ShellKind},
+    messages::MessageCatalog,
+    miri::{miri_status, MiriStatus},
+    monitor::{ToolchainEvent, ToolchainMonitor},
+    names::{canonical_name, tool_names},
+    nextest::{nextest, Nextest},
+    pin::{is_pin_file, toolchain_pin, ToolchainPin, PIN_FILE_NAMES},
+    prefix::{set_command_prefix, CommandPrefix},
+    prerequisites::{missing_prerequisite, Prerequisite},
+    registry::{
+        discovered_tools, discovery_metrics, invalidate_tools, try_locate, DiscoveryMetrics,
+    },
+    repair::{repair_suggestions, Repair, RepairSuggestion},
+    revalidate::Revalidator,
+    rust_src::{rust_src, rust_src_for, rust_src_from_env},
+    rustc_meta::{rustc_meta, rustc_meta_in, RustcMeta},
+    rustup_health::{diagnose_rustup, RustupDiagnosis},
+    rustup_proxy::set_bypass_rustup_proxies,
+    setup_script::run_setup_script,
+    shared::{SharedToolchains, ToolchainId},
+    shim::{Shim, ShimKind},
+    slow_fs::{mark_slow_dir, set_stat_timeout, slow_dirs},
+    status::{discover_all, ToolStatus},
+    sysroot::{is_target_installed, sysroot},
+    target_list::target_list,
+    timings::{discovery_timings, CandidateTiming, DiscoveryTimings, Skipped, ToolTiming},
+    tool_kind::{Location, ToolKind},
+    toolchain::{PartialToolchain, TargetDir, Toolchain},
+    toolchain_spec::ToolchainSpec,
+    user_config::UserConfig,
+    validate::{
+        set_default_validation, set_validation, validate_executable, Expectations, Validation,
+        ValidationOutcome,
+    },
+    version::Version,
+    wasm::{wasm_readiness, WasmReadiness},
+    workspace::locate_workspace_root,
+    wrapper::RustcWrappers,
+};
+
+// Each tool is discovered on the first call, until `invalidate_tools`.
 pub fn cargo() -> PathBuf {
-    get_path_for_executable("cargo")
+    registry::path_for("cargo")
 }
 
 pub fn rustc() -> PathBuf {
-    get_path_for_executable("rustc")
+    registry::path_for("rustc")
 }
 
 pub fn rustup() -> PathBuf {
-    get_path_for_executable("rustup")
+    registry::path_for("rustup")
 }
 
 pub fn rustfmt() -> PathBuf {
-    get_path_for_executable("rustfmt")
-}
-
-/// Return a `PathBuf` to use for the given executable.
-///
-/// E.g., `get_path_for_executable("cargo")` may return just `cargo` if that
-/// gives a valid Cargo executable; or it may return a full path to a valid
-/// Cargo.
-fn get_path_for_executable(executable_name: &'static str) -> PathBuf {
-    // The current implementation checks three places for an executable to use:
-    // 1) Appropriate environment variable (erroring if this is set but not a usable executable)
-    //      example: for cargo, this checks $CARGO environment variable; for rustc, $RUSTC; etc
-    // 2) `<executable_name>`
-    //      example: for cargo, this tries just `cargo`, which will succeed if `cargo` is on the $PATH
-    // 3) `~/.cargo/bin/<executable_name>`
-    //      example: for cargo, this tries ~/.cargo/bin/cargo
-    //      It seems that this is a reasonable place to try for cargo, rustc, and rustup
-    let env_var = executable_name.to_ascii_uppercase();
-    if let Some(path) = env::var_os(&env_var) {
-        return path.into();
-    }
-
-    if lookup_in_path(executable_name) {
-        return executable_name.into();
-    }
-
-    if let Some(mut path) = home::home_dir() {
-        path.push(".cargo");
-        path.push("bin");
-        path.push(executable_name);
-        if let Some(path) = probe(path) {
-            return path;
-        }
-    }
-
-    executable_name.into()
+    registry::path_for("rustfmt")
 }
 
-fn lookup_in_path(exec: &str) -> bool {
-    let paths = env::var_os("PATH").unwrap_or_default();
-    env::split_paths(&paths).map(|path| path.join(exec)).find_map(probe).is_some()
+/// Runs `cmd` in the [`ExecutionEnvironment`] and returns its trimmed
+/// stdout, turning a non-zero exit status into an error.
+fn utf8_stdout(mut cmd: Command) -> io::Result<String> {
+    registry::count_spawn();
+    let output = execution::execution_environment().output(&mut cmd)?;
+    check_status(&cmd, &output)?;
+    let stdout = String::from_utf8(output.stdout)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    Ok(stdout.trim().to_string())
 }
 
-fn probe(path: PathBuf) -> Option<PathBuf> {
-    let with_extension = match env::consts::EXE_EXTENSION {
-        "" => None,
-        it => Some(path.with_extension(it)),
+fn check_status(cmd: &Command, output: &Output) -> io::Result<()> {
+    if output.status.success() {
+        return Ok(());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let msg = if stderr.trim().is_empty() {
+        format!("{:?} failed, {}", cmd, output.status)
+    } else {
+        format!("{:?} failed, {}\nstderr:\n{}", cmd, output.status, stderr.trim())
     };
-    iter::once(path).chain(with_extension).find(|it| it.is_file())
+    Err(io::Error::new(io::ErrorKind::Other, msg))
 }
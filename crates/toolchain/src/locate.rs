@@ -0,0 +1,527 @@
+//! The search for an executable: environment overrides, `PATH`, cargo's bin
+//! directory and, as a last resort, rustup's toolchain directories.
+
+use std::{
+    env, fs, io, iter,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Instant,
+};
+
+use stdx::split_once;
+
+use crate::{
+    app_alias::{self, is_app_execution_alias},
+    execution::{execution_environment, ExecutionEnvironment},
+    names::{canonical_name, tool_names},
+    path_index::PathIndex,
+    registry,
+    rustup_health::is_symlink_loop,
+    shim::{detect_shim, Shim},
+    slow_fs,
+    timings::{self, CandidateTiming, Skipped, ToolTiming},
+    tool_kind::{self, Location},
+    validate::{is_valid_executable, resolve_override},
+    DiscoveryError, Env, ErrorKind, ToolchainSpec, Version,
+};
+
+/// An executable we settled on, and why.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FoundExecutable {
+    pub path: PathBuf,
+    pub provenance: Provenance,
+    /// Set if `path` is a script wrapping the real executable.
+    pub shim: Option<Shim>,
+    /// The version found while validating an override. `None` if nothing
+    /// was run, which is the case for the other provenances.
+    pub version: Option<Version>,
+}
+
+/// Where a [`FoundExecutable`] came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Provenance {
+    /// An environment variable like `$CARGO`.
+    EnvVar(String),
+    /// A `PATH` entry.
+    Path,
+    /// `~/.cargo/bin`, where rustup installs its proxies.
+    CargoBin,
+    /// The `bin` directory of one of rustup's toolchains. We only get here if
+    /// the proxies in `~/.cargo/bin` are missing, which means the rustup
+    /// installation is broken.
+    RustupToolchain { toolchain: String },
+}
+
+impl FoundExecutable {
+    /// Whether the user should be told to repair their rustup installation.
+    pub fn needs_rustup_repair(&self) -> bool {
+        matches!(self.provenance, Provenance::RustupToolchain { .. })
+    }
+
+    /// The executable which does the actual work: the target of the shim, if
+    /// known, or `path` itself.
+    pub fn real_path(&self) -> &Path {
+        match self.shim.as_ref().and_then(|it| it.target.as_deref()) {
+            Some(target) => target,
+            None => &self.path,
+        }
+    }
+
+    /// Asks the version manager behind the shim, if any, for the real
+    /// executable. Unlike shim detection, this spawns a process.
+    pub fn resolve_shim(&mut self, tool: &str) {
+        if let Some(shim) = &mut self.shim {
+            shim.resolve(tool);
+        }
+    }
+}
+
+/// Finds the given executable.
+///
+/// The search checks, in order:
+///
+/// 1. The environment variable named after the executable, like `$CARGO` or
+///    `$RUSTC`, see [`crate::ToolKind::env_var`].
+/// 2. `PATH`.
+/// 3. `~/.cargo/bin`, which is a reasonable place to try for cargo, rustc
+///    and rustup.
+/// 4. The toolchain directories under `$RUSTUP_HOME`, preferring
+///    `$RUSTUP_TOOLCHAIN` and then rustup's default toolchain.
+///
+/// Steps 2 to 4 also look for the other names of the executable, see
+/// [`crate::tool_names`], and are skipped for tools which are never
+/// installed there, see [`crate::ToolKind::locations`].
+///
+/// An override variable which is set must point to a working executable,
+/// otherwise that's an error rather than a reason to look elsewhere.
+///
+/// Script shims (as installed by asdf, mise or custom wrappers) are detected,
+/// but only resolved if they say where the real executable is without
+/// running anything, see [`FoundExecutable::resolve_shim`].
+///
+/// How long each checked candidate took is recorded, see
+/// [`crate::discovery_timings`].
+pub fn locate(executable_name: &str) -> Result<FoundExecutable, DiscoveryError> {
+    let (res, timing) = locate_timed(&Env::current(), executable_name);
+    timings::record(timing);
+    res
+}
+
+/// Like [`locate`], but looks at `env` rather than at the process
+/// environment, and doesn't record timings. Only the file system is
+/// consulted beyond `env`, and an override is run in `env` to check that it
+/// works. Directories found to be slow earlier are still skipped, see
+/// [`crate::slow_dirs`].
+pub fn locate_in(env: &Env, executable_name: &str) -> Result<FoundExecutable, DiscoveryError> {
+    locate_timed(env, executable_name).0
+}
+
+fn locate_timed(
+    env: &Env,
+    executable_name: &str,
+) -> (Result<FoundExecutable, DiscoveryError>, ToolTiming) {
+    let executable_name = canonical_name(executable_name);
+    let start = Instant::now();
+    let mut candidates = Vec::new();
+    let res = search(&mut candidates, env, executable_name);
+    let timing =
+        ToolTiming { tool: executable_name.to_string(), total: start.elapsed(), candidates };
+    let res = res.map(|(path, provenance, version)| {
+        let shim = detect_shim(&path, executable_name);
+        FoundExecutable { path, provenance, shim, version }
+    });
+    (res, timing)
+}
+
+type Found = (PathBuf, Provenance, Option<Version>);
+
+fn search(
+    acc: &mut Vec<CandidateTiming>,
+    env: &Env,
+    executable_name: &str,
+) -> Result<Found, DiscoveryError> {
+    if let Some(env_var) = tool_kind::env_var(executable_name) {
+        if let Some(value) = env.var_os(&env_var) {
+            let path = resolve_override(env, executable_name, &env_var, value.into())?;
+            let version = is_valid_executable(Some(env), executable_name, &path)?;
+            return Ok((path, Provenance::EnvVar(env_var), version));
+        }
+    }
+
+    // Every place is searched for every name before moving on, so that an
+    // alias in `PATH` wins over the canonical name in `~/.cargo/bin`.
+    let names = tool_names(executable_name);
+    for location in tool_kind::locations(executable_name) {
+        match location {
+            Location::Path => {
+                if let Some(path) =
+                    names.iter().find_map(|name| lookup_in_path_impl(acc, env, name))
+                {
+                    return Ok((path, Provenance::Path, None));
+                }
+            }
+            Location::CargoBin => {
+                if let Some(path) =
+                    names.iter().find_map(|name| lookup_in_cargo_bin(acc, env, name))
+                {
+                    return Ok((path, Provenance::CargoBin, None));
+                }
+            }
+            Location::RustupToolchains => {
+                let rustup_home = match env.rustup_home() {
+                    Some(it) => it,
+                    None => continue,
+                };
+                let preferred = env
+                    .var("RUSTUP_TOOLCHAIN")
+                    .map(str::to_string)
+                    .or_else(|| default_toolchain(&rustup_home));
+                let found = names.iter().find_map(|name| {
+                    lookup_in_rustup_toolchains(acc, &rustup_home, name, preferred.as_deref())
+                });
+                if let Some((path, toolchain)) = found {
+                    log::warn!(
+                        "{} is not in PATH or ~/.cargo/bin, using {} directly. \
+                         The rustup installation seems to be broken, try reinstalling rustup.",
+                        executable_name,
+                        path.display()
+                    );
+                    return Ok((path, Provenance::RustupToolchain { toolchain }, None));
+                }
+            }
+        }
+    }
+
+    let res = DiscoveryError::new(ErrorKind::NotFound, executable_name);
+    // The aliases are skipped while searching, but they are the likely
+    // reason why nothing was found.
+    let alias = env.path_dirs().into_iter().find_map(|dir| {
+        let path = dir.join(executable_name).with_extension(env::consts::EXE_EXTENSION);
+        Some(path).filter(|it| is_app_execution_alias(it))
+    });
+    match alias {
+        Some(path) => {
+            let detail = app_alias::explain(executable_name, &path);
+            Err(res.with_path(path).with_detail(detail))
+        }
+        None => Err(res),
+    }
+}
+
+/// Return a `PathBuf` to use for the given executable.
+///
+/// E.g., `get_path_for_executable("cargo")` may return just `cargo` if
+/// nothing better is found, leaving the error for when it is run; or it may
+/// return a full path to a valid Cargo.
+pub(crate) fn get_path_for_executable(executable_name: &'static str) -> PathBuf {
+    match locate(executable_name) {
+        Ok(it) => it.path,
+        Err(err) => {
+            log::warn!("{}", err);
+            fallback_path(&Env::current(), executable_name)
+        }
+    }
+}
+
+/// What to run when `executable_name` wasn't found. A broken override, like
+/// `$CARGO` pointing to a removed file, is returned as is: running it fails
+/// visibly, where `PATH` could silently run another tool.
+fn fallback_path(env: &Env, executable_name: &str) -> PathBuf {
+    let value = tool_kind::env_var(executable_name).and_then(|var| env.var_os(&var));
+    match value {
+        Some(value) => value.into(),
+        None => executable_name.into(),
+    }
+}
+
+/// Looks for `exec` in `PATH`, then in `~/.cargo/bin`, returning the full
+/// path. Unlike [`locate`], there's no environment variable override.
+pub(crate) fn lookup(exec: &str) -> Option<PathBuf> {
+    let acc = &mut Vec::new();
+    let env = &Env::current();
+    lookup_in_path_impl(acc, env, exec).or_else(|| lookup_in_cargo_bin(acc, env, exec))
+}
+
+pub(crate) fn lookup_in_path(env: &Env, exec: &str) -> Option<PathBuf> {
+    lookup_in_path_impl(&mut Vec::new(), env, exec)
+}
+
+/// Only the directories which have `exec` are probed, see
+/// [`registry::path_index`]. Directories on slow filesystems are tried last,
+/// see [`slow_fs`].
+fn lookup_in_path_impl(acc: &mut Vec<CandidateTiming>, env: &Env, exec: &str) -> Option<PathBuf> {
+    let find = |acc: &mut Vec<CandidateTiming>, index: Arc<PathIndex>| {
+        slow_fs::slow_last(index.candidates(exec).into_iter())
+            .into_iter()
+            .find_map(|path| probe(acc, path.join(exec)))
+    };
+    let dirs = env.path_dirs();
+    // A miss may be a tool installed since the directories were listed.
+    find(acc, registry::path_index(dirs.clone(), false))
+        .or_else(|| find(acc, registry::path_index(dirs, true)))
+}
+
+fn lookup_in_cargo_bin(acc: &mut Vec<CandidateTiming>, env: &Env, exec: &str) -> Option<PathBuf> {
+    let mut path = env.home_dir()?;
+    path.push(".cargo");
+    path.push("bin");
+    path.push(exec);
+    probe(acc, path)
+}
+
+/// Finds `exec` in `$RUSTUP_HOME/toolchains/*/bin`, returning it together
+/// with the name of the toolchain directory.
+///
+/// `preferred` may be a full toolchain name or a short one, like `stable`.
+/// Otherwise, stable toolchains win over the rest, and the names decide
+/// ties.
+fn lookup_in_rustup_toolchains(
+    acc: &mut Vec<CandidateTiming>,
+    rustup_home: &Path,
+    exec: &str,
+    preferred: Option<&str>,
+) -> Option<(PathBuf, String)> {
+    let mut toolchains: Vec<String> = execution_environment()
+        .read_dir(&rustup_home.join("toolchains"))
+        .ok()?
+        .into_iter()
+        .filter_map(|it| it.into_string().ok())
+        .collect();
+    toolchains.sort_by_key(|name| {
+        let is_preferred = preferred.map_or(false, |it| toolchain_matches(name, it));
+        (!is_preferred, !name.starts_with("stable"), name.clone())
+    });
+    toolchains.into_iter().find_map(|toolchain| {
+        let dir = rustup_home.join("toolchains").join(&toolchain);
+        let path = bin_dirs(&dir).into_iter().find_map(|it| probe(acc, it.join(exec)))?;
+        Some((path, toolchain))
+    })
+}
+
+/// The directories with the executables of the toolchain in `dir`.
+///
+/// A toolchain linked with `rustup toolchain link` to a stage of a local
+/// build, like `build/x86_64-unknown-linux-gnu/stage1`, only has the compiler
+/// in `bin`: `x.py` puts the tools, like cargo and rustfmt, in the
+/// `stage1-tools-bin` next to it.
+pub(crate) fn bin_dirs(dir: &Path) -> Vec<PathBuf> {
+    let mut res = vec![dir.join("bin")];
+    if let Ok(target) = fs::read_link(dir) {
+        let target = match dir.parent() {
+            Some(parent) => parent.join(target),
+            None => target,
+        };
+        let stage =
+            target.file_name().and_then(|it| it.to_str()).filter(|it| it.starts_with("stage"));
+        if let (Some(stage), Some(build)) = (stage, target.parent()) {
+            res.push(build.join(format!("{}-tools-bin", stage)));
+        }
+    }
+    res
+}
+
+fn toolchain_matches(dir_name: &str, toolchain: &str) -> bool {
+    ToolchainSpec::parse(toolchain).matches(&ToolchainSpec::parse(dir_name))
+}
+
+/// Reads `default_toolchain` from rustup's `settings.toml`.
+pub(crate) fn default_toolchain(rustup_home: &Path) -> Option<String> {
+    let settings = fs::read_to_string(rustup_home.join("settings.toml")).ok()?;
+    settings.lines().find_map(|line| {
+        let (key, value) = split_once(line, '=')?;
+        if key.trim() != "default_toolchain" {
+            return None;
+        }
+        Some(value.trim().trim_matches('"').to_string())
+    })
+}
+
+/// Checks `path`, unless it is the same file as an earlier candidate in
+/// `acc`.
+fn probe(acc: &mut Vec<CandidateTiming>, path: PathBuf) -> Option<PathBuf> {
+    let with_extension = match env::consts::EXE_EXTENSION {
+        "" => None,
+        it => Some(path.with_extension(it)),
+    };
+    let exec_env = execution_environment();
+    iter::once(path).chain(with_extension).find(|it| {
+        let start = Instant::now();
+        let (resolved, skipped) = match resolve_dir(&*exec_env, it) {
+            Ok(resolved) if acc.iter().any(|probed| probed.resolved == resolved) => {
+                (resolved, Some(Skipped::Duplicate))
+            }
+            Ok(resolved) => (resolved, None),
+            Err(skipped) => (it.clone(), Some(skipped)),
+        };
+        let found = skipped.is_none() && exec_env.is_file(it) && !is_app_execution_alias(it);
+        acc.push(CandidateTiming {
+            path: it.clone(),
+            resolved,
+            duration: start.elapsed(),
+            found,
+            skipped,
+        });
+        found
+    })
+}
+
+/// Resolves the links in the directory of `path`, but not `path` itself: a
+/// link named `cargo` to `rustup` is a different tool than `rustup`.
+///
+/// Slow directories are left alone, they are not worth another `stat`, and
+/// so are the ones which don't answer in time.
+fn resolve_dir(exec_env: &dyn ExecutionEnvironment, path: &Path) -> Result<PathBuf, Skipped> {
+    let (dir, name) = match (path.parent(), path.file_name()) {
+        (Some(dir), Some(name)) if !slow_fs::is_slow(dir) => (dir, name),
+        _ => return Ok(path.to_path_buf()),
+    };
+    match exec_env.canonicalize(dir) {
+        Ok(dir) => Ok(dir.join(name)),
+        Err(err) if err.kind() == io::ErrorKind::TimedOut => Ok(path.to_path_buf()),
+        Err(_) if is_symlink_loop(dir) => {
+            log::warn!("{} is a symbolic link cycle, skipping it", dir.display());
+            Err(Skipped::SymlinkCycle)
+        }
+        // Likely a directory which doesn't exist.
+        Err(_) => Ok(path.to_path_buf()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testdir::TestDir;
+
+    use super::*;
+
+    fn fake_rustup_home(name: &str, toolchains: &[&str]) -> (TestDir, PathBuf) {
+        let tmp = TestDir::new(name);
+        let home = tmp.path().to_path_buf();
+        for toolchain in toolchains {
+            let bin = home.join("toolchains").join(toolchain).join("bin");
+            fs::create_dir_all(&bin).unwrap();
+            fs::write(bin.join(format!("cargo{}", env::consts::EXE_SUFFIX)), "").unwrap();
+        }
+        fs::write(home.join("settings.toml"), "default_toolchain = \"nightly-x86_64\"\n").unwrap();
+        (tmp, home)
+    }
+
+    #[test]
+    fn rustup_toolchain_fallback_prefers_requested_toolchain() {
+        let (_tmp, home) =
+            fake_rustup_home("preferred", &["beta-x86_64", "nightly-x86_64", "stable-x86_64"]);
+
+        let acc = &mut Vec::new();
+        let default = default_toolchain(&home);
+        assert_eq!(default.as_deref(), Some("nightly-x86_64"));
+        let (path, toolchain) =
+            lookup_in_rustup_toolchains(acc, &home, "cargo", default.as_deref()).unwrap();
+        assert_eq!(toolchain, "nightly-x86_64");
+        assert!(path.starts_with(home.join("toolchains/nightly-x86_64/bin")));
+
+        let (_, toolchain) =
+            lookup_in_rustup_toolchains(acc, &home, "cargo", Some("beta")).unwrap();
+        assert_eq!(toolchain, "beta-x86_64");
+
+        let (_, toolchain) = lookup_in_rustup_toolchains(acc, &home, "cargo", None).unwrap();
+        assert_eq!(toolchain, "stable-x86_64");
+
+        assert_eq!(lookup_in_rustup_toolchains(acc, &home, "rustfmt", None), None);
+    }
+
+    #[test]
+    fn locate_records_timings() {
+        let found = locate("cargo").unwrap();
+        let timings = crate::discovery_timings();
+        let timing = timings.tools.iter().find(|it| it.tool == "cargo").unwrap();
+        if found.provenance == Provenance::Path {
+            assert_eq!(
+                timing.candidates.last().map(|it| (&it.path, it.found)),
+                Some((&found.path, true))
+            );
+        }
+    }
+
+    #[test]
+    fn locate_in_explicit_env() {
+        let (_tmp, home) = fake_rustup_home("explicit-env", &["stable-x86_64"]);
+        let bin = home.join("toolchains/stable-x86_64/bin");
+        let mut env = Env::default();
+        env.set_var("HOME", home.clone().into());
+        env.set_var("RUSTUP_HOME", home.join("nope").into());
+
+        assert_eq!(locate_in(&env, "cargo").unwrap_err().kind(), ErrorKind::NotFound);
+
+        env.set_var("PATH", bin.clone().into());
+        let found = locate_in(&env, "cargo").unwrap();
+        assert_eq!(found.provenance, Provenance::Path);
+        assert!(found.path.starts_with(&bin));
+
+        assert_eq!(fallback_path(&env, "cargo"), Path::new("cargo"));
+        env.set_var("CARGO", "surely-not-cargo".into());
+        let err = locate_in(&env, "cargo").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::EnvVarNotExecutable);
+        assert_eq!(fallback_path(&env, "cargo"), Path::new("surely-not-cargo"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn overrides_run_in_the_given_env() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let tmp = TestDir::new("override-env");
+        let cargo = tmp.path().join("cargo");
+        fs::write(&cargo, "#!/bin/sh\n[ \"$RA_OVERRIDE_TEST\" = 1 ] && echo cargo 1.52.0\n")
+            .unwrap();
+        fs::set_permissions(&cargo, fs::Permissions::from_mode(0o755)).unwrap();
+        let mut env = Env::default();
+        env.set_var("CARGO", cargo.clone().into());
+        assert!(locate_in(&env, "cargo").is_err());
+        env.set_var("RA_OVERRIDE_TEST", "1".into());
+        assert_eq!(locate_in(&env, "cargo").unwrap().path, cargo);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn linked_and_cyclic_dirs_are_skipped() {
+        use std::os::unix::fs::symlink;
+
+        let (_tmp, home) = fake_rustup_home("links", &["stable-x86_64"]);
+        let bin = home.join("toolchains/stable-x86_64/bin");
+        symlink(&bin, home.join("bin-link")).unwrap();
+        symlink(home.join("loop-a"), home.join("loop-b")).unwrap();
+        symlink(home.join("loop-b"), home.join("loop-a")).unwrap();
+
+        let acc = &mut Vec::new();
+        assert_eq!(probe(acc, home.join("loop-a/rustfmt")), None);
+        assert_eq!(probe(acc, bin.join("rustfmt")), None);
+        assert_eq!(probe(acc, home.join("bin-link/rustfmt")), None);
+        assert!(probe(acc, home.join("bin-link/cargo")).is_some());
+        let skipped: Vec<_> = acc.iter().map(|it| it.skipped.clone()).collect();
+        assert_eq!(skipped, [Some(Skipped::SymlinkCycle), None, Some(Skipped::Duplicate), None]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn linked_stage_finds_tools_bin() {
+        let (_tmp, home) = fake_rustup_home("linked-stage", &["stable"]);
+        let build = home.join("rust/build/x86_64-unknown-linux-gnu");
+        fs::create_dir_all(build.join("stage1/bin")).unwrap();
+        fs::create_dir_all(build.join("stage1-tools-bin")).unwrap();
+        fs::write(build.join("stage1-tools-bin/cargo"), "").unwrap();
+        fs::create_dir_all(home.join("toolchains")).unwrap();
+        std::os::unix::fs::symlink(build.join("stage1"), home.join("toolchains/stage1")).unwrap();
+
+        let acc = &mut Vec::new();
+        let (path, toolchain) =
+            lookup_in_rustup_toolchains(acc, &home, "cargo", Some("stage1")).unwrap();
+        assert_eq!(toolchain, "stage1");
+        assert_eq!(path, build.join("stage1-tools-bin/cargo"));
+    }
+
+    #[test]
+    fn short_toolchain_names_match() {
+        assert!(toolchain_matches("stable-x86_64-unknown-linux-gnu", "stable"));
+        assert!(toolchain_matches("stable", "stable"));
+        assert!(!toolchain_matches("stable2-x86_64", "stable"));
+    }
+}
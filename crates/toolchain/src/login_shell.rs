@@ -0,0 +1,213 @@
+//! The environment of the user's login shell, for servers started from a
+//! desktop launcher, which don't get the `PATH` set up by shell profiles.
+
+use std::{
+    env,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    process::{ExitStatus, Stdio},
+    sync::{mpsc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use once_cell::sync::Lazy;
+use rustc_hash::FxHashMap;
+use stdx::split_once;
+
+use crate::{locate::lookup, ToolCommand};
+
+/// The kinds of shells which need different arguments to load their profiles
+/// and print their environment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShellKind {
+    /// `sh`, `bash`, `dash`, `ksh` and everything else POSIX-like.
+    Posix,
+    Zsh,
+    Fish,
+    Nushell,
+    PowerShell,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoginShell {
+    pub kind: ShellKind,
+    pub path: PathBuf,
+}
+
+/// Printed before the environment, to skip whatever the profiles print.
+pub(crate) const MARKER: &str = "__RUST_ANALYZER_ENV__";
+
+/// Shells which take longer than this, usually because they wait for input,
+/// are killed.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Variables describing the shell process rather than the user's setup.
+const SHELL_VARS: &[&str] = &["PWD", "OLDPWD", "SHLVL", "_"];
+
+/// The user's shell: `$SHELL` on Unix, PowerShell on Windows.
+pub fn login_shell() -> Option<LoginShell> {
+    if cfg!(windows) {
+        let path = lookup("pwsh").or_else(|| lookup("powershell"))?;
+        return Some(LoginShell { kind: ShellKind::PowerShell, path });
+    }
+    let path = PathBuf::from(env::var_os("SHELL").filter(|it| !it.is_empty())?);
+    Some(LoginShell { kind: ShellKind::of(&path), path })
+}
+
+impl ShellKind {
+    fn of(path: &Path) -> ShellKind {
+        let name = path.file_stem().and_then(|it| it.to_str()).unwrap_or_default();
+        match name {
+            "zsh" => ShellKind::Zsh,
+            "fish" => ShellKind::Fish,
+            "nu" => ShellKind::Nushell,
+            "pwsh" | "powershell" => ShellKind::PowerShell,
+            _ => ShellKind::Posix,
+        }
+    }
+}
+
+impl LoginShell {
+    /// The command which loads the profiles and prints the environment.
+    pub fn command(&self) -> ToolCommand {
+        let mut res = ToolCommand::new(self.path.clone());
+        let print_env = format!("echo {}; env", MARKER);
+        match self.kind {
+            ShellKind::Posix | ShellKind::Fish => res.args(["-l", "-c", &print_env]),
+            // Many users set their `PATH` in `.zshrc`, which only interactive
+            // shells read.
+            ShellKind::Zsh => res.args(["-l", "-i", "-c", &print_env]),
+            // `^env` is the external command, which sees the variables
+            // converted back to strings.
+            ShellKind::Nushell => {
+                res.args(["--login", "-c", &format!("print {}; ^env", MARKER)])
+            }
+            // The profile is loaded unless `-NoProfile` is passed.
+            ShellKind::PowerShell => res.args([
+                "-NoLogo",
+                "-NonInteractive",
+                "-Command",
+                &format!(
+                    "Write-Output {}; Get-ChildItem env: | ForEach-Object {{ \"$($_.Name)=$($_.Value)\" }}",
+                    MARKER
+                ),
+            ]),
+        };
+        res
+    }
+
+    /// Runs the shell and returns the variables of its environment, without
+    /// the ones which only describe the shell process, like `SHLVL`.
+    pub fn capture_env(&self) -> io::Result<Vec<(String, String)>> {
+        let (_, stdout) = run_with_timeout(&self.command())?;
+        Ok(parse(&stdout, MARKER))
+    }
+
+    /// Like [`LoginShell::capture_env`], but only runs the shell once per
+    /// process: it can take seconds to start, and what the profiles set
+    /// seldom changes while the server runs. Failures are not remembered.
+    pub fn cached_env(&self) -> io::Result<Vec<(String, String)>> {
+        if let Some(it) = CAPTURED.lock().unwrap().get(&self.path) {
+            return Ok(it.clone());
+        }
+        let res = self.capture_env()?;
+        CAPTURED.lock().unwrap().insert(self.path.clone(), res.clone());
+        Ok(res)
+    }
+}
+
+type Vars = Vec<(String, String)>;
+
+static CAPTURED: Lazy<Mutex<FxHashMap<PathBuf, Vars>>> = Lazy::new(Default::default);
+
+/// Runs `cmd` without input, killing it after [`TIMEOUT`], and returns its
+/// exit status and output.
+pub(crate) fn run_with_timeout(cmd: &ToolCommand) -> io::Result<(ExitStatus, String)> {
+    crate::registry::count_spawn();
+    let mut child = cmd
+        .to_command()
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+    let mut stdout = child.stdout.take().unwrap();
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut buf = Vec::new();
+        let _ = sender.send(stdout.read_to_end(&mut buf).map(|_| buf));
+    });
+    match receiver.recv_timeout(TIMEOUT) {
+        Ok(stdout) => {
+            let status = child.wait()?;
+            Ok((status, String::from_utf8_lossy(&stdout?).into_owned()))
+        }
+        Err(_) => {
+            let _ = child.kill();
+            let _ = child.wait();
+            Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("`{}` didn't exit in time", cmd.program().display()),
+            ))
+        }
+    }
+}
+
+/// Parses the variables printed after the `marker` line. Lines which don't
+/// start a new variable continue a multi-line value.
+pub(crate) fn parse(stdout: &str, marker: &str) -> Vec<(String, String)> {
+    let mut res: Vec<(String, String)> = Vec::new();
+    let lines = stdout.lines().skip_while(|it| it.trim() != marker).skip(1);
+    for line in lines {
+        let var = split_once(line, '=').filter(|(key, _)| {
+            !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        });
+        match (var, res.last_mut()) {
+            (Some((key, value)), _) => res.push((key.to_string(), value.to_string())),
+            (None, Some((_, value))) => {
+                value.push('\n');
+                value.push_str(line);
+            }
+            (None, None) => (),
+        }
+    }
+    res.retain(|(key, _)| !SHELL_VARS.contains(&key.as_str()));
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shells_are_told_apart() {
+        assert_eq!(ShellKind::of(Path::new("/usr/bin/fish")), ShellKind::Fish);
+        assert_eq!(ShellKind::of(Path::new("/bin/zsh")), ShellKind::Zsh);
+        assert_eq!(ShellKind::of(Path::new("/opt/homebrew/bin/nu")), ShellKind::Nushell);
+        assert_eq!(ShellKind::of(Path::new("/usr/local/bin/pwsh")), ShellKind::PowerShell);
+        assert_eq!(ShellKind::of(Path::new("/bin/bash")), ShellKind::Posix);
+    }
+
+    #[test]
+    fn skips_profile_output() {
+        let stdout = format!(
+            "Welcome!\n{}\nPATH=/home/me/.cargo/bin:/usr/bin\nSHLVL=2\nMULTI=a\nb\n",
+            MARKER
+        );
+        assert_eq!(
+            parse(&stdout, MARKER),
+            [
+                ("PATH".to_string(), "/home/me/.cargo/bin:/usr/bin".to_string()),
+                ("MULTI".to_string(), "a\nb".to_string())
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn captures_sh_environment() {
+        let shell = LoginShell { kind: ShellKind::Posix, path: "/bin/sh".into() };
+        let env = shell.capture_env().unwrap();
+        assert!(env.iter().any(|(key, _)| key == "PATH"));
+    }
+}
@@ -0,0 +1,104 @@
+//! Rendering of [`DiscoveryError`]s, in English or in whatever language the
+//! client supplies templates for.
+//!
+//! Templates are keyed by error code and may refer to the fields of the error
+//! as `{tool}`, `{path}`, `{var}` and `{detail}`. Logs always use the English
+//! templates, via `Display`.
+
+use rustc_hash::FxHashMap;
+
+use crate::{DiscoveryError, ErrorKind};
+
+/// Message templates overriding the English ones. The default catalog is
+/// empty, and so renders everything in English.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MessageCatalog {
+    templates: FxHashMap<String, String>,
+}
+
+impl MessageCatalog {
+    /// Sets the template for the error `code`, like `"E0102"`.
+    pub fn insert(&mut self, code: &str, template: String) {
+        self.templates.insert(code.to_string(), template);
+    }
+
+    pub fn template(&self, kind: ErrorKind) -> &str {
+        match self.templates.get(kind.code()) {
+            Some(it) => it,
+            None => english(kind),
+        }
+    }
+}
+
+impl DiscoveryError {
+    /// The message for this error, without the code. The detail, if any, is
+    /// appended unless the template places it somewhere itself.
+    pub fn render(&self, catalog: &MessageCatalog) -> String {
+        let template = catalog.template(self.kind());
+        let path = match self.path() {
+            Some(it) => it.display().to_string(),
+            None => self.tool().to_string(),
+        };
+        let detail = self.detail().unwrap_or_default();
+        let mut res = template
+            .replace("{tool}", self.tool())
+            .replace("{path}", &path)
+            .replace("{var}", self.var().unwrap_or_default())
+            .replace("{detail}", detail);
+        if !detail.is_empty() && !template.contains("{detail}") {
+            res.push_str(": ");
+            res.push_str(detail);
+        }
+        res
+    }
+}
+
+fn english(kind: ErrorKind) -> &'static str {
+    match kind {
+        ErrorKind::EnvVarNotExecutable => "${var} is set to `{path}`, which is not an executable",
+        ErrorKind::NotFound => "could not find {tool}",
+        ErrorKind::SpawnFailed => "failed to run `{path}`",
+        ErrorKind::BadExitStatus => "`{path}` exited with an error",
+        ErrorKind::UnexpectedOutput => "`{path}` printed unexpected output",
+        ErrorKind::RustupCorrupted => "the rustup installation is broken",
+        ErrorKind::ProxyFailed => "`{path}` could not download through the proxy",
+        ErrorKind::ServerUnreachable => "`{path}` could not reach the download server",
+        ErrorKind::NotWritable => "{tool} can't write to `{path}`",
+        ErrorKind::InvalidRustSrc => {
+            "${var} is set to `{path}`, which doesn't contain the standard library sources"
+        }
+        ErrorKind::WrapperCycle => "`{path}` leads back to discovering {tool}, not running it",
+        ErrorKind::WrongTool => "`{path}` is not {tool}",
+        ErrorKind::Unsupported => "`{path}` doesn't support {detail}",
+        ErrorKind::MissingPrerequisite => "{tool} can't link without {detail}",
+        ErrorKind::DiscoveryPanicked => "looking for {tool} panicked",
+        ErrorKind::TargetNotInstalled => {
+            "the `{detail}` target is not installed for `{path}`, try `rustup target add {detail}`"
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn client_templates_override_english() {
+        let err = DiscoveryError::new(ErrorKind::SpawnFailed, "cargo")
+            .with_path("/bin/cargo".into())
+            .with_detail("permission denied".to_string());
+
+        let mut catalog = MessageCatalog::default();
+        assert_eq!(err.render(&catalog), "failed to run `/bin/cargo`: permission denied");
+
+        catalog.insert("E0103", "`{path}` konnte nicht gestartet werden ({detail})".to_string());
+        assert_eq!(
+            err.render(&catalog),
+            "`/bin/cargo` konnte nicht gestartet werden (permission denied)"
+        );
+        assert_eq!(
+            err.to_string(),
+            "E0103 SpawnFailed: failed to run `/bin/cargo`: permission denied"
+        );
+    }
+}
@@ -0,0 +1,104 @@
+//! Detection of Miri, the interpreter behind `cargo miri test`.
+
+use std::path::Path;
+
+use stdx::split_once;
+
+use crate::{rustc_meta, utf8_stdout, Version};
+
+/// Whether `cargo miri` can be used with the discovered toolchain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MiriStatus {
+    Available {
+        version: Version,
+    },
+    /// Neither the `miri` component nor `cargo-miri` is installed.
+    NotInstalled,
+    /// Miri only exists for nightly toolchains.
+    RequiresNightly {
+        rustc: Version,
+    },
+    /// Miri was built for a different nightly than the one `rustc` is from,
+    /// which typically happens when only one of them got updated.
+    Incompatible {
+        miri_date: String,
+        rustc_date: String,
+    },
+    /// `rustc -vV` failed or printed garbage.
+    UnknownRustc,
+}
+
+impl MiriStatus {
+    pub fn is_available(&self) -> bool {
+        matches!(self, MiriStatus::Available { .. })
+    }
+}
+
+pub fn miri_status(cargo: &Path, rustc: &Path) -> MiriStatus {
+    let rustc = match rustc_meta(rustc) {
+        Ok(it) => it,
+        Err(_) => return MiriStatus::UnknownRustc,
+    };
+    if !matches!(rustc.version.pre.as_deref(), Some("nightly") | Some("dev")) {
+        return MiriStatus::RequiresNightly { rustc: rustc.version };
+    }
+
+    let miri_output = match version_output(cargo, &["miri", "--version"]) {
+        Some(it) => it,
+        None => return MiriStatus::NotInstalled,
+    };
+    let version = match Version::parse_output(&miri_output) {
+        Some(it) => it,
+        None => return MiriStatus::NotInstalled,
+    };
+    if let (Some(miri_date), Some(rustc_date)) =
+        (commit_date(&miri_output), rustc.commit_date.as_deref())
+    {
+        if miri_date != rustc_date {
+            return MiriStatus::Incompatible {
+                miri_date: miri_date.to_string(),
+                rustc_date: rustc_date.to_string(),
+            };
+        }
+    }
+    MiriStatus::Available { version }
+}
+
+fn version_output(program: &Path, args: &[&str]) -> Option<String> {
+    let mut cmd = crate::interpreter::command(program);
+    cmd.args(args);
+    utf8_stdout(cmd).ok()
+}
+
+/// The date from a `tool 1.2.3 (hash date)` version line.
+fn commit_date(output: &str) -> Option<&str> {
+    let line = output.lines().next()?;
+    let (_, rest) = split_once(line, '(')?;
+    let (inside, _) = split_once(rest, ')')?;
+    inside.split_whitespace().nth(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extracts_commit_date() {
+        assert_eq!(commit_date("miri 0.1.0 (3b1717c 2021-06-09)"), Some("2021-06-09"));
+        assert_eq!(
+            commit_date("rustc 1.54.0-nightly (ed597e7e1 2021-06-08)\n"),
+            Some("2021-06-08")
+        );
+        assert_eq!(commit_date("miri 0.1.0"), None);
+    }
+
+    #[test]
+    fn stable_rustc_has_no_miri() {
+        let rustc = crate::rustc();
+        let status = miri_status(&crate::cargo(), &rustc);
+        let version = version_output(&rustc, &["--version"]).unwrap();
+        if !version.contains("nightly") && !version.contains("-dev") {
+            assert!(matches!(status, MiriStatus::RequiresNightly { .. }), "{:?}", status);
+        }
+    }
+}
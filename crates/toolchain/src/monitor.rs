@@ -0,0 +1,181 @@
+//! Notices when the effective toolchain changes under a running server.
+
+use std::{
+    path::PathBuf,
+    sync::mpsc::{self, RecvTimeoutError, Sender},
+    time::Duration,
+};
+
+use crate::{locate::default_toolchain, Env, Fingerprint, Version};
+
+/// What changed about the toolchain since the last check.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ToolchainEvent {
+    /// A different executable is found for `tool`, or the old one was
+    /// replaced, like after `rustup update`.
+    ExecutableChanged {
+        tool: &'static str,
+    },
+    RustupDefaultChanged {
+        old: Option<String>,
+        new: Option<String>,
+    },
+    /// An environment variable affecting discovery changed, through
+    /// [`crate::set_client_env`].
+    EnvChanged {
+        var: &'static str,
+    },
+    /// A cached path of `tool` no longer exists, see [`crate::Revalidator`].
+    ExecutableRemoved {
+        tool: String,
+        path: PathBuf,
+    },
+    /// The file at a cached path of `tool` was replaced.
+    ExecutableReplaced {
+        tool: String,
+        path: PathBuf,
+    },
+    /// The same file reports another version, like a rustup proxy after
+    /// `rustup default`.
+    VersionChanged {
+        tool: String,
+        old: Version,
+        new: Version,
+    },
+}
+
+/// Polls the toolchain in the background and reports changes to a callback.
+/// The spawned thread is shut down when this struct is dropped.
+#[derive(Debug)]
+pub struct ToolchainMonitor {
+    // XXX: drop order is significant
+    _stop: Sender<()>,
+    _thread: jod_thread::JoinHandle,
+}
+
+const WATCHED_TOOLS: &[&str] = &["cargo", "rustc", "rustfmt"];
+
+const WATCHED_VARS: &[&str] =
+    &["CARGO", "RUSTC", "RUSTFMT", "RUSTUP_TOOLCHAIN", "RUSTUP_HOME", "CARGO_HOME", "PATH"];
+
+impl ToolchainMonitor {
+    /// Checks for changes every `interval`, calling `sender` for each one.
+    /// Checking `stat`s the paths already found for the tools, it neither
+    /// runs discovery nor the tools.
+    pub fn spawn(
+        interval: Duration,
+        sender: Box<dyn Fn(ToolchainEvent) + Send>,
+    ) -> ToolchainMonitor {
+        let (stop, receiver) = mpsc::channel();
+        let thread = jod_thread::spawn(move || {
+            let mut snapshot = Snapshot::take();
+            // Anything but a timeout means the monitor was dropped.
+            while let Err(RecvTimeoutError::Timeout) = receiver.recv_timeout(interval) {
+                let new = Snapshot::take();
+                let events = snapshot.diff(&new);
+                if !events.is_empty() {
+                    crate::invalidate_tools();
+                }
+                for event in events {
+                    sender(event);
+                }
+                snapshot = new;
+            }
+        });
+        ToolchainMonitor { _stop: stop, _thread: thread }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+struct Snapshot {
+    executables: Vec<(&'static str, PathBuf, Option<Fingerprint>)>,
+    rustup_default: Option<String>,
+    vars: Vec<(&'static str, Option<String>)>,
+}
+
+impl Snapshot {
+    fn take() -> Snapshot {
+        let env = Env::current();
+        let executables = crate::registry::cached_paths()
+            .into_iter()
+            .filter_map(|(tool, path)| {
+                let tool = *WATCHED_TOOLS.iter().find(|&&it| it == tool)?;
+                let fingerprint = Fingerprint::of(&path).ok();
+                Some((tool, path, fingerprint))
+            })
+            .collect();
+        let rustup_default = env.rustup_home().and_then(|it| default_toolchain(&it));
+        let vars = WATCHED_VARS.iter().map(|&var| (var, env.var(var).map(String::from))).collect();
+        Snapshot { executables, rustup_default, vars }
+    }
+
+    fn diff(&self, new: &Snapshot) -> Vec<ToolchainEvent> {
+        let mut res = Vec::new();
+        for (var, value) in &new.vars {
+            if self.vars.iter().all(|(v, old)| v != var || old != value) {
+                res.push(ToolchainEvent::EnvChanged { var });
+            }
+        }
+        if self.rustup_default != new.rustup_default {
+            res.push(ToolchainEvent::RustupDefaultChanged {
+                old: self.rustup_default.clone(),
+                new: new.rustup_default.clone(),
+            });
+        }
+        // Paths which are only in one snapshot were discovered or forgotten
+        // in between, which is not a change of the files.
+        for (tool, path, fingerprint) in &new.executables {
+            let replaced = self
+                .executables
+                .iter()
+                .any(|(t, p, old)| t == tool && p == path && old != fingerprint);
+            let event = ToolchainEvent::ExecutableChanged { tool };
+            if replaced && !res.contains(&event) {
+                res.push(event);
+            }
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diff_reports_changes() {
+        let old = Snapshot {
+            executables: vec![
+                ("cargo", PathBuf::from("/bin/cargo"), None),
+                ("rustc", PathBuf::from("/bin/rustc"), None),
+            ],
+            rustup_default: Some("stable".to_string()),
+            vars: vec![("PATH", Some("/bin".to_string())), ("RUSTC", None)],
+        };
+        assert_eq!(old.diff(&old), []);
+
+        let mut new = old.clone();
+        new.rustup_default = Some("nightly".to_string());
+        new.vars[1].1 = Some("/opt/rustc".to_string());
+        new.executables[1].2 = Fingerprint::of(&crate::rustc()).ok();
+        new.executables.push(("rustfmt", PathBuf::from("/bin/rustfmt"), None));
+        assert_eq!(
+            old.diff(&new),
+            [
+                ToolchainEvent::EnvChanged { var: "RUSTC" },
+                ToolchainEvent::RustupDefaultChanged {
+                    old: Some("stable".to_string()),
+                    new: Some("nightly".to_string())
+                },
+                ToolchainEvent::ExecutableChanged { tool: "rustc" },
+            ]
+        );
+    }
+
+    #[test]
+    fn monitor_shuts_down_on_drop() {
+        let monitor = ToolchainMonitor::spawn(Duration::from_millis(10), Box::new(|_| ()));
+        std::thread::sleep(Duration::from_millis(30));
+        drop(monitor);
+    }
+}
@@ -0,0 +1,38 @@
+//! The other names some tools go by, depending on the platform or on how
+//! they were packaged, see [`crate::ToolKind::aliases`].
+
+use crate::ToolKind;
+
+/// The name `tool` is known by: legacy spellings map to the current one,
+/// anything else is returned as is.
+pub fn canonical_name(tool: &str) -> &str {
+    ToolKind::from_name(tool).map_or(tool, |it| it.name())
+}
+
+/// Every name to search for `tool`, the canonical one first. The executable
+/// extension is not part of the names, it's added by the search.
+pub fn tool_names(tool: &str) -> Vec<&str> {
+    match ToolKind::from_name(tool) {
+        Some(kind) => {
+            let mut res = vec![kind.name()];
+            res.extend(kind.aliases().iter().copied());
+            res
+        }
+        None => vec![tool],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn legacy_names_are_normalized() {
+        assert_eq!(canonical_name("ra_lsp_server"), "rust-analyzer");
+        assert_eq!(canonical_name("clippy-driver"), "clippy-driver");
+        assert_eq!(canonical_name("cargo"), "cargo");
+        assert_eq!(tool_names("ra_lsp_server"), ["rust-analyzer", "ra_lsp_server"]);
+        assert_eq!(tool_names("cargo-clippy"), ["cargo-clippy", "clippy-driver"]);
+        assert_eq!(tool_names("rustc"), ["rustc"]);
+    }
+}
@@ -0,0 +1,45 @@
+//! Detection of `cargo-nextest`, an alternative test runner.
+
+use std::{path::PathBuf, process::Command};
+
+use crate::{utf8_stdout, Version};
+
+/// An installed `cargo-nextest`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Nextest {
+    pub path: PathBuf,
+    pub version: Version,
+}
+
+impl Nextest {
+    /// Whether `cargo nextest run --message-format libtest-json` is
+    /// available. It is still experimental, so the run also needs
+    /// `NEXTEST_EXPERIMENTAL_LIBTEST_JSON=1`.
+    pub fn supports_json_output(&self) -> bool {
+        self.version.at_least(0, 9, 66)
+    }
+}
+
+/// Finds `cargo-nextest` in `PATH` or `~/.cargo/bin`, returning `None` if it
+/// is missing or doesn't report its version.
+pub fn nextest() -> Option<Nextest> {
+    let path = crate::locate::lookup("cargo-nextest")?;
+    // Like every cargo subcommand, the binary expects its own name first.
+    let mut cmd = Command::new(&path);
+    cmd.args(["nextest", "--version"]);
+    let version = Version::parse_output(&utf8_stdout(cmd).ok()?)?;
+    Some(Nextest { path, version })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_output_is_version_gated() {
+        let nextest = |version| Nextest { path: "cargo-nextest".into(), version };
+        assert!(!nextest(Version::parse("0.9.65").unwrap()).supports_json_output());
+        assert!(nextest(Version::parse("0.9.66").unwrap()).supports_json_output());
+        assert!(nextest(Version::parse("1.0.0").unwrap()).supports_json_output());
+    }
+}
@@ -0,0 +1,144 @@
+//! The contents of the `PATH` directories, read once.
+//!
+//! Looking for a tool used to `stat` its name in every `PATH` entry, for
+//! every tool. With dozens of entries, some of them on network mounts, that
+//! adds up. Instead, each directory is listed once, and lookups only probe
+//! the directories which have an entry of the right name. The index is
+//! rebuilt when `PATH` changes, and dropped by [`crate::invalidate_tools`].
+//! When a lookup finds nothing, the directories which changed since they
+//! were listed are listed again, in case the tool was just installed.
+
+use std::{
+    env,
+    ffi::{OsStr, OsString},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use rustc_hash::FxHashSet;
+
+use crate::{slow_fs, ExecutionEnvironment};
+
+#[derive(Debug, Default)]
+pub(crate) struct PathIndex {
+    /// The `PATH` directories, in order.
+    dirs: Vec<PathBuf>,
+    /// The entries of each directory. `None` for the ones which couldn't be
+    /// listed, or weren't because they are slow: they are probed for every
+    /// name, like before.
+    listings: Vec<Option<Listing>>,
+}
+
+#[derive(Debug, Clone)]
+struct Listing {
+    /// `None` if the backend can't tell, which makes the listing stale on
+    /// every miss.
+    modified: Option<SystemTime>,
+    names: FxHashSet<OsString>,
+}
+
+impl PathIndex {
+    pub(crate) fn scan(exec_env: &dyn ExecutionEnvironment, dirs: Vec<PathBuf>) -> PathIndex {
+        let listings = dirs.iter().map(|dir| list(exec_env, dir)).collect();
+        PathIndex { dirs, listings }
+    }
+
+    /// The index with the directories which changed since they were listed
+    /// listed again.
+    pub(crate) fn rescan(&self, exec_env: &dyn ExecutionEnvironment) -> PathIndex {
+        let listings = self
+            .dirs
+            .iter()
+            .zip(&self.listings)
+            .map(|(dir, listing)| match listing {
+                Some(it) if it.modified.is_some() && exec_env.modified(dir).ok() == it.modified => {
+                    Some(it.clone())
+                }
+                _ => list(exec_env, dir),
+            })
+            .collect();
+        PathIndex { dirs: self.dirs.clone(), listings }
+    }
+
+    pub(crate) fn dirs(&self) -> &[PathBuf] {
+        &self.dirs
+    }
+
+    /// The directories which may have `exec`, in `PATH` order. Like the
+    /// probe, `exec` matches with and without the executable extension.
+    pub(crate) fn candidates(&self, exec: &str) -> Vec<PathBuf> {
+        let exec = Path::new(exec);
+        if exec.components().count() != 1 {
+            return self.dirs.clone();
+        }
+        let mut names = vec![normalize(exec.as_os_str())];
+        if !env::consts::EXE_EXTENSION.is_empty() {
+            names.push(normalize(exec.with_extension(env::consts::EXE_EXTENSION).as_os_str()));
+        }
+        self.dirs
+            .iter()
+            .zip(&self.listings)
+            .filter(|(_, listing)| match listing {
+                Some(it) => names.iter().any(|name| it.names.contains(name)),
+                None => true,
+            })
+            .map(|(dir, _)| dir.clone())
+            .collect()
+    }
+}
+
+fn list(exec_env: &dyn ExecutionEnvironment, dir: &Path) -> Option<Listing> {
+    if slow_fs::is_slow(dir) {
+        return None;
+    }
+    // Read before listing, so that a change while listing makes it stale.
+    let modified = exec_env.modified(dir).ok();
+    match exec_env.read_dir(dir) {
+        Ok(names) => {
+            Some(Listing { modified, names: names.iter().map(|it| normalize(it)).collect() })
+        }
+        Err(err) => {
+            log::debug!("can't list {}: {}", dir.display(), err);
+            None
+        }
+    }
+}
+
+/// File names are case-insensitive on Windows.
+fn normalize(name: &OsStr) -> OsString {
+    match cfg!(windows) {
+        true => name.to_string_lossy().to_lowercase().into(),
+        false => name.to_os_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::{testdir::TestDir, LocalEnvironment};
+
+    use super::*;
+
+    #[test]
+    fn only_dirs_with_the_name_are_candidates() {
+        let tmp = TestDir::new("path-index");
+        let root = tmp.path().to_path_buf();
+        let (a, b) = (root.join("a"), root.join("b"));
+        fs::create_dir_all(&a).unwrap();
+        fs::create_dir_all(&b).unwrap();
+        let cargo = format!("cargo{}", env::consts::EXE_SUFFIX);
+        fs::write(a.join(&cargo), "").unwrap();
+        fs::write(b.join(&cargo), "").unwrap();
+        fs::write(b.join("rustc"), "").unwrap();
+        let missing = root.join("missing");
+
+        let index = PathIndex::scan(&LocalEnvironment, vec![a.clone(), missing.clone(), b.clone()]);
+        assert_eq!(index.candidates("cargo"), [a.clone(), missing.clone(), b.clone()]);
+        assert_eq!(index.candidates("rustc"), [missing.clone(), b.clone()]);
+        assert_eq!(index.candidates("rustfmt"), vec![missing.clone()]);
+
+        fs::write(a.join("rustfmt"), "").unwrap();
+        assert_eq!(index.rescan(&LocalEnvironment).candidates("rustfmt"), [a, missing]);
+    }
+}
@@ -0,0 +1,123 @@
+//! `rust-toolchain` files, which pin the toolchain rustup uses in a directory.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use stdx::split_once;
+
+use crate::ToolchainSpec;
+
+/// The names rustup looks for, in the order it looks for them.
+pub const PIN_FILE_NAMES: &[&str] = &["rust-toolchain", "rust-toolchain.toml"];
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ToolchainPin {
+    pub file: PathBuf,
+    /// Like `nightly-2021-06-01`. `None` if the file only lists components,
+    /// or points at a custom toolchain through `path`.
+    pub channel: Option<ToolchainSpec>,
+    pub components: Vec<String>,
+    pub targets: Vec<String>,
+}
+
+/// Finds the pin which applies to `dir`: the first pin file in `dir` or its
+/// ancestors, like rustup does. An unreadable file counts as no pin.
+pub fn toolchain_pin(dir: &Path) -> Option<ToolchainPin> {
+    let file = dir
+        .ancestors()
+        .flat_map(|dir| PIN_FILE_NAMES.iter().map(move |name| dir.join(name)))
+        .find(|it| it.is_file())?;
+    let text = fs::read_to_string(&file).ok()?;
+    let mut res = parse(&text);
+    res.file = file;
+    Some(res)
+}
+
+/// Whether a change to `path` may change the pinned toolchain.
+pub fn is_pin_file(path: &Path) -> bool {
+    let name = path.file_name().and_then(|it| it.to_str()).unwrap_or_default();
+    PIN_FILE_NAMES.contains(&name)
+}
+
+/// Parses both the legacy format, which is just a channel name, and the TOML
+/// one, with a `[toolchain]` table.
+fn parse(text: &str) -> ToolchainPin {
+    let mut res = ToolchainPin {
+        file: PathBuf::new(),
+        channel: None,
+        components: Vec::new(),
+        targets: Vec::new(),
+    };
+    if !text.contains("[toolchain]") {
+        res.channel =
+            text.lines().map(str::trim).find(|it| !it.is_empty()).map(ToolchainSpec::parse);
+        return res;
+    }
+    let mut in_toolchain = false;
+    for line in text.lines() {
+        let line = line.trim();
+        if line.starts_with('[') {
+            in_toolchain = line == "[toolchain]";
+            continue;
+        }
+        let (key, value) = match split_once(line, '=') {
+            Some(it) if in_toolchain => it,
+            _ => continue,
+        };
+        let mut values = value
+            .trim()
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .split(',')
+            .map(|it| it.trim().trim_matches(|c| c == '"' || c == '\'').to_string())
+            .filter(|it| !it.is_empty());
+        match key.trim() {
+            "channel" => res.channel = values.next().map(|it| ToolchainSpec::parse(&it)),
+            "components" => res.components = values.collect(),
+            "targets" => res.targets = values.collect(),
+            _ => (),
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testdir::TestDir;
+
+    use super::*;
+
+    #[test]
+    fn parses_both_formats() {
+        assert_eq!(
+            parse("nightly-2021-06-01\n").channel,
+            Some(ToolchainSpec::parse("nightly-2021-06-01"))
+        );
+
+        let pin = parse(
+            "[toolchain]\nchannel = \"1.52.0\"\ncomponents = [ \"rustfmt\", \"rust-src\" ]\ntargets = [\"wasm32-unknown-unknown\"]\n",
+        );
+        assert_eq!(pin.channel, Some(ToolchainSpec::parse("1.52.0")));
+        assert_eq!(pin.components, ["rustfmt", "rust-src"]);
+        assert_eq!(pin.targets, ["wasm32-unknown-unknown"]);
+
+        assert_eq!(parse("[toolchain]\npath = \"/opt/rust\"\n").channel, None);
+    }
+
+    #[test]
+    fn finds_nearest_pin() {
+        let tmp = TestDir::new("pin");
+        let root = tmp.path().to_path_buf();
+        let nested = root.join("crates/foo");
+        fs::create_dir_all(&nested).unwrap();
+        fs::write(root.join("rust-toolchain.toml"), "[toolchain]\nchannel = \"stable\"\n").unwrap();
+
+        let pin = toolchain_pin(&nested).unwrap();
+        assert_eq!(pin.file, root.join("rust-toolchain.toml"));
+        assert_eq!(pin.channel, Some(ToolchainSpec::parse("stable")));
+        assert!(is_pin_file(&pin.file));
+        assert!(!is_pin_file(&root.join("Cargo.toml")));
+    }
+}
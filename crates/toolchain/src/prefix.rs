@@ -0,0 +1,97 @@
+//! A command prefix, like `nice -n19` or `nix develop -c`, put in front of
+//! every tool we run.
+
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use once_cell::sync::Lazy;
+
+use crate::{
+    locate::lookup_in_path,
+    validate::{validate_executable, Expectations, Validation},
+    DiscoveryError, Env, ErrorKind,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandPrefix {
+    program: PathBuf,
+    args: Vec<OsString>,
+}
+
+static PREFIX: Lazy<Mutex<Option<CommandPrefix>>> = Lazy::new(Default::default);
+
+/// Sets the prefix of the commands built by [`crate::ToolCommand`], `None`
+/// to run tools directly. Discovery itself still looks at, and runs, the
+/// tools without the prefix.
+///
+/// Returns whether the prefix changed.
+pub fn set_command_prefix(prefix: Option<CommandPrefix>) -> bool {
+    let mut current = PREFIX.lock().unwrap();
+    if *current == prefix {
+        return false;
+    }
+    *current = prefix;
+    true
+}
+
+pub(crate) fn command_prefix() -> Option<CommandPrefix> {
+    PREFIX.lock().unwrap().clone()
+}
+
+impl CommandPrefix {
+    /// Checks that the first word is an executable, looking it up in `PATH`
+    /// if it is a bare name, so that a typo fails here rather than on every
+    /// command. An empty prefix is `None`.
+    pub fn new(words: Vec<String>) -> Result<Option<CommandPrefix>, DiscoveryError> {
+        let mut words = words.into_iter();
+        let program = match words.next() {
+            Some(it) => it,
+            None => return Ok(None),
+        };
+        let env = Env::current();
+        let is_bare_name = Path::new(&program).components().count() == 1;
+        let path = if is_bare_name {
+            lookup_in_path(&env, &program)
+        } else {
+            Some(env.absolute(program.clone().into())).filter(|it| it.is_file())
+        };
+        let path = path.ok_or_else(|| {
+            DiscoveryError::new(ErrorKind::NotFound, &program)
+                .with_detail("the command prefix is not an executable".to_string())
+        })?;
+        let expectations = Expectations {
+            validation: Some(Validation::MetadataOnly),
+            ..Expectations::new(&program)
+        };
+        validate_executable(&path, &expectations).into_result()?;
+        Ok(Some(CommandPrefix { program: path, args: words.map(OsString::from).collect() }))
+    }
+
+    pub fn program(&self) -> &Path {
+        &self.program
+    }
+
+    pub fn args(&self) -> &[OsString] {
+        &self.args
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validates_program() {
+        assert_eq!(CommandPrefix::new(Vec::new()), Ok(None));
+
+        let rustc = crate::rustc().to_string_lossy().into_owned();
+        let prefix = CommandPrefix::new(vec![rustc, "--".to_string()]).unwrap().unwrap();
+        assert_eq!(prefix.args(), ["--"]);
+
+        let err = CommandPrefix::new(vec!["surely-not-a-prefix".to_string()]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+    }
+}
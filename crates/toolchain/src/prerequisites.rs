@@ -0,0 +1,239 @@
+//! The platform tools rustc needs to link, which rustup doesn't install.
+//!
+//! Without them, the first build fails with errors like "linker `link.exe`
+//! not found" or "linker `cc` not found", which don't say what to install.
+
+use std::{fmt, fs, path::PathBuf, process::Command};
+
+use crate::{
+    cargo_config::{config_files, config_value, unquote},
+    install::{push, Platform},
+    locate::lookup_in_path,
+    utf8_stdout, Env, SuggestedCommand,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Prerequisite {
+    /// `link.exe`, for the `*-pc-windows-msvc` targets.
+    MsvcBuildTools,
+    /// The linker and SDK on macOS.
+    XcodeCommandLineTools,
+    /// `cc`, which rustc uses as the linker on Linux and most Unixes.
+    CCompiler,
+}
+
+/// The prerequisite of the host platform, if it is missing in `env`.
+///
+/// Nothing is missing if the project configures its own linker for the
+/// host, in cargo's configuration or in the rustflags. On Windows, this is only checked if the
+/// host toolchain targets MSVC: the GNU toolchains ship their own linker.
+pub fn missing_prerequisite(env: &Env) -> Option<Prerequisite> {
+    let platform = Platform::current();
+    let host = crate::rustc_meta(&crate::rustc()).ok().map(|it| it.host);
+    if let Some(host) = &host {
+        if configured_linker(env, host).is_some() {
+            return None;
+        }
+    }
+    let res = match platform {
+        Platform::Windows => {
+            if !host?.ends_with("-msvc")
+                || lookup_in_path(env, "link").is_some()
+                || has_msvc_build_tools(env)
+            {
+                return None;
+            }
+            Prerequisite::MsvcBuildTools
+        }
+        Platform::MacOs => {
+            let mut cmd = Command::new("xcode-select");
+            cmd.arg("-p");
+            if utf8_stdout(cmd).is_ok() {
+                return None;
+            }
+            Prerequisite::XcodeCommandLineTools
+        }
+        Platform::Linux { .. } | Platform::Other => {
+            if lookup_in_path(env, "cc").is_some() {
+                return None;
+            }
+            Prerequisite::CCompiler
+        }
+    };
+    Some(res)
+}
+
+/// The linker configured for `target`, which rustc uses instead of the
+/// platform one: `-C linker` in the rustflags, `CARGO_TARGET_<TRIPLE>_LINKER`
+/// or `target.<triple>.linker` in the cargo configuration of the working
+/// directory, in the order cargo prefers them.
+fn configured_linker(env: &Env, target: &str) -> Option<String> {
+    let flags: Vec<String> = match env.var("CARGO_ENCODED_RUSTFLAGS") {
+        Some(it) => it.split('\x1f').map(str::to_string).collect(),
+        None => env
+            .var("RUSTFLAGS")
+            .unwrap_or_default()
+            .split_whitespace()
+            .map(str::to_string)
+            .collect(),
+    };
+    if let Some(linker) = linker_flag(&flags) {
+        return Some(linker);
+    }
+    let var = format!(
+        "CARGO_TARGET_{}_LINKER",
+        target.to_uppercase().replace(|c| c == '-' || c == '.', "_")
+    );
+    if let Some(linker) = env.var(&var) {
+        return Some(linker.to_string());
+    }
+    let key = format!("target.{}.linker", target);
+    config_files(env.cwd()?).iter().find_map(|file| {
+        let text = fs::read_to_string(file).ok()?;
+        config_value(&text, &key).map(|it| unquote(it).to_string())
+    })
+}
+
+/// The value of the last `-C linker=...`, which is the one rustc uses.
+fn linker_flag(flags: &[String]) -> Option<String> {
+    let mut res = None;
+    let mut iter = flags.iter();
+    while let Some(flag) = iter.next() {
+        let codegen = match flag.as_str() {
+            "-C" | "--codegen" => match iter.next() {
+                Some(it) => it.as_str(),
+                None => break,
+            },
+            _ => match flag.strip_prefix("-C").or_else(|| flag.strip_prefix("--codegen=")) {
+                Some(it) => it,
+                None => continue,
+            },
+        };
+        if let Some(linker) = codegen.strip_prefix("linker=") {
+            res = Some(linker.to_string());
+        }
+    }
+    res
+}
+
+/// Asks the Visual Studio installer, which is where rustc looks too.
+/// `link.exe` is usually not in `PATH` outside of a developer prompt.
+fn has_msvc_build_tools(env: &Env) -> bool {
+    let program_files = match env.var_os("ProgramFiles(x86)") {
+        Some(it) => PathBuf::from(it),
+        None => return false,
+    };
+    let vswhere = program_files.join("Microsoft Visual Studio/Installer/vswhere.exe");
+    let mut cmd = Command::new(vswhere);
+    cmd.args([
+        "-latest",
+        "-products",
+        "*",
+        "-requires",
+        "Microsoft.VisualStudio.Component.VC.Tools.x86.x64",
+        "-property",
+        "installationPath",
+    ]);
+    utf8_stdout(cmd).map_or(false, |it| !it.is_empty())
+}
+
+impl Prerequisite {
+    /// Ways to install the prerequisite, the preferred one first.
+    pub fn install_suggestion(self) -> Vec<SuggestedCommand> {
+        install_suggestion(self, &Platform::current())
+    }
+}
+
+fn install_suggestion(prerequisite: Prerequisite, platform: &Platform) -> Vec<SuggestedCommand> {
+    let mut res = Vec::new();
+    match prerequisite {
+        Prerequisite::MsvcBuildTools => push(
+            &mut res,
+            "Install the MSVC Build Tools with winget",
+            "winget install Microsoft.VisualStudio.2022.BuildTools --override \
+             \"--wait --passive --add Microsoft.VisualStudio.Workload.VCTools --includeRecommended\""
+                .into(),
+        ),
+        Prerequisite::XcodeCommandLineTools => {
+            push(&mut res, "Install the Xcode Command Line Tools", "xcode-select --install".into())
+        }
+        Prerequisite::CCompiler => {
+            let distro = match platform {
+                Platform::Linux { distro } => distro.as_slice(),
+                _ => &[],
+            };
+            let package_manager = distro.iter().find_map(|id| match id.as_str() {
+                "debian" | "ubuntu" => Some("sudo apt install build-essential"),
+                "fedora" | "rhel" => Some("sudo dnf install gcc"),
+                "arch" => Some("sudo pacman -S base-devel"),
+                "opensuse" | "suse" => Some("sudo zypper install gcc"),
+                "alpine" => Some("sudo apk add build-base"),
+                _ => None,
+            });
+            if let Some(command) = package_manager {
+                push(&mut res, "Install a C compiler and linker", command.into());
+            }
+        }
+    }
+    res
+}
+
+impl fmt::Display for Prerequisite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Prerequisite::MsvcBuildTools => "the MSVC Build Tools (`link.exe`)",
+            Prerequisite::XcodeCommandLineTools => "the Xcode Command Line Tools",
+            Prerequisite::CCompiler => "a C compiler (`cc`)",
+        };
+        f.write_str(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testdir::TestDir;
+
+    use super::*;
+
+    #[test]
+    fn suggestions_follow_the_distribution() {
+        let mint = Platform::Linux { distro: vec!["linuxmint".into(), "ubuntu".into()] };
+        let res = install_suggestion(Prerequisite::CCompiler, &mint);
+        assert_eq!(res[0].command, "sudo apt install build-essential");
+        let unknown = Platform::Linux { distro: vec!["gentoo".into()] };
+        assert_eq!(install_suggestion(Prerequisite::CCompiler, &unknown), []);
+        let mac = install_suggestion(Prerequisite::XcodeCommandLineTools, &Platform::MacOs);
+        assert_eq!(mac[0].command, "xcode-select --install");
+    }
+
+    #[test]
+    fn configured_linkers_are_found() {
+        let flags = |it: &str| it.split_whitespace().map(str::to_string).collect::<Vec<_>>();
+        assert_eq!(linker_flag(&flags("-C opt-level=2 -C linker=clang")).unwrap(), "clang");
+        assert_eq!(linker_flag(&flags("-Clinker=lld -Clinker=mold")).unwrap(), "mold");
+        assert_eq!(linker_flag(&flags("--codegen=linker=gcc")).unwrap(), "gcc");
+        assert_eq!(linker_flag(&flags("-C target-cpu=native -C")), None);
+
+        let mut env = Env::default();
+        env.set_var("CARGO_TARGET_X86_64_UNKNOWN_LINUX_GNU_LINKER", "clang".into());
+        assert_eq!(configured_linker(&env, "x86_64-unknown-linux-gnu").unwrap(), "clang");
+        env.set_var("RUSTFLAGS", "-C linker=mold".into());
+        assert_eq!(configured_linker(&env, "x86_64-unknown-linux-gnu").unwrap(), "mold");
+        assert_eq!(configured_linker(&env, "aarch64-unknown-linux-gnu").unwrap(), "mold");
+    }
+
+    #[test]
+    fn configured_linkers_are_read_from_cargo_config() {
+        let tmp = TestDir::new("prerequisites");
+        fs::create_dir_all(tmp.path().join(".cargo")).unwrap();
+        fs::write(
+            tmp.path().join(".cargo/config.toml"),
+            "[target.x86_64-pc-windows-msvc]\nlinker = \"rust-lld.exe\" # no MSVC\n",
+        )
+        .unwrap();
+        let mut env = Env::default();
+        env.set_cwd(tmp.path().to_path_buf());
+        assert_eq!(configured_linker(&env, "x86_64-pc-windows-msvc").unwrap(), "rust-lld.exe");
+        assert_eq!(configured_linker(&env, "x86_64-pc-windows-gnu"), None);
+    }
+}
@@ -0,0 +1,83 @@
+//! Protection against executables which re-enter discovery.
+//!
+//! A `$RUSTC` wrapper which runs rust-analyzer (or anything else using this
+//! crate) makes validation spawn the wrapper, which discovers `rustc`, which
+//! spawns the wrapper again, and so on until the machine runs out of
+//! processes. Every executable we validate or run with a
+//! [`crate::ToolCommand`] gets the current depth in its environment, so that
+//! the nested discoveries can notice and stop.
+
+use std::{env, ffi::OsStr, path::Path, process::Command};
+
+use crate::{DiscoveryError, ErrorKind};
+
+const DEPTH_VAR: &str = "RA_TOOLCHAIN_DISCOVERY_DEPTH";
+
+/// A legitimate wrapper may run the real tool through a couple of levels of
+/// discovery, but not more.
+const MAX_DEPTH: u32 = 4;
+
+/// Fails if `path` is the running executable, or if discovery is nested too
+/// deep in the wrappers which spawned this process.
+pub(crate) fn check(tool: &str, path: &Path) -> Result<(), DiscoveryError> {
+    let error = |detail: String| {
+        DiscoveryError::new(ErrorKind::WrapperCycle, tool)
+            .with_path(path.to_path_buf())
+            .with_detail(detail)
+    };
+    if is_current_exe(path) {
+        return Err(error("it is the running executable".to_string()));
+    }
+    let depth = depth(env::var_os(DEPTH_VAR).as_deref());
+    if depth >= MAX_DEPTH {
+        return Err(error(format!(
+            "discovery is nested {} levels deep, a wrapper likely runs rust-analyzer",
+            depth
+        )));
+    }
+    Ok(())
+}
+
+/// Makes `cmd` carry the depth of the discovery spawning it.
+pub(crate) fn mark(cmd: &mut Command) {
+    let depth = depth(env::var_os(DEPTH_VAR).as_deref());
+    cmd.env(DEPTH_VAR, (depth + 1).to_string());
+}
+
+fn depth(value: Option<&OsStr>) -> u32 {
+    value.and_then(|it| it.to_str()).and_then(|it| it.parse().ok()).unwrap_or(0)
+}
+
+fn is_current_exe(path: &Path) -> bool {
+    let current = match env::current_exe().and_then(|it| it.canonicalize()) {
+        Ok(it) => it,
+        Err(_) => return false,
+    };
+    path.canonicalize().map_or(false, |it| it == current)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn running_executable_is_a_cycle() {
+        let exe = env::current_exe().unwrap();
+        assert_eq!(check("rustc", &exe).unwrap_err().kind(), ErrorKind::WrapperCycle);
+        assert_eq!(check("rustc", &crate::rustc()), Ok(()));
+    }
+
+    #[test]
+    fn tool_commands_carry_the_depth() {
+        let cmd = crate::ToolCommand::new("cargo".into()).to_command();
+        let depth = cmd.get_envs().find(|(key, _)| *key == DEPTH_VAR).and_then(|it| it.1);
+        assert!(depth.is_some());
+    }
+
+    #[test]
+    fn parses_depth() {
+        assert_eq!(depth(None), 0);
+        assert_eq!(depth(Some(OsStr::new("3"))), 3);
+        assert_eq!(depth(Some(OsStr::new("many"))), 0);
+    }
+}
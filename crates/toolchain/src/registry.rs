@@ -0,0 +1,423 @@
+//! The paths behind [`crate::cargo`] and friends, each discovered the first
+//! time it is asked for.
+//!
+//! Discovery of a tool may spawn it, and most sessions never need some of the
+//! tools (rustfmt isn't looked for until the first formatting request), so
+//! nothing is probed up front.
+//!
+//! [`try_locate`] memoizes full [`locate`] results the same way, but runs
+//! the discovery on a background thread.
+//!
+//! The rustup proxies resolved for [`crate::set_bypass_rustup_proxies`] live
+//! here too, so that they are forgotten together with the tools.
+//!
+//! [`discovery_metrics`] counts how often all of this had to be redone, for
+//! rust-analyzer's status page: a server which keeps spawning processes or
+//! missing the caches has something in its environment which keeps changing.
+
+use std::{
+    fmt, panic,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    task::Poll,
+    thread,
+    time::{Duration, Instant},
+};
+
+use once_cell::sync::{Lazy, OnceCell};
+use rustc_hash::FxHashMap;
+
+use crate::{
+    execution::{execution_environment, ExecutionEnvironment},
+    locate,
+    locate::get_path_for_executable,
+    path_index::PathIndex,
+    rustup_proxy::{ProxyKey, ResolvedProxy},
+    DiscoveryError, ErrorKind, FoundExecutable,
+};
+
+type Located = Result<FoundExecutable, DiscoveryError>;
+type Indexed = (Arc<dyn ExecutionEnvironment>, Arc<PathIndex>);
+
+#[derive(Debug, Default)]
+struct Registry {
+    cargo: OnceCell<PathBuf>,
+    rustc: OnceCell<PathBuf>,
+    rustup: OnceCell<PathBuf>,
+    rustfmt: OnceCell<PathBuf>,
+    /// `None` while the discovery is running.
+    located: Mutex<FxHashMap<String, Option<Located>>>,
+    /// `None` for programs which are not proxies.
+    proxies: Mutex<FxHashMap<ProxyKey, Option<ResolvedProxy>>>,
+    /// One index per execution environment, as tests scope their own, see
+    /// [`crate::execution::with_scoped`].
+    path_index: Mutex<Vec<Indexed>>,
+}
+
+/// Replaced as a whole on invalidation, so that a discovery which is still
+/// running can't store a stale path in the new registry.
+static REGISTRY: Lazy<Mutex<Arc<Registry>>> = Lazy::new(Default::default);
+
+/// What discovery did since the start of the process. Unlike the caches, the
+/// counters survive [`invalidate_tools`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DiscoveryMetrics {
+    /// Probes like `rustc --version` and `rustup which cargo`.
+    pub spawned: u64,
+    pub cache_hits: u64,
+    pub cache_misses: u64,
+    pub invalidations: u64,
+    /// The time spent looking for tools, summed over all threads.
+    pub discovery_time: Duration,
+}
+
+struct Counters {
+    spawned: AtomicU64,
+    cache_hits: AtomicU64,
+    cache_misses: AtomicU64,
+    invalidations: AtomicU64,
+    discovery_micros: AtomicU64,
+}
+
+static COUNTERS: Counters = Counters {
+    spawned: AtomicU64::new(0),
+    cache_hits: AtomicU64::new(0),
+    cache_misses: AtomicU64::new(0),
+    invalidations: AtomicU64::new(0),
+    discovery_micros: AtomicU64::new(0),
+};
+
+pub fn discovery_metrics() -> DiscoveryMetrics {
+    let load = |it: &AtomicU64| it.load(Ordering::Relaxed);
+    DiscoveryMetrics {
+        spawned: load(&COUNTERS.spawned),
+        cache_hits: load(&COUNTERS.cache_hits),
+        cache_misses: load(&COUNTERS.cache_misses),
+        invalidations: load(&COUNTERS.invalidations),
+        discovery_time: Duration::from_micros(load(&COUNTERS.discovery_micros)),
+    }
+}
+
+/// Called for every process spawned to learn about the environment.
+pub(crate) fn count_spawn() {
+    COUNTERS.spawned.fetch_add(1, Ordering::Relaxed);
+}
+
+fn count_lookup(hit: bool) {
+    let counter = if hit { &COUNTERS.cache_hits } else { &COUNTERS.cache_misses };
+    counter.fetch_add(1, Ordering::Relaxed);
+}
+
+fn timed<T>(discover: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let res = discover();
+    let micros = start.elapsed().as_micros() as u64;
+    COUNTERS.discovery_micros.fetch_add(micros, Ordering::Relaxed);
+    res
+}
+
+impl fmt::Display for DiscoveryMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} processes spawned, {} cache hits, {} misses, {} invalidations, {}ms discovering",
+            self.spawned,
+            self.cache_hits,
+            self.cache_misses,
+            self.invalidations,
+            self.discovery_time.as_millis()
+        )
+    }
+}
+
+impl Registry {
+    fn slot(&self, tool: &str) -> Option<&OnceCell<PathBuf>> {
+        let res = match tool {
+            "cargo" => &self.cargo,
+            "rustc" => &self.rustc,
+            "rustup" => &self.rustup,
+            "rustfmt" => &self.rustfmt,
+            _ => return None,
+        };
+        Some(res)
+    }
+
+    fn get(&self, tool: &'static str, discover: impl FnOnce() -> PathBuf) -> PathBuf {
+        match self.slot(tool) {
+            Some(slot) => slot.get_or_init(discover).clone(),
+            None => discover(),
+        }
+    }
+
+    fn discovered(&self) -> Vec<&'static str> {
+        ["cargo", "rustc", "rustup", "rustfmt"]
+            .iter()
+            .copied()
+            .filter(|tool| self.slot(tool).map_or(false, |it| it.get().is_some()))
+            .collect()
+    }
+
+    fn try_locate(self: &Arc<Self>, tool: &str, locate: fn(&str) -> Located) -> Poll<Located> {
+        let mut located = self.located.lock().unwrap();
+        match located.get(tool) {
+            Some(Some(res)) => {
+                count_lookup(true);
+                return Poll::Ready(res.clone());
+            }
+            Some(None) => return Poll::Pending,
+            None => count_lookup(false),
+        }
+        let registry = Arc::clone(self);
+        let tool = tool.to_string();
+        let spawned = thread::Builder::new().name(format!("locate {}", tool)).spawn({
+            let tool = tool.clone();
+            move || {
+                let res = timed(|| locate_catching(&tool, locate));
+                registry.located.lock().unwrap().insert(tool, Some(res));
+            }
+        });
+        match spawned {
+            Ok(_) => {
+                located.insert(tool, None);
+                Poll::Pending
+            }
+            // Not much we can do without threads but to block.
+            Err(_) => {
+                let res = timed(|| locate(&tool));
+                located.insert(tool, Some(res.clone()));
+                Poll::Ready(res)
+            }
+        }
+    }
+}
+
+/// `locate(tool)`, with a panic turned into an error. The result is stored
+/// either way, a discovery which never stores one would leave the tool
+/// pending for the rest of the session.
+fn locate_catching(tool: &str, locate: fn(&str) -> Located) -> Located {
+    panic::catch_unwind(|| locate(tool)).unwrap_or_else(|payload| {
+        let detail = match payload.downcast_ref::<&str>() {
+            Some(it) => it.to_string(),
+            None => payload.downcast_ref::<String>().cloned().unwrap_or_default(),
+        };
+        Err(DiscoveryError::new(ErrorKind::DiscoveryPanicked, tool).with_detail(detail))
+    })
+}
+
+pub(crate) fn path_for(tool: &'static str) -> PathBuf {
+    // Don't hold the lock while discovering, other tools may be asked for
+    // concurrently. Two threads asking for the same tool wait for one
+    // discovery.
+    let registry = REGISTRY.lock().unwrap().clone();
+    let mut hit = true;
+    let res = registry.get(tool, || {
+        hit = false;
+        timed(|| get_path_for_executable(tool))
+    });
+    count_lookup(hit);
+    res
+}
+
+/// Like [`locate`], but never blocks: the first call starts the discovery
+/// on a background thread and returns [`Poll::Pending`], later calls return
+/// the result once it is there.
+///
+/// Meant for code which can do with "not known yet", like status reports,
+/// and can't afford to wait for the tool to be run.
+pub fn try_locate(tool: &str) -> Poll<Result<FoundExecutable, DiscoveryError>> {
+    let registry = REGISTRY.lock().unwrap().clone();
+    registry.try_locate(tool, locate)
+}
+
+/// Resolves a rustup proxy once per key. Two threads asking for the same key
+/// at once may both resolve it.
+pub(crate) fn resolved_proxy(
+    key: ProxyKey,
+    resolve: impl FnOnce(&ProxyKey) -> Option<ResolvedProxy>,
+) -> Option<ResolvedProxy> {
+    let registry = REGISTRY.lock().unwrap().clone();
+    if let Some(res) = registry.proxies.lock().unwrap().get(&key) {
+        count_lookup(true);
+        return res.clone();
+    }
+    count_lookup(false);
+    let res = resolve(&key);
+    registry.proxies.lock().unwrap().insert(key, res.clone());
+    res
+}
+
+/// The index of `dirs`, the `PATH` directories, as seen by the current
+/// [`ExecutionEnvironment`]. Scanned on first use, and again whenever `PATH`
+/// changes. With `rescan`, the directories which changed since they were
+/// listed are listed again.
+pub(crate) fn path_index(dirs: Vec<PathBuf>, rescan: bool) -> Arc<PathIndex> {
+    let exec_env = execution_environment();
+    let registry = REGISTRY.lock().unwrap().clone();
+    let cached = registry
+        .path_index
+        .lock()
+        .unwrap()
+        .iter()
+        .find(|(it, _)| same_env(it, &exec_env))
+        .map(|(_, it)| it.clone());
+    // Listing a directory may take a while, so it's done without the lock.
+    // Threads racing to do it list the same directories.
+    let res = match cached {
+        Some(it) if it.dirs() == dirs.as_slice() && !rescan => return it,
+        Some(it) if it.dirs() == dirs.as_slice() => Arc::new(timed(|| it.rescan(&*exec_env))),
+        _ => Arc::new(timed(|| PathIndex::scan(&*exec_env, dirs))),
+    };
+    let mut path_index = registry.path_index.lock().unwrap();
+    path_index.retain(|(it, _)| !same_env(it, &exec_env));
+    path_index.push((exec_env, res.clone()));
+    res
+}
+
+fn same_env(lhs: &Arc<dyn ExecutionEnvironment>, rhs: &Arc<dyn ExecutionEnvironment>) -> bool {
+    // Not `Arc::ptr_eq`, which also compares the vtables.
+    Arc::as_ptr(lhs) as *const () == Arc::as_ptr(rhs) as *const ()
+}
+
+/// Forgets the discovered paths and [`try_locate`] results, so that the next
+/// request for each tool looks for it again.
+///
+/// This happens on its own when the client environment changes, and when a
+/// [`crate::ToolchainMonitor`] notices a change.
+pub fn invalidate_tools() {
+    COUNTERS.invalidations.fetch_add(1, Ordering::Relaxed);
+    *REGISTRY.lock().unwrap() = Default::default();
+}
+
+/// The tools which were discovered since the last invalidation.
+pub fn discovered_tools() -> Vec<&'static str> {
+    REGISTRY.lock().unwrap().discovered()
+}
+
+/// The directories of the tools discovered so far, in the order of
+/// [`discovered_tools`]. Tools which were not found anywhere, and ended up
+/// as a bare name, have no directory.
+pub(crate) fn discovered_dirs() -> Vec<PathBuf> {
+    let registry = REGISTRY.lock().unwrap().clone();
+    let mut res: Vec<PathBuf> = Vec::new();
+    for tool in registry.discovered() {
+        let dir = registry.slot(tool).and_then(|it| it.get()).and_then(|it| it.parent());
+        match dir {
+            Some(dir) if dir.is_absolute() && !res.iter().any(|it| it == dir) => {
+                res.push(dir.to_path_buf())
+            }
+            _ => (),
+        }
+    }
+    res
+}
+
+/// The tools with a cached path, from [`crate::cargo`] and friends, from
+/// [`try_locate`] and from the resolved rustup proxies. Bare names, used when
+/// a tool wasn't found, are left out: there is nothing to revalidate.
+pub(crate) fn cached_paths() -> Vec<(String, PathBuf)> {
+    let registry = REGISTRY.lock().unwrap().clone();
+    let mut res: Vec<(String, PathBuf)> = registry
+        .discovered()
+        .into_iter()
+        .filter_map(|tool| Some((tool.to_string(), registry.slot(tool)?.get()?.clone())))
+        .collect();
+    for (tool, located) in registry.located.lock().unwrap().iter() {
+        if let Some(Ok(found)) = located {
+            res.push((tool.clone(), found.path.clone()));
+        }
+    }
+    for (key, resolved) in registry.proxies.lock().unwrap().iter() {
+        if let (Some(tool), Some(resolved)) = (key.proxy.file_stem(), resolved) {
+            res.push((tool.to_string_lossy().into_owned(), resolved.path.clone()));
+        }
+    }
+    res.retain(|(_, path)| path.is_absolute());
+    res.sort();
+    res.dedup();
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, time::Duration};
+
+    use crate::Provenance;
+
+    use super::*;
+
+    #[test]
+    fn tools_are_discovered_once_on_demand() {
+        let registry = Registry::default();
+        assert!(registry.discovered().is_empty());
+
+        let calls = Cell::new(0);
+        let discover = || {
+            calls.set(calls.get() + 1);
+            PathBuf::from("/usr/bin/rustfmt")
+        };
+        assert_eq!(registry.get("rustfmt", discover), PathBuf::from("/usr/bin/rustfmt"));
+        assert_eq!(registry.get("rustfmt", discover), PathBuf::from("/usr/bin/rustfmt"));
+        assert_eq!(calls.get(), 1);
+        assert_eq!(registry.discovered(), ["rustfmt"]);
+
+        registry.get("clippy-driver", discover);
+        registry.get("clippy-driver", discover);
+        assert_eq!(calls.get(), 3);
+    }
+
+    #[test]
+    fn try_locate_is_pending_until_discovered() {
+        fn slow_locate(tool: &str) -> Located {
+            thread::sleep(Duration::from_millis(50));
+            match tool {
+                "cargo" => Ok(FoundExecutable {
+                    path: "/usr/bin/cargo".into(),
+                    provenance: Provenance::Path,
+                    shim: None,
+                    version: None,
+                }),
+                _ => Err(DiscoveryError::new(ErrorKind::NotFound, "rustfmt")),
+            }
+        }
+
+        let before = discovery_metrics();
+        let registry = Arc::new(Registry::default());
+        assert_eq!(registry.try_locate("cargo", slow_locate), Poll::Pending);
+        assert_eq!(registry.try_locate("rustfmt", slow_locate), Poll::Pending);
+        let wait = |tool| loop {
+            if let Poll::Ready(res) = registry.try_locate(tool, slow_locate) {
+                return res;
+            }
+            thread::sleep(Duration::from_millis(10));
+        };
+        assert_eq!(wait("cargo").unwrap().path, PathBuf::from("/usr/bin/cargo"));
+        assert_eq!(wait("rustfmt").unwrap_err().kind(), ErrorKind::NotFound);
+
+        // Other tests may be discovering at the same time.
+        let after = discovery_metrics();
+        assert!(after.cache_misses >= before.cache_misses + 2);
+        assert!(after.cache_hits >= before.cache_hits + 2);
+        assert!(after.discovery_time >= before.discovery_time + Duration::from_millis(100));
+    }
+
+    #[test]
+    fn panicking_discovery_is_an_error() {
+        fn panicking_locate(_: &str) -> Located {
+            panic!("boom")
+        }
+
+        let registry = Arc::new(Registry::default());
+        assert_eq!(registry.try_locate("cargo", panicking_locate), Poll::Pending);
+        let err = loop {
+            if let Poll::Ready(res) = registry.try_locate("cargo", panicking_locate) {
+                break res.unwrap_err();
+            }
+            thread::sleep(Duration::from_millis(10));
+        };
+        assert_eq!(err.kind(), ErrorKind::DiscoveryPanicked);
+        assert_eq!(err.to_string(), "E0116 DiscoveryPanicked: looking for cargo panicked: boom");
+    }
+}
@@ -0,0 +1,219 @@
+//! Fixes for a toolchain which is installed but not where the server looks.
+//!
+//! The usual culprit is `~/.cargo/bin` missing from `PATH`: either the editor
+//! was started from a desktop launcher and doesn't get the login shell's
+//! `PATH`, or rustup was installed with `--no-modify-path` and no profile adds
+//! it. Comparing the server's environment with the login shell's tells the
+//! two apart.
+
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use crate::{install_suggestion, locate_in, Env, LoginShell, ShellKind, SuggestedCommand};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RepairSuggestion {
+    /// What's wrong, in a sentence.
+    pub problem: String,
+    pub repair: Repair,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Repair {
+    /// Add `line` at the end of `file`, a shell profile.
+    AppendLine { file: PathBuf, line: String },
+    /// Run a command in a terminal.
+    Run(SuggestedCommand),
+    /// Change a rust-analyzer setting, like `rust-analyzer.toolchain.loginShell`.
+    Setting { key: String, value: String },
+}
+
+/// What to do about the toolchain missing from `env`, empty if there is
+/// nothing to repair.
+///
+/// `shell` is only called if its environment matters, as running the login
+/// shell may take a while, see [`LoginShell::capture_env`].
+pub fn repair_suggestions(
+    env: &Env,
+    shell: impl FnOnce() -> Option<(LoginShell, Vec<(String, String)>)>,
+) -> Vec<RepairSuggestion> {
+    let cargo_bin = match env.cargo_home() {
+        Some(it) => it.join("bin"),
+        None => return Vec::new(),
+    };
+    if env.path_dirs().contains(&cargo_bin) {
+        return Vec::new();
+    }
+    let installed = cargo_bin.join("cargo").with_extension(std::env::consts::EXE_EXTENSION);
+    if !installed.is_file() {
+        if locate_in(env, "cargo").is_ok() {
+            return Vec::new();
+        }
+        let problem = "cargo is not installed".to_string();
+        return install_suggestion("cargo")
+            .into_iter()
+            .map(|it| RepairSuggestion { problem: problem.clone(), repair: Repair::Run(it) })
+            .collect();
+    }
+
+    let shell = shell();
+    let shell_path = shell.as_ref().map(|(_, vars)| {
+        let mut shell_env = Env::default();
+        for (key, value) in vars {
+            shell_env.set_var(key, value.into());
+        }
+        shell_env.path_dirs()
+    });
+    if shell_path.map_or(false, |it| it.contains(&cargo_bin)) {
+        return vec![RepairSuggestion {
+            problem: format!(
+                "{} is in the PATH of your login shell, but the editor was started without it",
+                cargo_bin.display()
+            ),
+            repair: Repair::Setting {
+                key: "rust-analyzer.toolchain.loginShell".to_string(),
+                value: "true".to_string(),
+            },
+        }];
+    }
+
+    let problem = format!(
+        "{} is not in PATH, rustup was likely installed with --no-modify-path",
+        cargo_bin.display()
+    );
+    let home = env.home_dir();
+    let mut res = Vec::new();
+    if let Some((shell, _)) = &shell {
+        if let Some(home) = &home {
+            let cargo_home = cargo_bin.parent().unwrap_or(&cargo_bin);
+            let (file, line) = profile_line(shell.kind, home, cargo_home);
+            res.push(RepairSuggestion {
+                problem: problem.clone(),
+                repair: Repair::AppendLine { file, line },
+            });
+        }
+    }
+    let installer = SuggestedCommand {
+        description: "re-run the rustup installer, letting it modify PATH".to_string(),
+        command: if cfg!(windows) {
+            "rustup-init.exe -y".to_string()
+        } else {
+            "curl --proto '=https' --tlsv1.2 -sSf https://sh.rustup.rs | sh -s -- -y".to_string()
+        },
+    };
+    res.push(RepairSuggestion { problem, repair: Repair::Run(installer) });
+    res
+}
+
+/// The profile of `kind` which login shells read, and the line which puts
+/// `cargo_home`'s `bin` in `PATH` there.
+fn profile_line(kind: ShellKind, home: &Path, cargo_home: &Path) -> (PathBuf, String) {
+    let in_home = |var: &str| match cargo_home.strip_prefix(home) {
+        Ok(rest) => format!("{}/{}", var, rest.display()),
+        Err(_) => cargo_home.display().to_string(),
+    };
+    match kind {
+        // What the rustup installer adds.
+        ShellKind::Posix => {
+            let bash_profile = home.join(".bash_profile");
+            let file = if bash_profile.is_file() { bash_profile } else { home.join(".profile") };
+            (file, format!(". \"{}/env\"", in_home("$HOME")))
+        }
+        ShellKind::Zsh => (home.join(".zprofile"), format!("source \"{}/env\"", in_home("$HOME"))),
+        ShellKind::Fish => (
+            home.join(".config/fish/config.fish"),
+            format!("fish_add_path \"{}/bin\"", in_home("$HOME")),
+        ),
+        ShellKind::Nushell => (
+            home.join(".config/nushell/env.nu"),
+            format!("$env.PATH = ($env.PATH | prepend \"{}/bin\")", cargo_home.display()),
+        ),
+        ShellKind::PowerShell => (
+            home.join("Documents/PowerShell/Microsoft.PowerShell_profile.ps1"),
+            format!("$env:Path = \"{}\\bin;$env:Path\"", in_home("$env:USERPROFILE")),
+        ),
+    }
+}
+
+impl fmt::Display for RepairSuggestion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: ", self.problem)?;
+        match &self.repair {
+            Repair::AppendLine { file, line } => {
+                write!(f, "add `{}` to {}", line, file.display())
+            }
+            Repair::Run(command) => write!(f, "{}", command),
+            Repair::Setting { key, value } => write!(f, "set `{}` to `{}`", key, value),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use crate::testdir::TestDir;
+
+    use super::*;
+
+    fn fake_home(name: &str) -> (TestDir, Env, PathBuf) {
+        let tmp = TestDir::new(&format!("repair-{}", name));
+        let home = tmp.path().to_path_buf();
+        let cargo_bin = home.join(".cargo/bin");
+        fs::create_dir_all(&cargo_bin).unwrap();
+        let cargo = cargo_bin.join("cargo").with_extension(env::consts::EXE_EXTENSION);
+        fs::write(cargo, "").unwrap();
+
+        let mut env = Env::default();
+        env.set_var("HOME", home.clone().into());
+        env.set_var("USERPROFILE", home.clone().into());
+        env.set_var("PATH", "/usr/bin".into());
+        (tmp, env, cargo_bin)
+    }
+
+    fn zsh() -> LoginShell {
+        LoginShell { kind: ShellKind::Zsh, path: "/bin/zsh".into() }
+    }
+
+    #[test]
+    fn editor_without_login_path_gets_setting() {
+        let (_tmp, env, cargo_bin) = fake_home("launcher");
+        let shell_path = env::join_paths(&[cargo_bin, "/usr/bin".into()]).unwrap();
+        let shell_vars = vec![("PATH".to_string(), shell_path.to_string_lossy().into_owned())];
+        let res = repair_suggestions(&env, || Some((zsh(), shell_vars)));
+        assert_eq!(res.len(), 1);
+        assert_eq!(
+            res[0].repair,
+            Repair::Setting {
+                key: "rust-analyzer.toolchain.loginShell".to_string(),
+                value: "true".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn missing_profile_line_is_suggested() {
+        let (_tmp, env, cargo_bin) = fake_home("no-modify-path");
+        let shell_vars = vec![("PATH".to_string(), "/usr/bin".to_string())];
+        let res = repair_suggestions(&env, || Some((zsh(), shell_vars)));
+        let home = cargo_bin.parent().unwrap().parent().unwrap();
+        assert_eq!(
+            res[0].repair,
+            Repair::AppendLine {
+                file: home.join(".zprofile"),
+                line: "source \"$HOME/.cargo/env\"".to_string()
+            }
+        );
+        assert!(matches!(res[1].repair, Repair::Run(_)));
+    }
+
+    #[test]
+    fn nothing_to_repair_when_in_path() {
+        let (_tmp, mut env, cargo_bin) = fake_home("healthy");
+        env.set_var("PATH", cargo_bin.into());
+        let res = repair_suggestions(&env, || panic!("the shell isn't needed"));
+        assert_eq!(res, []);
+    }
+}
@@ -0,0 +1,183 @@
+//! Rechecks the cached tool paths during long sessions.
+//!
+//! [`crate::ToolchainMonitor`] only watches `cargo`, `rustc` and `rustfmt`,
+//! and only notices a change of file. A toolchain deleted with `rustup
+//! toolchain uninstall`, or upgraded behind a rustup proxy which stays the
+//! same file, would otherwise leave the caches pointing at a path which
+//! fails with an unhelpful "No such file or directory", or at another
+//! version than the status page says.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::mpsc::{self, RecvTimeoutError, Sender},
+    time::Duration,
+};
+
+use rustc_hash::FxHashMap;
+
+use crate::{execution::execution_environment, utf8_stdout, Fingerprint, ToolchainEvent, Version};
+
+/// Every how many checks the tools are run with `--version`. The other
+/// checks only `stat` them.
+const VERSION_EVERY: u32 = 10;
+
+/// Revalidates the cached paths in the background, reporting stale ones to
+/// a callback and invalidating the caches. The spawned thread is shut down
+/// when this struct is dropped.
+#[derive(Debug)]
+pub struct Revalidator {
+    // XXX: drop order is significant
+    _stop: Sender<()>,
+    _thread: jod_thread::JoinHandle,
+}
+
+impl Revalidator {
+    /// Checks the cached paths every `interval`, calling `sender` for each
+    /// stale one. A zero `interval` would check in a busy loop, and is
+    /// rounded up to a second.
+    pub fn spawn(interval: Duration, sender: Box<dyn Fn(ToolchainEvent) + Send>) -> Revalidator {
+        let interval = interval.max(Duration::from_secs(1));
+        let (stop, receiver) = mpsc::channel();
+        let thread = jod_thread::spawn(move || {
+            let mut known = Known::default();
+            let mut until_version = VERSION_EVERY;
+            // Anything but a timeout means the revalidator was dropped.
+            while let Err(RecvTimeoutError::Timeout) = receiver.recv_timeout(interval) {
+                until_version -= 1;
+                let run_tools = until_version == 0;
+                if run_tools {
+                    until_version = VERSION_EVERY;
+                }
+                let events = known.check(crate::registry::cached_paths(), run_tools);
+                if events.is_empty() {
+                    continue;
+                }
+                crate::invalidate_tools();
+                known = Known::default();
+                for event in events {
+                    sender(event);
+                }
+            }
+        });
+        Revalidator { _stop: stop, _thread: thread }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Known {
+    tools: FxHashMap<(String, PathBuf), KnownTool>,
+}
+
+#[derive(Debug)]
+struct KnownTool {
+    fingerprint: Option<Fingerprint>,
+    /// `None` until the first check which runs the tools.
+    version: Option<Version>,
+}
+
+impl Known {
+    fn check(&mut self, cached: Vec<(String, PathBuf)>, run_tools: bool) -> Vec<ToolchainEvent> {
+        let exec_env = execution_environment();
+        let mut res = Vec::new();
+        let mut tools = FxHashMap::default();
+        for key in cached {
+            let (tool, path) = &key;
+            if !exec_env.is_file(path) {
+                res.push(ToolchainEvent::ExecutableRemoved {
+                    tool: tool.clone(),
+                    path: path.clone(),
+                });
+                continue;
+            }
+            let fingerprint = Fingerprint::of(path).ok();
+            let mut known = match self.tools.remove(&key) {
+                Some(it) if it.fingerprint != fingerprint => {
+                    res.push(ToolchainEvent::ExecutableReplaced {
+                        tool: tool.clone(),
+                        path: path.clone(),
+                    });
+                    continue;
+                }
+                Some(it) => it,
+                None => KnownTool { fingerprint, version: None },
+            };
+            if run_tools {
+                let version = version_of(path);
+                match (&known.version, &version) {
+                    (Some(old), Some(new)) if old != new => {
+                        res.push(ToolchainEvent::VersionChanged {
+                            tool: tool.clone(),
+                            old: old.clone(),
+                            new: new.clone(),
+                        });
+                        continue;
+                    }
+                    _ => (),
+                }
+                known.version = version.or(known.version);
+            }
+            tools.insert(key, known);
+        }
+        self.tools = tools;
+        res
+    }
+}
+
+/// Run in the environment of the tools, which may pick another toolchain
+/// behind a rustup proxy than the one of the server.
+fn version_of(path: &Path) -> Option<Version> {
+    let mut cmd = crate::interpreter::command(path);
+    cmd.arg("--version");
+    Version::parse_output(&utf8_stdout(cmd).ok()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::testdir::TestDir;
+
+    use super::*;
+
+    #[test]
+    fn stale_paths_are_reported() {
+        let tmp = TestDir::new("revalidate");
+        let dir = tmp.path().to_path_buf();
+        fs::create_dir_all(&dir).unwrap();
+        let (cargo, rustc) = (dir.join("cargo"), dir.join("rustc"));
+        fs::write(&cargo, "").unwrap();
+        fs::write(&rustc, "").unwrap();
+        let cached =
+            || vec![("cargo".to_string(), cargo.clone()), ("rustc".to_string(), rustc.clone())];
+
+        let mut known = Known::default();
+        assert_eq!(known.check(cached(), false), []);
+        assert_eq!(known.check(cached(), false), []);
+
+        fs::write(&cargo, "a bigger cargo").unwrap();
+        fs::remove_file(&rustc).unwrap();
+        assert_eq!(
+            known.check(cached(), false),
+            [
+                ToolchainEvent::ExecutableReplaced {
+                    tool: "cargo".to_string(),
+                    path: cargo.clone()
+                },
+                ToolchainEvent::ExecutableRemoved {
+                    tool: "rustc".to_string(),
+                    path: rustc.clone()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn real_rustc_keeps_its_version() {
+        let rustc = crate::locate("rustc").unwrap().path;
+        let cached = || vec![("rustc".to_string(), rustc.clone())];
+        let mut known = Known::default();
+        assert_eq!(known.check(cached(), true), []);
+        assert!(known.tools.values().all(|it| it.version.is_some()));
+        assert_eq!(known.check(cached(), true), []);
+    }
+}
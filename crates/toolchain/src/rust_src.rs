@@ -0,0 +1,188 @@
+//! The sources of the standard library, as installed by the `rust-src`
+//! component.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    cargo_config::{config_value, unquote},
+    rustc_meta, utf8_stdout, DiscoveryError, Env, ErrorKind, ToolCommand, ToolchainSpec, Version,
+};
+
+/// The standard library sources in `sysroot`, if installed.
+///
+/// The sysroot of a local build linked with `rustup toolchain link`, like
+/// `build/x86_64-unknown-linux-gnu/stage1`, may not have them, in which case
+/// the sources of the checkout are used.
+pub fn rust_src(sysroot: &Path) -> Option<PathBuf> {
+    // Try the new path first since the old one still exists.
+    let layout = |root: PathBuf| -> Option<PathBuf> {
+        ["library", "src"].iter().map(|it| root.join(it)).find(|it| it.is_dir())
+    };
+    layout(sysroot.join("lib/rustlib/src/rust")).or_else(|| {
+        let checkout = checkout_of(sysroot)?;
+        layout(checkout)
+    })
+}
+
+/// The rust checkout `sysroot` was built in, if it's a stage of a local build.
+fn checkout_of(sysroot: &Path) -> Option<PathBuf> {
+    let stage = sysroot.file_name()?.to_str()?;
+    if !stage.starts_with("stage") {
+        return None;
+    }
+    let build = sysroot.parent()?.parent()?;
+    let checkout = build.parent()?;
+    match checkout.join("x.py").is_file() {
+        true => Some(checkout.to_path_buf()),
+        false => None,
+    }
+}
+
+/// The standard library sources of `toolchain`, which needn't be the default
+/// one. Needed when a project pins a toolchain other than the one rustup
+/// picks in the current directory.
+pub fn rust_src_for(toolchain: &ToolchainSpec) -> io::Result<PathBuf> {
+    let mut cmd = ToolCommand::rustup_run(toolchain, "rustc");
+    cmd.args(["--print", "sysroot"]);
+    let sysroot = PathBuf::from(utf8_stdout(cmd.to_command())?);
+    rust_src(&sysroot).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!(
+                "rust-src is not installed for the {} toolchain, \
+                 try `rustup component add rust-src --toolchain {}`",
+                toolchain, toolchain
+            ),
+        )
+    })
+}
+
+/// The standard library sources `RUST_SRC_PATH` points to, which take
+/// precedence over the ones in the sysroot. `None` if the variable isn't
+/// set.
+///
+/// The directory has to contain `core` and `std`, in the current `library`
+/// layout or the old `src` one. If the version of the sources can be told,
+/// and differs from the one of `rustc`, that's logged as a warning: the
+/// sources are used anyway, but analysis of std may be off.
+pub fn rust_src_from_env(env: &Env, rustc: &Path) -> Option<Result<PathBuf, DiscoveryError>> {
+    let var = "RUST_SRC_PATH";
+    let path = PathBuf::from(env.var_os(var).filter(|it| !it.is_empty())?);
+    let error = || {
+        DiscoveryError::new(ErrorKind::InvalidRustSrc, "rust-src")
+            .with_path(path.clone())
+            .with_var(var.to_string())
+    };
+    if path.is_relative() {
+        return Some(Err(error().with_detail("the path must be absolute".to_string())));
+    }
+    let has_crate = |name: &str| {
+        path.join(name).join("src/lib.rs").is_file()
+            || path.join(format!("lib{}", name)).join("lib.rs").is_file()
+    };
+    if !has_crate("core") || !has_crate("std") {
+        return Some(Err(error()));
+    }
+
+    let sources = sources_version(&path);
+    let compiler = rustc_meta(rustc).ok().map(|it| it.version);
+    if let (Some(sources), Some(compiler)) = (sources, compiler) {
+        let release = |it: &Version| (it.major, it.minor, it.patch);
+        if release(&sources) != release(&compiler) {
+            log::warn!(
+                "{} points to the sources of Rust {}, but rustc is {}",
+                var,
+                sources,
+                compiler
+            );
+        }
+    }
+    Some(Ok(path))
+}
+
+/// Finds the version of the sources in `dir`, through `src/version` for a
+/// checkout of the rust repository, where `dir` is `library` or `src`, or
+/// through the channel manifest of the toolchain installed by rustup, where
+/// `dir` is `lib/rustlib/src/rust/library` in the sysroot. Other ancestors
+/// are not looked at, they may hold an unrelated `src/version`.
+fn sources_version(dir: &Path) -> Option<Version> {
+    let checkout = dir.parent()?;
+    if let Ok(version) = fs::read_to_string(checkout.join("src/version")) {
+        return Version::parse(version.trim());
+    }
+    let sysroot = dir.ancestors().nth(5)?;
+    let manifest = fs::read_to_string(sysroot.join("lib/rustlib/multirust-channel-manifest.toml"));
+    let manifest = manifest.ok()?;
+    Version::parse_output(unquote(config_value(&manifest, "pkg.rustc.version")?))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testdir::TestDir;
+
+    use super::*;
+
+    #[test]
+    fn prefers_library_layout() {
+        let tmp = TestDir::new("rust-src");
+        let sysroot = tmp.path().to_path_buf();
+        assert_eq!(rust_src(&sysroot), None);
+
+        let rust = sysroot.join("lib/rustlib/src/rust");
+        fs::create_dir_all(rust.join("src")).unwrap();
+        assert_eq!(rust_src(&sysroot), Some(rust.join("src")));
+        fs::create_dir_all(rust.join("library")).unwrap();
+        assert_eq!(rust_src(&sysroot), Some(rust.join("library")));
+    }
+
+    #[test]
+    fn local_build_uses_checkout() {
+        let tmp = TestDir::new("rust-checkout");
+        let checkout = tmp.path().to_path_buf();
+        let sysroot = checkout.join("build/x86_64-unknown-linux-gnu/stage1");
+        fs::create_dir_all(&sysroot).unwrap();
+        fs::create_dir_all(checkout.join("library")).unwrap();
+        assert_eq!(rust_src(&sysroot), None);
+
+        fs::write(checkout.join("x.py"), "").unwrap();
+        assert_eq!(rust_src(&sysroot), Some(checkout.join("library")));
+    }
+
+    #[test]
+    fn rust_src_path_is_validated() {
+        let tmp = TestDir::new("rust-src-path");
+        let dir = tmp.path().to_path_buf();
+        let library = dir.join("lib/rustlib/src/rust/library");
+        for krate in &["core", "std"] {
+            fs::create_dir_all(library.join(krate).join("src")).unwrap();
+        }
+        let rustc = crate::rustc();
+
+        let mut env = Env::default();
+        assert!(rust_src_from_env(&env, &rustc).is_none());
+        env.set_var("RUST_SRC_PATH", dir.clone().into());
+        let err = rust_src_from_env(&env, &rustc).unwrap().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidRustSrc);
+
+        for krate in &["core", "std"] {
+            fs::write(library.join(krate).join("src/lib.rs"), "").unwrap();
+        }
+        fs::write(
+            dir.join("lib/rustlib/multirust-channel-manifest.toml"),
+            "[pkg.rustc]\nversion = \"1.52.1 (9bc8c42bb 2021-05-09)\"\n",
+        )
+        .unwrap();
+        env.set_var("RUST_SRC_PATH", library.clone().into());
+        assert_eq!(rust_src_from_env(&env, &rustc).unwrap(), Ok(library.clone()));
+        assert_eq!(sources_version(&library).unwrap().to_string(), "1.52.1");
+        assert_eq!(sources_version(&library.join("core")), None);
+    }
+
+    #[test]
+    fn unknown_toolchain_has_no_sources() {
+        assert!(rust_src_for(&ToolchainSpec::parse("surely-not-a-toolchain")).is_err());
+    }
+}
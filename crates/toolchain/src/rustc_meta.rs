@@ -0,0 +1,156 @@
+//! What `rustc -vV` says about a compiler.
+
+use std::{io, path::Path, sync::Mutex};
+
+use once_cell::sync::Lazy;
+use rustc_hash::FxHashMap;
+use stdx::split_once;
+
+use crate::{utf8_stdout, Fingerprint, Version};
+
+/// The verbose version information of a `rustc`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RustcMeta {
+    pub version: Version,
+    /// The `release` field verbatim, like `1.54.0-nightly`.
+    pub release: String,
+    /// `None` for compilers built outside of a git checkout.
+    pub commit_hash: Option<String>,
+    pub commit_date: Option<String>,
+    /// The default target triple.
+    pub host: String,
+    pub llvm_version: Option<String>,
+}
+
+/// Runs `rustc -vV`. The result is cached per [`Fingerprint`] of `rustc`.
+pub fn rustc_meta(rustc: &Path) -> io::Result<RustcMeta> {
+    meta(rustc, None)
+}
+
+/// Like [`rustc_meta`], but runs `rustc` in `dir`, so that a rustup proxy
+/// picks the toolchain pinned or overridden for `dir`.
+pub fn rustc_meta_in(rustc: &Path, dir: &Path) -> io::Result<RustcMeta> {
+    meta(rustc, Some(dir))
+}
+
+fn meta(rustc: &Path, dir: Option<&Path>) -> io::Result<RustcMeta> {
+    static CACHE: Lazy<Mutex<FxHashMap<Fingerprint, RustcMeta>>> = Lazy::new(Default::default);
+
+    let fingerprint = match dir {
+        Some(dir) => Fingerprint::of_in(rustc, dir),
+        None => Fingerprint::of(rustc),
+    };
+    let fingerprint = fingerprint.ok();
+    if let Some(fingerprint) = &fingerprint {
+        if let Some(meta) = CACHE.lock().unwrap().get(fingerprint) {
+            return Ok(meta.clone());
+        }
+    }
+
+    let mut cmd = crate::interpreter::command(rustc);
+    cmd.arg("-vV");
+    if let Some(dir) = dir {
+        cmd.current_dir(dir);
+    }
+    let stdout = utf8_stdout(cmd)?;
+    let meta = RustcMeta::parse(&stdout).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unexpected output of `{} -vV`:\n{}", rustc.display(), stdout),
+        )
+    })?;
+
+    if let Some(fingerprint) = fingerprint {
+        CACHE.lock().unwrap().insert(fingerprint, meta.clone());
+    }
+    Ok(meta)
+}
+
+impl RustcMeta {
+    fn parse(output: &str) -> Option<RustcMeta> {
+        let field = |name: &str| {
+            output.lines().find_map(|line| {
+                let (key, value) = split_once(line, ':')?;
+                let value = value.trim();
+                (key.trim() == name && !value.is_empty() && value != "unknown")
+                    .then(|| value.to_string())
+            })
+        };
+        // Local builds, like those linked with `rustup toolchain link`, may
+        // report a `release` which isn't a version, and nothing but the first
+        // line is guaranteed.
+        let release = field("release").filter(|it| Version::parse(it).is_some()).or_else(|| {
+            let first_line = output.lines().next()?.strip_prefix("rustc ")?;
+            first_line.split_whitespace().next().map(str::to_string)
+        })?;
+        Some(RustcMeta {
+            version: Version::parse(&release)?,
+            release,
+            commit_hash: field("commit-hash"),
+            commit_date: field("commit-date"),
+            host: field("host")?,
+            llvm_version: field("LLVM version"),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_verbose_version() {
+        let output = "\
+rustc 1.54.0-nightly (ed597e7e1 2021-06-08)
+binary: rustc
+commit-hash: ed597e7e19d0fe716d9f81b1e840a5abbfd7c28d
+commit-date: 2021-06-08
+host: x86_64-unknown-linux-gnu
+release: 1.54.0-nightly
+LLVM version: 12.0.1
+";
+        let meta = RustcMeta::parse(output).unwrap();
+        assert_eq!(meta.version.to_string(), "1.54.0-nightly");
+        assert_eq!(meta.commit_date.as_deref(), Some("2021-06-08"));
+        assert_eq!(meta.host, "x86_64-unknown-linux-gnu");
+        assert_eq!(meta.llvm_version.as_deref(), Some("12.0.1"));
+
+        let output =
+            "rustc 1.53.0\ncommit-hash: unknown\nhost: aarch64-apple-darwin\nrelease: 1.53.0\n";
+        let meta = RustcMeta::parse(output).unwrap();
+        assert_eq!(meta.commit_hash, None);
+        assert_eq!(meta.llvm_version, None);
+
+        assert_eq!(RustcMeta::parse("rustc 1.53.0\n"), None);
+    }
+
+    #[test]
+    fn parses_local_build() {
+        let output = "\
+rustc 1.56.0-dev
+binary: rustc
+commit-hash: unknown
+commit-date: unknown
+host: x86_64-unknown-linux-gnu
+release: 1.56.0-dev
+LLVM version: 12.0.1
+";
+        let meta = RustcMeta::parse(output).unwrap();
+        assert_eq!(meta.version.to_string(), "1.56.0-dev");
+        assert_eq!(meta.commit_hash, None);
+
+        let output =
+            "rustc 1.56.0-dev (custom build)\nhost: x86_64-unknown-linux-gnu\nrelease: dev\n";
+        let meta = RustcMeta::parse(output).unwrap();
+        assert_eq!(meta.release, "1.56.0-dev");
+    }
+
+    #[test]
+    fn real_rustc_reports_host() {
+        let meta = rustc_meta(&crate::rustc()).unwrap();
+        assert!(!meta.host.is_empty());
+        assert_eq!(rustc_meta(&crate::rustc()).unwrap(), meta);
+        let dir = std::env::current_dir().unwrap();
+        assert_eq!(rustc_meta_in(&crate::rustc(), &dir).unwrap(), meta);
+    }
+}
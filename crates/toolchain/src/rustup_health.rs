@@ -0,0 +1,200 @@
+//! Detection of broken rustup installations, for more helpful errors than
+//! "not a valid executable".
+
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use rustc_hash::FxHashSet;
+
+use crate::{Env, SuggestedCommand};
+
+/// A specific way in which a rustup installation is broken.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RustupDiagnosis {
+    /// `$RUSTUP_HOME` exists, but `settings.toml` doesn't, so rustup has no
+    /// default toolchain.
+    MissingSettings { rustup_home: PathBuf },
+    /// A toolchain linked with `rustup toolchain link` points nowhere.
+    DanglingToolchainLink { toolchain: String, target: PathBuf },
+    /// The symlinks at `proxy` form a cycle.
+    ProxyLoop { proxy: PathBuf },
+    /// An empty executable, typically left by an interrupted install.
+    EmptyBinary { path: PathBuf, toolchain: Option<String> },
+}
+
+/// Checks the rustup installation of `env` for every known kind of damage.
+pub fn diagnose_rustup(env: &Env) -> Vec<RustupDiagnosis> {
+    let mut res = Vec::new();
+    if let Some(rustup_home) = env.rustup_home().filter(|it| it.is_dir()) {
+        if !rustup_home.join("settings.toml").is_file() {
+            res.push(RustupDiagnosis::MissingSettings { rustup_home: rustup_home.clone() });
+        }
+        let entries = fs::read_dir(rustup_home.join("toolchains")).into_iter().flatten();
+        for entry in entries.filter_map(Result::ok) {
+            let toolchain = entry.file_name().to_string_lossy().into_owned();
+            let path = entry.path();
+            if let Ok(target) = fs::read_link(&path) {
+                if !path.exists() {
+                    res.push(RustupDiagnosis::DanglingToolchainLink { toolchain, target });
+                    continue;
+                }
+            }
+            let bins = fs::read_dir(path.join("bin")).into_iter().flatten();
+            for bin in bins.filter_map(Result::ok) {
+                res.extend(diagnose_path(&bin.path()));
+            }
+        }
+    }
+    if let Some(home) = env.home_dir() {
+        let bins = fs::read_dir(home.join(".cargo").join("bin")).into_iter().flatten();
+        for bin in bins.filter_map(Result::ok) {
+            res.extend(diagnose_path(&bin.path()));
+        }
+    }
+    res
+}
+
+/// Checks a single executable for damage rustup is known to leave behind.
+pub(crate) fn diagnose_path(path: &Path) -> Option<RustupDiagnosis> {
+    if is_symlink_loop(path) {
+        return Some(RustupDiagnosis::ProxyLoop { proxy: path.to_path_buf() });
+    }
+    let metadata = fs::metadata(path).ok()?;
+    if metadata.is_file() && metadata.len() == 0 {
+        return Some(RustupDiagnosis::EmptyBinary {
+            path: path.to_path_buf(),
+            toolchain: toolchain_of(path),
+        });
+    }
+    None
+}
+
+impl RustupDiagnosis {
+    /// Commands which fix the problem.
+    pub fn repair(&self) -> Vec<SuggestedCommand> {
+        let command = |description: &str, command: String| SuggestedCommand {
+            description: description.to_string(),
+            command,
+        };
+        match self {
+            RustupDiagnosis::MissingSettings { .. } => {
+                vec![command("Choose a default toolchain", "rustup default stable".to_string())]
+            }
+            RustupDiagnosis::DanglingToolchainLink { toolchain, .. } => vec![command(
+                "Remove the broken link",
+                format!("rustup toolchain uninstall {}", toolchain),
+            )],
+            RustupDiagnosis::ProxyLoop { .. }
+            | RustupDiagnosis::EmptyBinary { toolchain: None, .. } => {
+                vec![command("Reinstall the rustup proxies", "rustup self update".to_string())]
+            }
+            RustupDiagnosis::EmptyBinary { toolchain: Some(toolchain), .. } => vec![command(
+                "Reinstall the toolchain",
+                format!("rustup toolchain install {} --force", toolchain),
+            )],
+        }
+    }
+}
+
+impl fmt::Display for RustupDiagnosis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RustupDiagnosis::MissingSettings { rustup_home } => {
+                write!(f, "{} has no settings.toml", rustup_home.display())
+            }
+            RustupDiagnosis::DanglingToolchainLink { toolchain, target } => write!(
+                f,
+                "the toolchain `{}` links to {}, which doesn't exist",
+                toolchain,
+                target.display()
+            ),
+            RustupDiagnosis::ProxyLoop { proxy } => {
+                write!(f, "the symlinks at {} form a loop", proxy.display())
+            }
+            RustupDiagnosis::EmptyBinary { path, .. } => {
+                write!(f, "{} is empty, an install was probably interrupted", path.display())
+            }
+        }
+    }
+}
+
+/// Whether following the links from `path` comes back to a link already
+/// seen. Only the final component is followed at each step.
+pub(crate) fn is_symlink_loop(path: &Path) -> bool {
+    let mut visited = FxHashSet::default();
+    let mut current = path.to_path_buf();
+    while let Ok(target) = fs::read_link(&current) {
+        if !visited.insert(current.clone()) {
+            return true;
+        }
+        current = match current.parent() {
+            Some(parent) => parent.join(target),
+            None => target,
+        };
+    }
+    false
+}
+
+/// The toolchain directory `path` is in, if it's inside
+/// `$RUSTUP_HOME/toolchains/<toolchain>/bin`.
+pub(crate) fn toolchain_of(path: &Path) -> Option<String> {
+    let bin = path.parent()?;
+    let toolchain = bin.parent()?;
+    if bin.file_name()? != "bin" || toolchain.parent()?.file_name()? != "toolchains" {
+        return None;
+    }
+    Some(toolchain.file_name()?.to_string_lossy().into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testdir::TestDir;
+
+    use super::*;
+
+    #[test]
+    fn finds_empty_binaries_and_missing_settings() {
+        let tmp = TestDir::new("health");
+        let home = tmp.path().to_path_buf();
+        let bin = home.join(".rustup/toolchains/stable-x86_64/bin");
+        fs::create_dir_all(&bin).unwrap();
+        fs::write(bin.join("rustc"), "").unwrap();
+        fs::write(bin.join("cargo"), "not empty").unwrap();
+
+        let mut env = Env::default();
+        env.set_var("HOME", home.clone().into());
+        env.remove_var("RUSTUP_HOME");
+        let diagnoses = diagnose_rustup(&env);
+        assert_eq!(
+            diagnoses,
+            [
+                RustupDiagnosis::MissingSettings { rustup_home: home.join(".rustup") },
+                RustupDiagnosis::EmptyBinary {
+                    path: bin.join("rustc"),
+                    toolchain: Some("stable-x86_64".to_string())
+                },
+            ]
+        );
+        assert_eq!(
+            diagnoses[1].repair()[0].command,
+            "rustup toolchain install stable-x86_64 --force"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn finds_symlink_loops() {
+        let tmp = TestDir::new("loop");
+        let dir = tmp.path().to_path_buf();
+        fs::create_dir_all(&dir).unwrap();
+        std::os::unix::fs::symlink("cargo-b", dir.join("cargo")).unwrap();
+        std::os::unix::fs::symlink("cargo", dir.join("cargo-b")).unwrap();
+
+        assert_eq!(
+            diagnose_path(&dir.join("cargo")),
+            Some(RustupDiagnosis::ProxyLoop { proxy: dir.join("cargo") })
+        );
+    }
+}
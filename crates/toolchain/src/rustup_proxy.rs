@@ -0,0 +1,146 @@
+//! Running the binaries behind rustup's proxies directly.
+//!
+//! A proxy like `~/.cargo/bin/cargo` works out the toolchain for the current
+//! directory on every run, which costs tens of milliseconds, and
+//! check-on-save and metadata refreshes run cargo a lot. Once a proxy has
+//! been resolved for a directory, [`crate::ToolCommand`] runs the
+//! toolchain's binary instead, with `RUSTUP_TOOLCHAIN` set so that the tools
+//! it runs in turn agree with it.
+//!
+//! Resolutions are forgotten by [`crate::invalidate_tools`], which is what
+//! happens when a [`crate::ToolchainMonitor`] notices that the toolchain
+//! files changed.
+
+use std::{
+    env::consts::EXE_SUFFIX,
+    ffi::{OsStr, OsString},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use crate::{execution::execution_environment, registry, rustup_health::toolchain_of, utf8_stdout};
+
+static BYPASS: AtomicBool = AtomicBool::new(false);
+
+/// Makes the commands built by [`crate::ToolCommand`] run the binaries
+/// behind rustup's proxies directly. Off by default.
+///
+/// Commands run through a [`crate::CommandPrefix`] keep using the proxies,
+/// as rustup may pick another toolchain on the other side of the prefix.
+///
+/// Returns whether the setting changed.
+pub fn set_bypass_rustup_proxies(enabled: bool) -> bool {
+    let changed = BYPASS.swap(enabled, Ordering::Relaxed) != enabled;
+    if changed {
+        crate::invalidate_tools();
+    }
+    changed
+}
+
+/// A proxy, resolved for a directory and a value of `RUSTUP_TOOLCHAIN`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct ProxyKey {
+    pub(crate) proxy: PathBuf,
+    pub(crate) dir: PathBuf,
+    pub(crate) rustup_toolchain: Option<OsString>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ResolvedProxy {
+    /// Like `~/.rustup/toolchains/stable-x86_64-unknown-linux-gnu/bin/cargo`.
+    pub(crate) path: PathBuf,
+    /// The value for `RUSTUP_TOOLCHAIN`.
+    pub(crate) toolchain: String,
+}
+
+/// The binary `program` stands for, if bypassing is enabled and `program`
+/// is a rustup proxy.
+pub(crate) fn resolve(
+    program: &Path,
+    dir: &Path,
+    rustup_toolchain: Option<&OsStr>,
+) -> Option<ResolvedProxy> {
+    if !BYPASS.load(Ordering::Relaxed) {
+        return None;
+    }
+    let key = ProxyKey {
+        proxy: program.to_path_buf(),
+        dir: dir.to_path_buf(),
+        rustup_toolchain: rustup_toolchain.map(OsStr::to_os_string),
+    };
+    registry::resolved_proxy(key, resolve_uncached)
+}
+
+fn resolve_uncached(key: &ProxyKey) -> Option<ResolvedProxy> {
+    let rustup = rustup_of(&key.proxy)?;
+    let tool = key.proxy.file_stem()?;
+    let rustup_cmd = || {
+        let mut cmd = crate::interpreter::command(&rustup);
+        cmd.current_dir(&key.dir);
+        match &key.rustup_toolchain {
+            Some(it) => cmd.env("RUSTUP_TOOLCHAIN", it),
+            None => cmd.env_remove("RUSTUP_TOOLCHAIN"),
+        };
+        cmd
+    };
+    let mut cmd = rustup_cmd();
+    cmd.arg("which").arg(tool);
+    let path = match utf8_stdout(cmd) {
+        Ok(it) => PathBuf::from(it),
+        Err(err) => {
+            log::warn!("can't resolve the rustup proxy {}: {}", key.proxy.display(), err);
+            return None;
+        }
+    };
+    // Toolchains linked with `rustup toolchain link` live outside of
+    // `$RUSTUP_HOME/toolchains`, so only rustup knows their name.
+    let toolchain = toolchain_of(&path).or_else(|| {
+        let mut cmd = rustup_cmd();
+        cmd.args(["show", "active-toolchain"]);
+        let stdout = utf8_stdout(cmd).ok()?;
+        stdout.split_whitespace().next().map(str::to_string)
+    })?;
+    match execution_environment().is_file(&path) {
+        true => Some(ResolvedProxy { path, toolchain }),
+        false => None,
+    }
+}
+
+/// The `rustup` next to `proxy`, if `proxy` is one of its proxies: rustup
+/// installs them as links to itself, so they are the same file.
+fn rustup_of(proxy: &Path) -> Option<PathBuf> {
+    if proxy.file_stem()? == "rustup" {
+        return None;
+    }
+    let rustup = proxy.parent()?.join(format!("rustup{}", EXE_SUFFIX));
+    // Hard links or symlinks, depending on the platform and the install.
+    match same_file::is_same_file(proxy, &rustup) {
+        Ok(true) => Some(rustup),
+        Ok(false) | Err(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::testdir::TestDir;
+
+    use super::*;
+
+    #[test]
+    fn proxies_are_links_to_rustup() {
+        let tmp = TestDir::new("rustup-proxy");
+        let dir = tmp.path();
+        let exe = |name: &str| dir.join(format!("{}{}", name, EXE_SUFFIX));
+        fs::write(exe("rustup"), "rustup").unwrap();
+        fs::hard_link(exe("rustup"), exe("cargo")).unwrap();
+        // As big as rustup, but another file.
+        fs::write(exe("rustfmt"), "rustfm").unwrap();
+
+        assert_eq!(rustup_of(&exe("cargo")), Some(exe("rustup")));
+        assert_eq!(rustup_of(&exe("rustup")), None);
+        assert_eq!(rustup_of(&exe("rustfmt")), None);
+        assert_eq!(rustup_of(&exe("rustc")), None);
+    }
+}
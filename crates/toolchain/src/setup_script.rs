@@ -0,0 +1,111 @@
+//! A user-specified command which sets up the environment, like
+//! `source /opt/rust/env.sh` or `module load rust`, as environment-module
+//! systems on HPC clusters and corporate machines expect.
+
+use std::{io, path::Path};
+
+use crate::{
+    login_shell::{parse, run_with_timeout, MARKER},
+    ToolCommand,
+};
+
+/// Printed before the environment the script starts with.
+const BEFORE_MARKER: &str = "__RUST_ANALYZER_ENV_BEFORE__";
+
+/// Runs `script` in `sh` (`cmd` on Windows) in `dir`, with `env` on top of
+/// the environment of the tools, and returns the variables it sets or
+/// changes.
+///
+/// What the script prints goes to stderr, so it can't be mistaken for the
+/// environment. Like for [`crate::direnv_export`], variables the script
+/// unsets are not returned.
+pub fn run_setup_script(
+    script: &str,
+    dir: &Path,
+    env: &[(String, String)],
+) -> io::Result<Vec<(String, String)>> {
+    let mut cmd = setup_command(script);
+    cmd.current_dir(dir);
+    for (key, value) in env {
+        cmd.env(key, value);
+    }
+    let (status, stdout) = run_with_timeout(&cmd)?;
+    if !status.success() {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("`{}` failed with {}", script, status),
+        ));
+    }
+    Ok(changed_vars(&stdout))
+}
+
+fn setup_command(script: &str) -> ToolCommand {
+    if cfg!(windows) {
+        let mut res = ToolCommand::new("cmd".into());
+        res.args([
+            "/D",
+            "/C",
+            &format!("echo {} & set & ({}) 1>&2 && echo {} && set", BEFORE_MARKER, script, MARKER),
+        ]);
+        return res;
+    }
+    let mut res = ToolCommand::new("/bin/sh".into());
+    // The newline ends a trailing comment in `script`.
+    res.args([
+        "-c",
+        &format!(
+            "echo {}; env; {{ {}\n}} >&2 || exit $?; echo {}; env",
+            BEFORE_MARKER, script, MARKER
+        ),
+    ]);
+    res
+}
+
+/// The variables which differ between the two environments in `stdout`.
+fn changed_vars(stdout: &str) -> Vec<(String, String)> {
+    let (before, after) = match stdout.find(MARKER) {
+        Some(idx) => stdout.split_at(idx),
+        None => return Vec::new(),
+    };
+    let before = parse(before, BEFORE_MARKER);
+    let mut res: Vec<(String, String)> =
+        parse(after, MARKER).into_iter().filter(|it| !before.contains(it)).collect();
+    res.sort();
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_changes_are_returned() {
+        let stdout = format!(
+            "{}\nHOME=/home/me\nPATH=/usr/bin\n{}\nHOME=/home/me\nPATH=/opt/rust/bin:/usr/bin\nRUST_MODULE=1.52\n",
+            BEFORE_MARKER, MARKER
+        );
+        assert_eq!(
+            changed_vars(&stdout),
+            [
+                ("PATH".to_string(), "/opt/rust/bin:/usr/bin".to_string()),
+                ("RUST_MODULE".to_string(), "1.52".to_string())
+            ]
+        );
+        assert_eq!(changed_vars("module: command not found\n"), []);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn sourced_variables_are_captured() {
+        let dir = std::env::temp_dir();
+        let base = [("RA_SETUP_BASE".to_string(), "1".to_string())];
+        let res = run_setup_script(
+            "echo loading; export RA_SETUP_TEST=\"$RA_SETUP_BASE\" # a comment",
+            &dir,
+            &base,
+        )
+        .unwrap();
+        assert_eq!(res, [("RA_SETUP_TEST".to_string(), "1".to_string())]);
+        assert!(run_setup_script("false", &dir, &[]).is_err());
+    }
+}
@@ -0,0 +1,127 @@
+//! Sharing of per-toolchain state between workspaces.
+//!
+//! A multi-root workspace often has all of its roots on the same toolchain,
+//! and loading the sysroot once per root is wasted work.
+
+use std::{
+    fmt,
+    hash::Hash,
+    io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, Weak},
+};
+
+use rustc_hash::FxHashMap;
+
+use crate::Fingerprint;
+
+/// Identifies a toolchain by its sysroot, so that two roots pinned to
+/// `stable` and `1.52.0` share a toolchain when these are the same.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ToolchainId {
+    sysroot: PathBuf,
+    /// Of the `rustc` in the sysroot, which changes on `rustup update`.
+    rustc: Option<Fingerprint>,
+}
+
+impl ToolchainId {
+    pub fn of(sysroot: &Path) -> io::Result<ToolchainId> {
+        let sysroot = sysroot.canonicalize()?;
+        let rustc = sysroot.join("bin").join(format!("rustc{}", std::env::consts::EXE_SUFFIX));
+        let rustc = Fingerprint::of(&rustc).ok();
+        Ok(ToolchainId { sysroot, rustc })
+    }
+
+    pub fn sysroot(&self) -> &Path {
+        &self.sysroot
+    }
+}
+
+/// Values built once per toolchain, like a loaded sysroot.
+///
+/// The key is a [`ToolchainId`] by default. Values which depend on more than
+/// the toolchain, like on an environment variable, add it to the key.
+///
+/// Only weak references are kept, a value is dropped once no workspace uses
+/// it anymore.
+pub struct SharedToolchains<T, K = ToolchainId> {
+    values: Mutex<FxHashMap<K, Weak<T>>>,
+}
+
+impl<T, K> Default for SharedToolchains<T, K> {
+    fn default() -> Self {
+        SharedToolchains { values: Mutex::default() }
+    }
+}
+
+impl<T, K: fmt::Debug> fmt::Debug for SharedToolchains<T, K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let values = self.values.lock().unwrap();
+        f.debug_struct("SharedToolchains").field("toolchains", &values.keys()).finish()
+    }
+}
+
+impl<T, K: Hash + Eq> SharedToolchains<T, K> {
+    /// The value for `id`, built with `build` if there's none alive.
+    ///
+    /// The lock isn't held while building, two concurrent builds for the same
+    /// toolchain both run, and the first one stored wins.
+    pub fn get_or_try_insert_with<E>(
+        &self,
+        id: K,
+        build: impl FnOnce() -> Result<T, E>,
+    ) -> Result<Arc<T>, E> {
+        if let Some(it) = self.get(&id) {
+            return Ok(it);
+        }
+        let value = Arc::new(build()?);
+        let mut values = self.values.lock().unwrap();
+        values.retain(|_, it| it.strong_count() > 0);
+        if let Some(it) = values.get(&id).and_then(Weak::upgrade) {
+            return Ok(it);
+        }
+        values.insert(id, Arc::downgrade(&value));
+        Ok(value)
+    }
+
+    pub fn get(&self, id: &K) -> Option<Arc<T>> {
+        self.values.lock().unwrap().get(id).and_then(Weak::upgrade)
+    }
+
+    /// Forgets the keys of the values which were dropped, like after a
+    /// reload which moved the workspaces to another toolchain.
+    pub fn prune(&self) {
+        self.values.lock().unwrap().retain(|_, it| it.strong_count() > 0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::testdir::TestDir;
+
+    use super::*;
+
+    #[test]
+    fn same_sysroot_is_shared() {
+        let tmp = TestDir::new("shared-sysroot");
+        let dir = tmp.path();
+        fs::create_dir_all(dir.join("bin")).unwrap();
+        let shared = SharedToolchains::default();
+        let build = |it: &str| -> Result<String, ()> { Ok(it.to_string()) };
+
+        let first = shared.get_or_try_insert_with(ToolchainId::of(dir).unwrap(), || build("a"));
+        let first = first.unwrap();
+        let second = shared
+            .get_or_try_insert_with(ToolchainId::of(&dir.join("bin/..")).unwrap(), || build("b"));
+        assert!(Arc::ptr_eq(&first, &second.unwrap()));
+
+        drop(first);
+        let third = shared.get_or_try_insert_with(ToolchainId::of(dir).unwrap(), || build("c"));
+        assert_eq!(third.unwrap().as_str(), "c");
+        assert_eq!(shared.values.lock().unwrap().len(), 1);
+        shared.prune();
+        assert_eq!(shared.values.lock().unwrap().len(), 0);
+    }
+}
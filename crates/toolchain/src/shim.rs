@@ -0,0 +1,133 @@
+//! Detection of script shims standing in for real executables.
+//!
+//! Version managers like asdf and mise, as well as corporate setups, put
+//! scripts or links named `cargo` in `PATH` which pick the real executable
+//! at run time. They work fine for running tools, but diagnostics that only
+//! mention the shim are misleading, so we try to see through them.
+
+use std::{
+    fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+
+use crate::utf8_stdout;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Shim {
+    pub kind: ShimKind,
+    /// The real executable behind the shim, if known.
+    pub target: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShimKind {
+    /// A shim from `~/.asdf/shims`, resolved with `asdf which`.
+    Asdf,
+    /// A mise (formerly rtx) shim, resolved with `mise which`.
+    Mise,
+    /// Any other shell or batch script.
+    Script,
+}
+
+/// Checks whether `path` is a shim for `tool`, without spawning anything.
+///
+/// For plain scripts which `exec` an absolute path to the tool, the target
+/// is read from the script.
+pub(crate) fn detect_shim(path: &Path, tool: &str) -> Option<Shim> {
+    if let Ok(link) = fs::read_link(path) {
+        let name = link.file_stem()?.to_str()?;
+        if name == "mise" || name == "rtx" {
+            return Some(Shim { kind: ShimKind::Mise, target: None });
+        }
+    }
+
+    let text = read_script(path).ok()??;
+    let path_str = path.to_string_lossy().replace('\\', "/");
+    let kind = if path_str.contains("/.asdf/shims/") || text.contains("asdf exec") {
+        ShimKind::Asdf
+    } else if path_str.contains("/mise/shims/") || text.contains("mise x") {
+        ShimKind::Mise
+    } else {
+        ShimKind::Script
+    };
+    let target = match kind {
+        ShimKind::Script => exec_target(&text, path, tool),
+        ShimKind::Asdf | ShimKind::Mise => None,
+    };
+    Some(Shim { kind, target })
+}
+
+impl Shim {
+    pub(crate) fn resolve(&mut self, tool: &str) {
+        if self.target.is_some() {
+            return;
+        }
+        let manager = match self.kind {
+            ShimKind::Asdf => "asdf",
+            ShimKind::Mise => "mise",
+            ShimKind::Script => return,
+        };
+        let mut cmd = crate::interpreter::command(Path::new(manager));
+        cmd.args(["which", tool]);
+        self.target = utf8_stdout(cmd).ok().map(PathBuf::from).filter(|it| it.is_file());
+    }
+}
+
+/// Returns the text of `path` if it is a script: a file starting with `#!`,
+/// or, anywhere, a file with a batch or PowerShell extension.
+fn read_script(path: &Path) -> io::Result<Option<String>> {
+    const MAX_LEN: u64 = 16 * 1024;
+
+    let is_batch = path
+        .extension()
+        .and_then(|it| it.to_str())
+        .map_or(false, |ext| ["bat", "cmd", "ps1"].contains(&ext.to_ascii_lowercase().as_str()));
+    let mut text = Vec::new();
+    fs::File::open(path)?.take(MAX_LEN).read_to_end(&mut text)?;
+    if !(is_batch || text.starts_with(b"#!")) {
+        return Ok(None);
+    }
+    Ok(String::from_utf8(text).ok())
+}
+
+/// Looks for an absolute path to an existing `tool` executable in the
+/// script, like in `exec "/opt/rust/bin/cargo" "$@"`.
+fn exec_target(text: &str, shim: &Path, tool: &str) -> Option<PathBuf> {
+    text.lines()
+        .filter(|line| !line.starts_with("#!"))
+        .flat_map(|line| line.split(|c: char| c.is_whitespace() || c == '"' || c == '\''))
+        .map(Path::new)
+        .filter(|it| it.is_absolute() && it.file_stem().map_or(false, |stem| stem == tool))
+        .find(|it| *it != shim && it.is_file())
+        .map(Path::to_path_buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testdir::TestDir;
+
+    use super::*;
+
+    #[test]
+    fn detects_shims() {
+        let tmp = TestDir::new("shims");
+        let dir = tmp.path().to_path_buf();
+        fs::create_dir_all(&dir).unwrap();
+        let real = dir.join("real-cargo");
+        fs::create_dir_all(&real).unwrap();
+        let real = real.join("cargo");
+        fs::write(&real, "\x7fELF").unwrap();
+
+        let script = dir.join("cargo");
+        fs::write(&script, format!("#!/bin/sh\nexec \"{}\" \"$@\"\n", real.display())).unwrap();
+        let shim = detect_shim(&script, "cargo").unwrap();
+        assert_eq!(shim, Shim { kind: ShimKind::Script, target: Some(real.clone()) });
+
+        let asdf = dir.join("asdf-cargo");
+        fs::write(&asdf, "#!/usr/bin/env bash\nexec asdf exec \"cargo\" \"$@\"\n").unwrap();
+        assert_eq!(detect_shim(&asdf, "cargo"), Some(Shim { kind: ShimKind::Asdf, target: None }));
+
+        assert_eq!(detect_shim(&real, "cargo"), None);
+    }
+}
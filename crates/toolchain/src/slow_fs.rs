@@ -0,0 +1,187 @@
+//! Protection against candidate directories on slow filesystems.
+//!
+//! A `PATH` entry on an unresponsive NFS or SMB mount can block a plain
+//! `stat` for many seconds. The first time we look into a directory, the
+//! `stat` runs on a helper thread with a timeout; directories which time out
+//! are marked as slow, and lookups try them only after every other one.
+//! Directories which answered in time are trusted from then on. Listing a
+//! directory, resolving its links or reading its modification time is
+//! guarded the same way.
+
+use std::{
+    ffi::OsString,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{mpsc, Mutex},
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use once_cell::sync::Lazy;
+use rustc_hash::FxHashSet;
+
+const DEFAULT_STAT_TIMEOUT: Duration = Duration::from_millis(500);
+
+#[derive(Default)]
+struct State {
+    timeout: Option<Duration>,
+    slow: FxHashSet<PathBuf>,
+    fast: FxHashSet<PathBuf>,
+    /// The directory of every probe which hasn't returned yet, once per
+    /// probe.
+    pending: Vec<PathBuf>,
+}
+
+/// Probes which hang also hang their thread, so past this many, directories
+/// which aren't known to be fast are given up on without a probe.
+const MAX_PENDING: usize = 16;
+
+static STATE: Lazy<Mutex<State>> = Lazy::new(Default::default);
+
+/// Sets how long the first `stat` in a directory may take before the
+/// directory is considered slow. The default is half a second.
+pub fn set_stat_timeout(timeout: Duration) {
+    STATE.lock().unwrap().timeout = Some(timeout);
+}
+
+/// Marks `dir` as slow, so that lookups try it last.
+pub fn mark_slow_dir(dir: PathBuf) {
+    let mut state = STATE.lock().unwrap();
+    state.fast.remove(&dir);
+    state.slow.insert(dir);
+}
+
+/// The directories currently considered slow, either because they were
+/// marked so or because a `stat` in them timed out.
+pub fn slow_dirs() -> Vec<PathBuf> {
+    let mut res: Vec<PathBuf> = STATE.lock().unwrap().slow.iter().cloned().collect();
+    res.sort();
+    res
+}
+
+pub(crate) fn is_slow(dir: &Path) -> bool {
+    STATE.lock().unwrap().slow.contains(dir)
+}
+
+/// Like [`Path::is_file`], but gives up (returning `false`) if the parent
+/// directory doesn't answer in time.
+pub(crate) fn is_file(path: &Path) -> bool {
+    let dir = match path.parent() {
+        Some(it) => it,
+        None => return path.is_file(),
+    };
+    let owned_path = path.to_path_buf();
+    with_timeout(dir, path, move || owned_path.is_file()).unwrap_or(false)
+}
+
+/// Like [`fs::read_dir`], but fails if `dir` doesn't answer in time.
+pub(crate) fn read_dir(dir: &Path) -> io::Result<Vec<OsString>> {
+    let owned_dir = dir.to_path_buf();
+    let list = move || fs::read_dir(owned_dir)?.map(|entry| Ok(entry?.file_name())).collect();
+    with_timeout(dir, dir, list).unwrap_or_else(|| Err(timed_out(dir)))
+}
+
+/// The modification time of `path`, failing if its parent doesn't answer in
+/// time.
+pub(crate) fn modified(path: &Path) -> io::Result<SystemTime> {
+    let owned_path = path.to_path_buf();
+    let modified = move || fs::metadata(owned_path)?.modified();
+    with_timeout(path.parent().unwrap_or(path), path, modified)
+        .unwrap_or_else(|| Err(timed_out(path)))
+}
+
+/// Like [`Path::canonicalize`], but fails if `path` doesn't answer in time.
+pub(crate) fn canonicalize(path: &Path) -> io::Result<PathBuf> {
+    let owned_path = path.to_path_buf();
+    with_timeout(path, path, move || owned_path.canonicalize())
+        .unwrap_or_else(|| Err(timed_out(path)))
+}
+
+/// Runs `f`, which looks at `path` in `dir`, on a helper thread unless `dir`
+/// is known to answer in time. `None` if it didn't answer in time, which
+/// marks `dir` as slow.
+fn with_timeout<T: Send + 'static>(
+    dir: &Path,
+    path: &Path,
+    f: impl FnOnce() -> T + Send + 'static,
+) -> Option<T> {
+    let timeout = {
+        let mut state = STATE.lock().unwrap();
+        if state.fast.contains(dir) {
+            return Some(f());
+        }
+        // A slow directory is probed again once its last probe returned, in
+        // case the mount came back.
+        let hanging = state.slow.contains(dir) && state.pending.iter().any(|it| it == dir);
+        if hanging || state.pending.len() >= MAX_PENDING {
+            return None;
+        }
+        state.pending.push(dir.to_path_buf());
+        state.timeout.unwrap_or(DEFAULT_STAT_TIMEOUT)
+    };
+
+    let (sender, receiver) = mpsc::channel();
+    let owned_dir = dir.to_path_buf();
+    // If the stat hangs, so does the thread, but discovery moves on.
+    thread::spawn(move || {
+        let _ = sender.send(f());
+        let mut state = STATE.lock().unwrap();
+        if let Some(idx) = state.pending.iter().position(|it| *it == owned_dir) {
+            state.pending.swap_remove(idx);
+        }
+    });
+    match receiver.recv_timeout(timeout) {
+        Ok(res) => {
+            let mut state = STATE.lock().unwrap();
+            if !state.slow.contains(dir) {
+                state.fast.insert(dir.to_path_buf());
+            }
+            Some(res)
+        }
+        Err(_) => {
+            log::warn!("{} timed out after {:?}, marking it as slow", path.display(), timeout);
+            mark_slow_dir(dir.to_path_buf());
+            None
+        }
+    }
+}
+
+fn timed_out(path: &Path) -> io::Error {
+    io::Error::new(io::ErrorKind::TimedOut, format!("{} didn't answer in time", path.display()))
+}
+
+/// Moves the slow directories to the end, keeping the order otherwise.
+pub(crate) fn slow_last(dirs: impl Iterator<Item = PathBuf>) -> Vec<PathBuf> {
+    let (fast, slow): (Vec<_>, Vec<_>) = dirs.partition(|it| !is_slow(it));
+    fast.into_iter().chain(slow).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slow_dirs_go_last() {
+        let slow = PathBuf::from("/ra-toolchain-test/slow-mount");
+        mark_slow_dir(slow.clone());
+        let dirs = vec![slow.clone(), PathBuf::from("/bin"), PathBuf::from("/usr/bin")];
+        assert_eq!(slow_last(dirs.into_iter()), ["/bin".into(), "/usr/bin".into(), slow.clone()]);
+        assert!(slow_dirs().contains(&slow));
+    }
+
+    #[test]
+    fn hanging_dir_is_not_probed_again() {
+        let slow = PathBuf::from("/ra-toolchain-test/hanging-mount");
+        mark_slow_dir(slow.clone());
+        STATE.lock().unwrap().pending.push(slow.clone());
+        assert!(!is_file(&slow.join("cargo")));
+        assert_eq!(STATE.lock().unwrap().pending.iter().filter(|it| **it == slow).count(), 1);
+    }
+
+    #[test]
+    fn answering_dir_is_fast() {
+        let exe = std::env::current_exe().unwrap();
+        assert!(is_file(&exe));
+        assert!(STATE.lock().unwrap().fast.contains(exe.parent().unwrap()));
+    }
+}
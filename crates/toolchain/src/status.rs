@@ -0,0 +1,78 @@
+//! The state of every tool this crate knows about, for an overview page.
+
+use std::{collections::BTreeMap, fmt, path::PathBuf};
+
+use crate::{validate::is_valid_executable, DiscoveryError, ToolKind, Version};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ToolStatus {
+    pub found: bool,
+    pub path: Option<PathBuf>,
+    pub version: Option<Version>,
+    /// Why the tool is missing or unusable. A tool can be found and still
+    /// have an error, if its `--version` fails.
+    pub error: Option<DiscoveryError>,
+}
+
+/// Locates every [`ToolKind`] which [`ToolKind::is_reported`] and asks it for
+/// its version.
+///
+/// This runs a process per found tool the first time, so it's meant for
+/// on-demand status reports rather than for startup. The versions are cached
+/// per [`crate::Fingerprint`], later reports only run the tools which
+/// changed.
+pub fn discover_all() -> BTreeMap<&'static str, ToolStatus> {
+    reported().map(|tool| (tool, tool_status(tool))).collect()
+}
+
+fn reported() -> impl Iterator<Item = &'static str> {
+    ToolKind::ALL.iter().filter(|it| it.is_reported()).map(|it| it.name())
+}
+
+fn tool_status(tool: &str) -> ToolStatus {
+    let found = match crate::locate(tool) {
+        Ok(it) => it,
+        Err(err) => {
+            return ToolStatus { found: false, path: None, version: None, error: Some(err) }
+        }
+    };
+    let res = match found.version {
+        Some(version) => Ok(Some(version)),
+        None => is_valid_executable(None, tool, &found.path),
+    };
+    let (version, error) = match res {
+        Ok(version) => (version, None),
+        Err(err) => (None, Some(err)),
+    };
+    ToolStatus { found: true, path: Some(found.path), version, error }
+}
+
+impl fmt::Display for ToolStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.path {
+            Some(path) => write!(f, "{}", path.display())?,
+            None => write!(f, "not found")?,
+        }
+        if let Some(version) = &self.version {
+            write!(f, " ({})", version)?;
+        }
+        if let Some(error) = &self.error {
+            write!(f, ": {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_every_known_tool() {
+        let statuses = discover_all();
+        assert_eq!(statuses.len(), reported().count());
+        let rustc = &statuses["rustc"];
+        assert!(rustc.found && rustc.version.is_some(), "{}", rustc);
+        assert_eq!(tool_status("rustc"), *rustc);
+    }
+}
@@ -0,0 +1,39 @@
+//! Queries about the sysroot of a `rustc`.
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use crate::utf8_stdout;
+
+/// Runs `rustc --print sysroot`.
+pub fn sysroot(rustc: &Path) -> io::Result<PathBuf> {
+    let mut cmd = crate::interpreter::command(rustc);
+    cmd.args(["--print", "sysroot"]);
+    Ok(PathBuf::from(utf8_stdout(cmd)?))
+}
+
+/// Whether the standard library for `target` is installed in the sysroot of
+/// `rustc`, which is what `rustup target add` does.
+pub fn is_target_installed(rustc: &Path, target: &str) -> io::Result<bool> {
+    let sysroot = sysroot(rustc)?;
+    Ok(has_target_libs(&sysroot, target))
+}
+
+pub(crate) fn has_target_libs(sysroot: &Path, target: &str) -> bool {
+    sysroot.join("lib").join("rustlib").join(target).join("lib").is_dir()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn host_target_is_installed() {
+        let rustc = crate::rustc();
+        let host = crate::rustc_meta(&rustc).unwrap().host;
+        assert!(is_target_installed(&rustc, &host).unwrap());
+        assert!(!is_target_installed(&rustc, "not-a-real-target").unwrap());
+    }
+}
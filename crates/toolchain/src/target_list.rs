@@ -0,0 +1,34 @@
+//! Runs `rustc --print target-list` to get the targets a compiler knows.
+
+use std::{io, path::Path, sync::Mutex};
+
+use once_cell::sync::Lazy;
+use rustc_hash::FxHashMap;
+
+use crate::{utf8_stdout, Fingerprint};
+
+/// Returns every target triple `rustc` can compile for, whether or not the
+/// standard library for it is installed.
+///
+/// The list is cached per [`Fingerprint`] of `rustc`.
+pub fn target_list(rustc: &Path) -> io::Result<Vec<String>> {
+    static CACHE: Lazy<Mutex<FxHashMap<Fingerprint, Vec<String>>>> = Lazy::new(Default::default);
+
+    let fingerprint = Fingerprint::of(rustc).ok();
+    if let Some(fingerprint) = &fingerprint {
+        if let Some(targets) = CACHE.lock().unwrap().get(fingerprint) {
+            return Ok(targets.clone());
+        }
+    }
+
+    let mut cmd = crate::interpreter::command(rustc);
+    cmd.args(["--print", "target-list"]);
+    let stdout = utf8_stdout(cmd)?;
+    let targets: Vec<String> =
+        stdout.lines().map(str::trim).filter(|it| !it.is_empty()).map(String::from).collect();
+
+    if let Some(fingerprint) = fingerprint {
+        CACHE.lock().unwrap().insert(fingerprint, targets.clone());
+    }
+    Ok(targets)
+}
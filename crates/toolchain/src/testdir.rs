@@ -0,0 +1,40 @@
+//! Temporary directories for tests which need a real file system, removed
+//! when the test ends, even if it fails.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+pub(crate) struct TestDir {
+    path: PathBuf,
+}
+
+impl TestDir {
+    /// An empty directory, unique to the test process and the call.
+    pub(crate) fn new(name: &str) -> TestDir {
+        static CNT: AtomicUsize = AtomicUsize::new(0);
+        let cnt = CNT.fetch_add(1, Ordering::Relaxed);
+        let path = std::env::temp_dir().join(format!(
+            "ra-toolchain-{}-{}_{}",
+            name,
+            std::process::id(),
+            cnt
+        ));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TestDir { path }
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        // Not `unwrap`: panicking while a failed test unwinds aborts.
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
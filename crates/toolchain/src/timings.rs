@@ -0,0 +1,84 @@
+//! How long discovery took, for rust-analyzer's status page.
+
+use std::{fmt, path::PathBuf, sync::Mutex, time::Duration};
+
+use once_cell::sync::Lazy;
+
+/// The latest [`crate::locate`] run for each tool.
+#[derive(Debug, Clone, Default)]
+pub struct DiscoveryTimings {
+    pub tools: Vec<ToolTiming>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ToolTiming {
+    pub tool: String,
+    pub total: Duration,
+    /// Every path which was checked, in order.
+    pub candidates: Vec<CandidateTiming>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CandidateTiming {
+    pub path: PathBuf,
+    /// `path` with the symbolic links in its directory resolved.
+    pub resolved: PathBuf,
+    pub duration: Duration,
+    pub found: bool,
+    /// Why the candidate wasn't looked at, if it wasn't.
+    pub skipped: Option<Skipped>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Skipped {
+    /// The same file as an earlier candidate, through a symbolic link or a
+    /// repeated `PATH` entry.
+    Duplicate,
+    /// The directory is a symbolic link which leads back to itself.
+    SymlinkCycle,
+}
+
+static TIMINGS: Lazy<Mutex<DiscoveryTimings>> = Lazy::new(Default::default);
+
+pub fn discovery_timings() -> DiscoveryTimings {
+    TIMINGS.lock().unwrap().clone()
+}
+
+pub(crate) fn record(timing: ToolTiming) {
+    let mut timings = TIMINGS.lock().unwrap();
+    timings.tools.retain(|it| it.tool != timing.tool);
+    timings.tools.push(timing);
+}
+
+impl fmt::Display for DiscoveryTimings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for tool in &self.tools {
+            writeln!(
+                f,
+                "{:<12} {:>6}us ({} candidates)",
+                tool.tool,
+                tool.total.as_micros(),
+                tool.candidates.len()
+            )?;
+            for candidate in &tool.candidates {
+                let mark = if candidate.found { "*" } else { " " };
+                write!(
+                    f,
+                    "  {}{:>6}us {}",
+                    mark,
+                    candidate.duration.as_micros(),
+                    candidate.path.display()
+                )?;
+                match candidate.skipped {
+                    Some(Skipped::Duplicate) => {
+                        write!(f, " (same as {})", candidate.resolved.display())?
+                    }
+                    Some(Skipped::SymlinkCycle) => write!(f, " (symlink cycle)")?,
+                    None => (),
+                }
+                writeln!(f)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,191 @@
+//! What we know about each tool: what it's called, where it's installed, how
+//! to override it and how to check it.
+//!
+//! Supporting a new tool should only take a new [`ToolKind`] variant. Tools
+//! without one can still be looked for by name, with the defaults: every
+//! place is searched, and the override variable is the upper-cased name.
+
+use crate::Validation;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ToolKind {
+    Cargo,
+    Rustc,
+    Rustup,
+    Rustfmt,
+    Rustdoc,
+    Clippy,
+    ClippyDriver,
+    Miri,
+    Nextest,
+    /// The server, which is also the proc-macro server, see
+    /// `rust-analyzer proc-macro`.
+    RustAnalyzer,
+    RustGdb,
+    RustLldb,
+    Gdb,
+    Lldb,
+    /// MSVC's `link.exe`.
+    Link,
+    RustLld,
+    LldLink,
+}
+
+/// A place [`crate::locate`] looks in, after the override variable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Location {
+    Path,
+    /// `~/.cargo/bin`, where rustup and `cargo install` put executables.
+    CargoBin,
+    /// The `bin` directories of rustup's toolchains.
+    RustupToolchains,
+}
+
+const EVERYWHERE: &[Location] = &[Location::Path, Location::CargoBin, Location::RustupToolchains];
+
+impl ToolKind {
+    pub const ALL: &'static [ToolKind] = &[
+        ToolKind::Cargo,
+        ToolKind::Rustc,
+        ToolKind::Rustup,
+        ToolKind::Rustfmt,
+        ToolKind::Rustdoc,
+        ToolKind::Clippy,
+        ToolKind::ClippyDriver,
+        ToolKind::Miri,
+        ToolKind::Nextest,
+        ToolKind::RustAnalyzer,
+        ToolKind::RustGdb,
+        ToolKind::RustLldb,
+        ToolKind::Gdb,
+        ToolKind::Lldb,
+        ToolKind::Link,
+        ToolKind::RustLld,
+        ToolKind::LldLink,
+    ];
+
+    /// The tool called `name`, by its canonical name or by an alias.
+    pub fn from_name(name: &str) -> Option<ToolKind> {
+        let kinds = || ToolKind::ALL.iter().copied();
+        kinds()
+            .find(|it| it.name() == name)
+            .or_else(|| kinds().find(|it| it.aliases().contains(&name)))
+    }
+
+    /// The name of the executable, without the extension.
+    pub fn name(self) -> &'static str {
+        match self {
+            ToolKind::Cargo => "cargo",
+            ToolKind::Rustc => "rustc",
+            ToolKind::Rustup => "rustup",
+            ToolKind::Rustfmt => "rustfmt",
+            ToolKind::Rustdoc => "rustdoc",
+            ToolKind::Clippy => "cargo-clippy",
+            ToolKind::ClippyDriver => "clippy-driver",
+            ToolKind::Miri => "cargo-miri",
+            ToolKind::Nextest => "cargo-nextest",
+            ToolKind::RustAnalyzer => "rust-analyzer",
+            ToolKind::RustGdb => "rust-gdb",
+            ToolKind::RustLldb => "rust-lldb",
+            ToolKind::Gdb => "gdb",
+            ToolKind::Lldb => "lldb",
+            ToolKind::Link => "link",
+            ToolKind::RustLld => "rust-lld",
+            ToolKind::LldLink => "lld-link",
+        }
+    }
+
+    /// The other names the tool may be installed under, preferred first.
+    pub fn aliases(self) -> &'static [&'static str] {
+        match self {
+            // Before the rename, the server binary was `ra_lsp_server`.
+            ToolKind::RustAnalyzer => &["ra_lsp_server"],
+            // Distribution packages sometimes ship only one of the two.
+            ToolKind::Clippy => &["clippy-driver"],
+            ToolKind::ClippyDriver => &["cargo-clippy"],
+            _ => &[],
+        }
+    }
+
+    /// The variable which points to the tool, taking precedence over the
+    /// search. Only tools with a conventional variable have one: `$LINK`,
+    /// for one, holds options for `link.exe`.
+    pub fn env_var(self) -> Option<&'static str> {
+        match self {
+            ToolKind::Cargo => Some("CARGO"),
+            ToolKind::Rustc => Some("RUSTC"),
+            ToolKind::Rustup => Some("RUSTUP"),
+            ToolKind::Rustfmt => Some("RUSTFMT"),
+            ToolKind::Rustdoc => Some("RUSTDOC"),
+            _ => None,
+        }
+    }
+
+    /// How to check the tool when there is no [`crate::set_validation`]
+    /// override.
+    pub fn validation(self) -> Validation {
+        match self {
+            // `link.exe` has neither `--version` nor `--help`, but prints
+            // a banner.
+            ToolKind::Link => Validation::NoArgs,
+            // Both act as a linker whatever the arguments.
+            ToolKind::RustLld | ToolKind::LldLink => Validation::MetadataOnly,
+            _ => Validation::Version,
+        }
+    }
+
+    /// Where the tool is installed, in the order to search.
+    pub fn locations(self) -> &'static [Location] {
+        match self {
+            ToolKind::Rustup | ToolKind::Nextest => &[Location::Path, Location::CargoBin],
+            ToolKind::Gdb
+            | ToolKind::Lldb
+            | ToolKind::Link
+            | ToolKind::RustLld
+            | ToolKind::LldLink => &[Location::Path],
+            _ => EVERYWHERE,
+        }
+    }
+
+    /// Whether the tool is part of [`crate::discover_all`]. Linkers are only
+    /// of interest to builds.
+    pub fn is_reported(self) -> bool {
+        !matches!(self, ToolKind::Link | ToolKind::RustLld | ToolKind::LldLink)
+    }
+}
+
+/// The [`Location`]s to search for `tool`, which may have no [`ToolKind`].
+pub(crate) fn locations(tool: &str) -> &'static [Location] {
+    ToolKind::from_name(tool).map_or(EVERYWHERE, ToolKind::locations)
+}
+
+/// The override variable of `tool`, which may have no [`ToolKind`].
+pub(crate) fn env_var(tool: &str) -> Option<String> {
+    match ToolKind::from_name(tool) {
+        Some(kind) => kind.env_var().map(str::to_string),
+        None => Some(tool.to_ascii_uppercase()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kinds_round_trip_through_names() {
+        for &kind in ToolKind::ALL {
+            assert_eq!(ToolKind::from_name(kind.name()), Some(kind));
+        }
+        assert_eq!(ToolKind::from_name("ra_lsp_server"), Some(ToolKind::RustAnalyzer));
+        assert_eq!(ToolKind::from_name("cargo-fuzz"), None);
+    }
+
+    #[test]
+    fn unknown_tools_get_defaults() {
+        assert_eq!(env_var("cargo").as_deref(), Some("CARGO"));
+        assert_eq!(env_var("link"), None);
+        assert_eq!(env_var("my-linker").as_deref(), Some("MY-LINKER"));
+        assert_eq!(locations("gdb"), [Location::Path]);
+        assert_eq!(locations("cargo-fuzz"), EVERYWHERE);
+    }
+}
@@ -0,0 +1,140 @@
+//! The set of tools rust-analyzer uses, discovered together.
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use crate::DiscoveryError;
+
+/// The discovered tools, plus the parts of the environment which affect how
+/// they should be run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Toolchain {
+    pub cargo: PathBuf,
+    pub rustc: PathBuf,
+    /// Missing for toolchains installed without rustup, like by a
+    /// distribution.
+    pub rustup: Option<PathBuf>,
+    /// Missing if the component isn't installed, formatting is unavailable
+    /// then.
+    pub rustfmt: Option<PathBuf>,
+    /// Where cargo puts build artifacts, if overridden by the environment.
+    pub target_dir: Option<TargetDir>,
+}
+
+/// A [`Toolchain`] where every tool may be missing, as returned by
+/// [`Toolchain::discover_partial`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PartialToolchain {
+    pub cargo: Result<PathBuf, DiscoveryError>,
+    pub rustc: Result<PathBuf, DiscoveryError>,
+    pub rustup: Result<PathBuf, DiscoveryError>,
+    pub rustfmt: Result<PathBuf, DiscoveryError>,
+    pub target_dir: Option<TargetDir>,
+}
+
+/// A build directory set through the environment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TargetDir {
+    pub path: PathBuf,
+    /// `CARGO_TARGET_DIR` or `CARGO_BUILD_TARGET_DIR`.
+    pub var: &'static str,
+}
+
+impl Toolchain {
+    /// Snapshots the process environment and discovers every tool, failing
+    /// if `cargo` or `rustc` is missing.
+    pub fn discover() -> Result<Toolchain, DiscoveryError> {
+        Toolchain::discover_partial().complete()
+    }
+
+    /// Like [`Toolchain::discover`], but never fails as a whole: each tool is
+    /// looked for independently, so that whatever is available can be used.
+    pub fn discover_partial() -> PartialToolchain {
+        let locate = |tool| crate::locate(tool).map(|it| it.path);
+        PartialToolchain {
+            cargo: locate("cargo"),
+            rustc: locate("rustc"),
+            rustup: locate("rustup"),
+            rustfmt: locate("rustfmt"),
+            target_dir: TargetDir::from_env(),
+        }
+    }
+}
+
+impl PartialToolchain {
+    /// The errors of the missing tools.
+    pub fn errors(&self) -> impl Iterator<Item = &DiscoveryError> {
+        let tools = vec![&self.cargo, &self.rustc, &self.rustup, &self.rustfmt];
+        tools.into_iter().filter_map(|it| it.as_ref().err())
+    }
+
+    /// The full toolchain, or the error of the first missing tool. Only
+    /// `cargo` and `rustc` are required.
+    pub fn complete(self) -> Result<Toolchain, DiscoveryError> {
+        Ok(Toolchain {
+            cargo: self.cargo?,
+            rustc: self.rustc?,
+            rustup: self.rustup.ok(),
+            rustfmt: self.rustfmt.ok(),
+            target_dir: self.target_dir,
+        })
+    }
+}
+
+impl TargetDir {
+    fn from_env() -> Option<TargetDir> {
+        // `CARGO_TARGET_DIR` takes precedence, as it does for cargo.
+        ["CARGO_TARGET_DIR", "CARGO_BUILD_TARGET_DIR"].iter().find_map(|&var| {
+            let path = env::var_os(var).filter(|it| !it.is_empty())?;
+            Some(TargetDir { path: path.into(), var })
+        })
+    }
+
+    /// Whether cargo will fail to write here: the directory (or, if it does
+    /// not exist yet, its closest existing ancestor) is read-only.
+    ///
+    /// Relative paths are resolved against `cwd`, like cargo does.
+    pub fn is_read_only(&self, cwd: &Path) -> bool {
+        let path = cwd.join(&self.path);
+        match path.ancestors().find_map(|it| fs::metadata(it).ok()) {
+            Some(metadata) => metadata.permissions().readonly(),
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ErrorKind;
+
+    use super::*;
+
+    #[test]
+    fn partial_toolchain_keeps_found_tools() {
+        let partial = PartialToolchain {
+            cargo: Ok("cargo".into()),
+            rustc: Ok("rustc".into()),
+            rustup: Ok("rustup".into()),
+            rustfmt: Err(DiscoveryError::new(ErrorKind::NotFound, "rustfmt")),
+            target_dir: None,
+        };
+        let errors: Vec<_> = partial.errors().map(|it| it.tool()).collect();
+        assert_eq!(errors, ["rustfmt"]);
+        let toolchain = partial.clone().complete().unwrap();
+        assert_eq!(toolchain.rustfmt, None);
+
+        let partial = PartialToolchain {
+            cargo: Err(DiscoveryError::new(ErrorKind::NotFound, "cargo")),
+            ..partial
+        };
+        assert_eq!(partial.complete().unwrap_err().kind(), ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn missing_target_dir_checks_ancestor() {
+        let dir = TargetDir { path: "target/does/not/exist".into(), var: "CARGO_TARGET_DIR" };
+        assert!(!dir.is_read_only(&env::temp_dir()));
+    }
+}
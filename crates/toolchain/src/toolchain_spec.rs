@@ -0,0 +1,218 @@
+//! Names of rustup toolchains, as passed to `rustup run` or `+toolchain`.
+
+use std::fmt;
+
+use stdx::split_once;
+
+use crate::Version;
+
+/// A rustup toolchain: `<channel>[-<date>][-<host triple>]`, like `stable`,
+/// `nightly-2021-05-10` or `1.52.0-x86_64-pc-windows-msvc`, or the name of a
+/// toolchain linked with `rustup toolchain link`.
+///
+/// Parsing never fails, anything which isn't an official toolchain is a
+/// custom one, and formatting gives back the parsed text.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ToolchainSpec {
+    repr: Repr,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Repr {
+    Official { channel: String, date: Option<String>, triple: Option<String> },
+    Custom(String),
+}
+
+impl ToolchainSpec {
+    pub fn parse(text: &str) -> ToolchainSpec {
+        let repr = parse_official(text).unwrap_or_else(|| Repr::Custom(text.to_string()));
+        ToolchainSpec { repr }
+    }
+
+    /// `stable`, `beta`, `nightly` or a version like `1.52` or `1.52.0`.
+    /// `None` for custom toolchains.
+    pub fn channel(&self) -> Option<&str> {
+        match &self.repr {
+            Repr::Official { channel, .. } => Some(channel),
+            Repr::Custom(_) => None,
+        }
+    }
+
+    /// The `YYYY-MM-DD` of an archived toolchain, like `nightly-2021-05-10`.
+    pub fn date(&self) -> Option<&str> {
+        match &self.repr {
+            Repr::Official { date, .. } => date.as_deref(),
+            Repr::Custom(_) => None,
+        }
+    }
+
+    /// The version of a channel like `1.52`, which means `1.52.0`.
+    pub fn version(&self) -> Option<Version> {
+        let channel = self.channel()?;
+        match channel.matches('.').count() {
+            1 => Version::parse(&format!("{}.0", channel)),
+            _ => Version::parse(channel),
+        }
+    }
+
+    /// The host triple, which rustup fills in if it's missing or partial,
+    /// like `x86_64`.
+    pub fn triple(&self) -> Option<&str> {
+        match &self.repr {
+            Repr::Official { triple, .. } => triple.as_deref(),
+            Repr::Custom(_) => None,
+        }
+    }
+
+    pub fn is_custom(&self) -> bool {
+        matches!(self.repr, Repr::Custom(_))
+    }
+
+    /// Whether `installed`, the full name of an installed toolchain, is what
+    /// this toolchain refers to: parts left out, like the triple of
+    /// `stable` or all but the architecture of `stable-x86_64`, match
+    /// anything.
+    ///
+    /// A date is never left out, `nightly` is not `nightly-2021-05-10`.
+    pub fn matches(&self, installed: &ToolchainSpec) -> bool {
+        match (&self.repr, &installed.repr) {
+            (
+                Repr::Official { channel, date, triple },
+                Repr::Official { channel: c, date: d, triple: t },
+            ) => {
+                let triple_matches = match (triple, t) {
+                    (None, _) => true,
+                    (Some(triple), Some(t)) => partial_triple_matches(triple, t),
+                    (Some(_), None) => false,
+                };
+                channel == c && date == d && triple_matches
+            }
+            (Repr::Custom(name), Repr::Custom(n)) => name == n,
+            _ => false,
+        }
+    }
+}
+
+/// Whether the parts of `partial`, like `x86_64` or `pc-windows`, are among
+/// the parts of `triple`, in order, as rustup completes a partial triple
+/// with the host's.
+fn partial_triple_matches(partial: &str, triple: &str) -> bool {
+    let mut parts = triple.split('-');
+    partial.split('-').all(|part| parts.any(|it| it == part))
+}
+
+fn parse_official(text: &str) -> Option<Repr> {
+    let (channel, mut rest) = match split_once(text, '-') {
+        Some((channel, rest)) => (channel, Some(rest)),
+        None => (text, None),
+    };
+    if !is_channel(channel) {
+        return None;
+    }
+    let mut date = None;
+    if let Some(it) = rest {
+        let (maybe_date, after) =
+            if it.len() > 10 { (&it[..10], it[10..].strip_prefix('-')) } else { (it, None) };
+        if is_date(maybe_date) {
+            date = Some(maybe_date.to_string());
+            rest = after;
+        }
+    }
+    let triple = match rest {
+        // Like rustup, accept partial triples such as `x86_64`.
+        Some(it) if !it.is_empty() && !it.starts_with('-') && !it.ends_with('-') => {
+            Some(it.to_string())
+        }
+        Some(_) => return None,
+        None => None,
+    };
+    Some(Repr::Official { channel: channel.to_string(), date, triple })
+}
+
+fn is_channel(text: &str) -> bool {
+    match text {
+        "stable" | "beta" | "nightly" => true,
+        _ => {
+            let parts: Vec<&str> = text.split('.').collect();
+            (2..=3).contains(&parts.len())
+                && parts.iter().all(|it| !it.is_empty() && it.bytes().all(|b| b.is_ascii_digit()))
+        }
+    }
+}
+
+fn is_date(text: &str) -> bool {
+    text.len() == 10
+        && text.bytes().enumerate().all(|(i, b)| match i {
+            4 | 7 => b == b'-',
+            _ => b.is_ascii_digit(),
+        })
+}
+
+impl fmt::Display for ToolchainSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.repr {
+            Repr::Official { channel, date, triple } => {
+                f.write_str(channel)?;
+                if let Some(date) = date {
+                    write!(f, "-{}", date)?;
+                }
+                if let Some(triple) = triple {
+                    write!(f, "-{}", triple)?;
+                }
+                Ok(())
+            }
+            Repr::Custom(name) => f.write_str(name),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_rustup_spellings() {
+        let check = |text: &str, channel, date, triple| {
+            let spec = ToolchainSpec::parse(text);
+            assert_eq!((spec.channel(), spec.date(), spec.triple()), (channel, date, triple));
+            assert_eq!(spec.to_string(), text);
+        };
+        check("stable", Some("stable"), None, None);
+        check("nightly-2024-03-01", Some("nightly"), Some("2024-03-01"), None);
+        check(
+            "1.74.0-x86_64-pc-windows-msvc",
+            Some("1.74.0"),
+            None,
+            Some("x86_64-pc-windows-msvc"),
+        );
+        check(
+            "nightly-2021-05-10-aarch64-apple-darwin",
+            Some("nightly"),
+            Some("2021-05-10"),
+            Some("aarch64-apple-darwin"),
+        );
+        check("custom-link-name", None, None, None);
+        check("stable-x86_64", Some("stable"), None, Some("x86_64"));
+        check("stable-", None, None, None);
+
+        assert_eq!(ToolchainSpec::parse("1.74").version().unwrap().to_string(), "1.74.0");
+        assert!(ToolchainSpec::parse("my-toolchain").is_custom());
+    }
+
+    #[test]
+    fn short_names_match_installed_toolchains() {
+        let installed = ToolchainSpec::parse("nightly-2021-05-10-x86_64-unknown-linux-gnu");
+        assert!(ToolchainSpec::parse("nightly-2021-05-10").matches(&installed));
+        assert!(!ToolchainSpec::parse("nightly").matches(&installed));
+        assert!(!ToolchainSpec::parse("nightly-2021-05-10-i686-pc-windows-gnu").matches(&installed));
+        assert!(ToolchainSpec::parse("my-rust").matches(&ToolchainSpec::parse("my-rust")));
+
+        let installed = ToolchainSpec::parse("stable-x86_64-unknown-linux-gnu");
+        assert!(ToolchainSpec::parse("stable-x86_64").matches(&installed));
+        assert!(ToolchainSpec::parse("stable-unknown-linux").matches(&installed));
+        assert!(ToolchainSpec::parse("stable-gnu").matches(&installed));
+        assert!(!ToolchainSpec::parse("stable-aarch64").matches(&installed));
+        assert!(!ToolchainSpec::parse("stable-gnu-x86_64").matches(&installed));
+        assert!(!ToolchainSpec::parse("stable-x86").matches(&installed));
+    }
+}
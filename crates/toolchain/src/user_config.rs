@@ -0,0 +1,161 @@
+//! The optional `toolchain.toml` of the user, for the knobs of discovery
+//! which have no place in an editor's settings.
+//!
+//! ```toml
+//! validation = "header"
+//! stat-timeout-ms = 2000
+//! slow-dirs = ["/mnt/nfs/bin"]
+//! ```
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::{
+    cargo_config::{config_value, unquote},
+    Env, Validation,
+};
+
+const FILE_NAME: &str = "toolchain.toml";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UserConfig {
+    /// The file the settings were read from.
+    pub file: PathBuf,
+    /// See [`crate::set_default_validation`].
+    pub validation: Option<Validation>,
+    /// See [`crate::set_stat_timeout`].
+    pub stat_timeout: Option<Duration>,
+    /// See [`crate::mark_slow_dir`].
+    pub slow_dirs: Vec<PathBuf>,
+}
+
+impl UserConfig {
+    /// Reads the first of [`UserConfig::locations`] which exists. `None` if
+    /// there is none, unknown keys and values are ignored.
+    pub fn load(env: &Env) -> io::Result<Option<UserConfig>> {
+        let file = match UserConfig::locations(env).into_iter().find(|it| it.is_file()) {
+            Some(it) => it,
+            None => return Ok(None),
+        };
+        let text = fs::read_to_string(&file)?;
+        let mut res = parse(&text);
+        res.file = file;
+        Ok(Some(res))
+    }
+
+    /// Where the file is looked for, in order:
+    ///
+    /// 1. `$RA_TOOLCHAIN_CONFIG`, if set, and nothing else.
+    /// 2. On Windows, `%APPDATA%\rust-analyzer\toolchain.toml`.
+    /// 3. On macOS, `~/Library/Application Support/rust-analyzer/toolchain.toml`.
+    /// 4. Everywhere but Windows, `$XDG_CONFIG_HOME/rust-analyzer/toolchain.toml`,
+    ///    with `XDG_CONFIG_HOME` defaulting to `~/.config`.
+    pub fn locations(env: &Env) -> Vec<PathBuf> {
+        locations(env, std::env::consts::OS)
+    }
+
+    /// Makes discovery use these settings.
+    pub fn apply(&self) {
+        if let Some(validation) = self.validation {
+            crate::set_default_validation(Some(validation));
+        }
+        if let Some(timeout) = self.stat_timeout {
+            crate::set_stat_timeout(timeout);
+        }
+        for dir in &self.slow_dirs {
+            crate::mark_slow_dir(dir.clone());
+        }
+    }
+}
+
+fn locations(env: &Env, os: &str) -> Vec<PathBuf> {
+    let var = |key: &str| {
+        env.var_os(key).filter(|it| !it.is_empty()).map(|it| env.absolute(PathBuf::from(it)))
+    };
+    if let Some(file) = var("RA_TOOLCHAIN_CONFIG") {
+        return vec![file];
+    }
+    let mut dirs = Vec::new();
+    let home = env.home_dir();
+    match os {
+        "windows" => dirs.extend(var("APPDATA")),
+        "macos" => dirs.extend(home.as_ref().map(|it| it.join("Library/Application Support"))),
+        _ => (),
+    }
+    if os != "windows" {
+        dirs.extend(var("XDG_CONFIG_HOME").or_else(|| Some(home?.join(".config"))));
+    }
+    dirs.into_iter().map(|it| it.join("rust-analyzer").join(FILE_NAME)).collect()
+}
+
+fn parse(text: &str) -> UserConfig {
+    let value = |key| config_value(text, key).map(unquote);
+    let validation = value("validation").and_then(|it| match it {
+        "version" => Some(Validation::Version),
+        "help" => Some(Validation::Help),
+        "no-args" => Some(Validation::NoArgs),
+        "metadata-only" => Some(Validation::MetadataOnly),
+        "header" => Some(Validation::Header),
+        _ => None,
+    });
+    let stat_timeout =
+        value("stat-timeout-ms").and_then(|it| it.parse().ok()).map(Duration::from_millis);
+    let slow_dirs = config_value(text, "slow-dirs")
+        .map(|it| {
+            it.trim_start_matches('[')
+                .trim_end_matches(']')
+                .split(',')
+                .map(unquote)
+                .filter(|it| !it.is_empty())
+                .map(|it| Path::new(it).to_path_buf())
+                .collect()
+        })
+        .unwrap_or_default();
+    UserConfig { file: PathBuf::new(), validation, stat_timeout, slow_dirs }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn follows_platform_conventions() {
+        let mut env = Env::default();
+        env.set_var("HOME", "/home/me".into());
+        env.set_var("USERPROFILE", "/home/me".into());
+        env.set_var("APPDATA", "/home/me/AppData/Roaming".into());
+        let check = |env: &Env, os, expected: &[&str]| {
+            let expected: Vec<PathBuf> = expected.iter().map(PathBuf::from).collect();
+            assert_eq!(locations(env, os), expected);
+        };
+        check(&env, "linux", &["/home/me/.config/rust-analyzer/toolchain.toml"]);
+        check(&env, "windows", &["/home/me/AppData/Roaming/rust-analyzer/toolchain.toml"]);
+        check(
+            &env,
+            "macos",
+            &[
+                "/home/me/Library/Application Support/rust-analyzer/toolchain.toml",
+                "/home/me/.config/rust-analyzer/toolchain.toml",
+            ],
+        );
+
+        env.set_var("XDG_CONFIG_HOME", "/xdg".into());
+        check(&env, "linux", &["/xdg/rust-analyzer/toolchain.toml"]);
+        env.set_var("RA_TOOLCHAIN_CONFIG", "/etc/ra.toml".into());
+        check(&env, "linux", &["/etc/ra.toml"]);
+    }
+
+    #[test]
+    fn parses_settings() {
+        let config = parse(
+            "validation = \"header\"\nstat-timeout-ms = 2000\nslow-dirs = [\"/mnt/nfs/bin\"]\n",
+        );
+        assert_eq!(config.validation, Some(Validation::Header));
+        assert_eq!(config.stat_timeout, Some(Duration::from_secs(2)));
+        assert_eq!(config.slow_dirs, [PathBuf::from("/mnt/nfs/bin")]);
+        assert_eq!(parse("validation = \"sometimes\"\n"), UserConfig::default());
+    }
+}
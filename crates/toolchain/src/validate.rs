@@ -0,0 +1,457 @@
+//! Checks that a candidate executable is actually usable.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Stdio,
+    sync::Mutex,
+};
+
+use once_cell::sync::Lazy;
+use rustc_hash::FxHashMap;
+
+use crate::{
+    app_alias::{self, is_app_execution_alias},
+    execution::execution_environment,
+    interpreter::{self, interpreter_for},
+    names::tool_names,
+    recursion,
+    rustup_health::diagnose_path,
+    DiscoveryError, Env, ErrorKind, Fingerprint, ToolKind, Version,
+};
+
+/// How to check that an executable works.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Validation {
+    /// Run `--version`, falling back to `--help` if that fails. Only this
+    /// gives a version.
+    Version,
+    /// Run `--help`, for tools without `--version`.
+    Help,
+    /// Run without arguments; any exit status will do, as long as the
+    /// process starts. For tools like `link.exe` which print a banner.
+    NoArgs,
+    /// Don't run anything, only check that it's an executable file. For
+    /// tools which misbehave when run outside of a build, like `rust-lld`.
+    MetadataOnly,
+    /// Like `MetadataOnly`, but also checks that the file starts like an
+    /// executable: an ELF, PE or Mach-O header, or a `#!` line. For setups
+    /// where running unknown binaries is forbidden, or too slow.
+    Header,
+}
+
+/// Overrides the [`Validation`] used for `tool`.
+pub fn set_validation(tool: &str, validation: Validation) {
+    OVERRIDES.lock().unwrap().insert(tool.to_string(), validation);
+}
+
+/// Uses `validation` for every tool without a [`set_validation`] override,
+/// in place of the built-in choice. `None` restores the built-in choice.
+pub fn set_default_validation(validation: Option<Validation>) {
+    *DEFAULT.lock().unwrap() = validation;
+}
+
+static OVERRIDES: Lazy<Mutex<FxHashMap<String, Validation>>> = Lazy::new(Default::default);
+static DEFAULT: Lazy<Mutex<Option<Validation>>> = Lazy::new(Default::default);
+
+pub(crate) fn validation_for(tool: &str) -> Validation {
+    if let Some(&it) = OVERRIDES.lock().unwrap().get(tool) {
+        return it;
+    }
+    if let Some(it) = *DEFAULT.lock().unwrap() {
+        return it;
+    }
+    ToolKind::from_name(tool).map_or(Validation::Version, ToolKind::validation)
+}
+
+/// What [`validate_executable`] checks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expectations {
+    /// The tool the executable should be, like `cargo`.
+    pub tool: String,
+    /// `None` for the [`Validation`] configured for `tool`.
+    pub validation: Option<Validation>,
+    /// Whether the `--version` output has to name `tool`, to catch paths to
+    /// the wrong executable, like `rustc` configured as `cargo`. Only checked
+    /// with [`Validation::Version`].
+    pub check_name: bool,
+}
+
+impl Expectations {
+    pub fn new(tool: &str) -> Expectations {
+        Expectations { tool: tool.to_string(), validation: None, check_name: false }
+    }
+}
+
+/// The result of [`validate_executable`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationOutcome {
+    pub path: PathBuf,
+    /// What was checked, in order, like `` `--version` `` then `` `--help` ``
+    /// when the first one failed.
+    pub checks: Vec<String>,
+    pub version: Option<Version>,
+    /// Why the executable can't be used, `None` if it can.
+    pub error: Option<DiscoveryError>,
+}
+
+impl ValidationOutcome {
+    pub fn is_valid(&self) -> bool {
+        self.error.is_none()
+    }
+
+    pub fn into_result(self) -> Result<Option<Version>, DiscoveryError> {
+        match self.error {
+            Some(err) => Err(err),
+            None => Ok(self.version),
+        }
+    }
+}
+
+/// Checks that `path` is a usable `expectations.tool`, for example a path
+/// from the user's configuration.
+///
+/// If the check fails because rustup left the executable broken, the error
+/// says so, see [`crate::RustupDiagnosis`]. Windows app execution aliases
+/// are not run at all and count as not found, and neither are executables
+/// which lead back to the running process.
+pub fn validate_executable(path: &Path, expectations: &Expectations) -> ValidationOutcome {
+    validate(path, expectations, None)
+}
+
+fn validate(path: &Path, expectations: &Expectations, env: Option<&Env>) -> ValidationOutcome {
+    let tool = expectations.tool.as_str();
+    let mut run = Run { tool, path, env, checks: Vec::new(), version_output: None };
+    let res = run.check(expectations);
+    let (version, error) = match res {
+        Ok(version) => (version, None),
+        Err(err) => (None, Some(err)),
+    };
+    ValidationOutcome { path: path.to_path_buf(), checks: run.checks, version, error }
+}
+
+/// Checks `path` with the [`Validation`] configured for `tool` in `env`,
+/// `None` standing for the process environment, returning its version if
+/// the validation yields one.
+///
+/// Successes are remembered per `tool`, [`Fingerprint`] of `path` and
+/// `env`. Failures are not, they may come from a download or a lock which is
+/// gone the next time.
+pub(crate) fn is_valid_executable(
+    env: Option<&Env>,
+    tool: &str,
+    path: &Path,
+) -> Result<Option<Version>, DiscoveryError> {
+    let key = Fingerprint::of(path).ok().map(|it| (tool.to_string(), it, env.cloned()));
+    if let Some(key) = &key {
+        if let Some(version) = VALIDATED.lock().unwrap().get(key) {
+            return Ok(version.clone());
+        }
+    }
+    let version = validate(path, &Expectations::new(tool), env).into_result()?;
+    if let Some(key) = key {
+        VALIDATED.lock().unwrap().insert(key, version.clone());
+    }
+    Ok(version)
+}
+
+type Validated = (String, Fingerprint, Option<Env>);
+
+static VALIDATED: Lazy<Mutex<FxHashMap<Validated, Option<Version>>>> = Lazy::new(Default::default);
+
+struct Run<'a> {
+    tool: &'a str,
+    path: &'a Path,
+    /// `None` for the process environment.
+    env: Option<&'a Env>,
+    checks: Vec<String>,
+    /// The stdout of a successful `--version`.
+    version_output: Option<String>,
+}
+
+impl Run<'_> {
+    fn check(&mut self, expectations: &Expectations) -> Result<Option<Version>, DiscoveryError> {
+        let (tool, path) = (self.tool, self.path);
+        self.checks.push("not leading back to discovery".to_string());
+        recursion::check(tool, path)?;
+        if is_app_execution_alias(path) {
+            return Err(DiscoveryError::new(ErrorKind::NotFound, tool)
+                .with_path(path.to_path_buf())
+                .with_detail(app_alias::explain(tool, path)));
+        }
+        let validation = expectations.validation.unwrap_or_else(|| validation_for(tool));
+        let version = self.validate(validation).map_err(|err| match diagnose_path(path) {
+            Some(diagnosis) => DiscoveryError::new(ErrorKind::RustupCorrupted, tool)
+                .with_path(path.to_path_buf())
+                .with_detail(diagnosis.to_string()),
+            None => err,
+        })?;
+        if expectations.check_name {
+            if let Some(output) = self.version_output.take() {
+                self.checks.push("the name in `--version`".to_string());
+                if !names_tool(&output, tool) {
+                    return Err(DiscoveryError::new(ErrorKind::WrongTool, tool)
+                        .with_path(path.to_path_buf())
+                        .with_detail(output));
+                }
+            }
+        }
+        Ok(version)
+    }
+
+    fn validate(&mut self, validation: Validation) -> Result<Option<Version>, DiscoveryError> {
+        let (tool, path) = (self.tool, self.path);
+        let error = |kind| DiscoveryError::new(kind, tool).with_path(path.to_path_buf());
+        match validation {
+            Validation::Version => match self.run(&["--version"]) {
+                Ok(stdout) => match Version::parse_output(&stdout) {
+                    Some(version) => {
+                        self.version_output = Some(stdout);
+                        Ok(Some(version))
+                    }
+                    None => self
+                        .validate(Validation::Help)
+                        .map_err(|_| error(ErrorKind::UnexpectedOutput).with_detail(stdout)),
+                },
+                Err((ErrorKind::SpawnFailed, detail)) => {
+                    Err(error(ErrorKind::SpawnFailed).with_detail(detail))
+                }
+                Err((kind, detail)) => {
+                    self.validate(Validation::Help).map_err(|_| error(kind).with_detail(detail))
+                }
+            },
+            Validation::Help => match self.run(&["--help"]) {
+                Ok(_) => Ok(None),
+                Err((kind, detail)) => Err(error(kind).with_detail(detail)),
+            },
+            Validation::NoArgs => match self.run(&[]) {
+                Err((ErrorKind::SpawnFailed, detail)) => {
+                    Err(error(ErrorKind::SpawnFailed).with_detail(detail))
+                }
+                _ => Ok(None),
+            },
+            Validation::MetadataOnly => {
+                self.checks.push("file metadata".to_string());
+                match execution_environment().metadata(path) {
+                    Ok(metadata) if metadata.is_file && metadata.is_executable => Ok(None),
+                    Ok(_) if interpreter_for(path).is_some() => Ok(None),
+                    Ok(_) => {
+                        Err(error(ErrorKind::SpawnFailed).with_detail("not executable".to_string()))
+                    }
+                    Err(err) => Err(error(ErrorKind::SpawnFailed).with_detail(err.to_string())),
+                }
+            }
+            Validation::Header => {
+                self.validate(Validation::MetadataOnly)?;
+                self.checks.push("file header".to_string());
+                match execution_environment().read_prefix(path, 4) {
+                    Ok(header) if is_executable_header(&header) => Ok(None),
+                    Ok(_) if interpreter_for(path).is_some() => Ok(None),
+                    Ok(_) => Err(error(ErrorKind::SpawnFailed)
+                        .with_detail("not an executable binary or script".to_string())),
+                    Err(err) => Err(error(ErrorKind::SpawnFailed).with_detail(err.to_string())),
+                }
+            }
+        }
+    }
+
+    fn run(&mut self, args: &[&str]) -> Result<String, (ErrorKind, String)> {
+        self.checks.push(match args {
+            [] => "running without arguments".to_string(),
+            _ => format!("`{}`", args.join(" ")),
+        });
+        run(self.path, args, self.env)
+    }
+}
+
+/// Whether the first word of a `--version` output is `tool`, like in
+/// `cargo 1.52.0`. Cargo subcommands may leave out the `cargo-` prefix,
+/// `cargo-clippy` prints `clippy 0.1.52`.
+fn names_tool(output: &str, tool: &str) -> bool {
+    let name = match output.split_whitespace().next() {
+        Some(it) => it,
+        None => return false,
+    };
+    let tool = tool.strip_suffix(std::env::consts::EXE_SUFFIX).unwrap_or(tool);
+    tool_names(tool).iter().any(|it| {
+        name == *it || it.strip_prefix("cargo-") == Some(name) || name.replace('_', "-") == *it
+    })
+}
+
+fn is_executable_header(header: &[u8]) -> bool {
+    const MAGIC: &[&[u8]] = &[
+        b"\x7fELF",
+        b"MZ",
+        // Mach-O, 32 and 64 bit in both byte orders, and universal binaries.
+        &[0xfe, 0xed, 0xfa, 0xce],
+        &[0xfe, 0xed, 0xfa, 0xcf],
+        &[0xce, 0xfa, 0xed, 0xfe],
+        &[0xcf, 0xfa, 0xed, 0xfe],
+        &[0xca, 0xfe, 0xba, 0xbe],
+        b"#!",
+    ];
+    MAGIC.iter().any(|magic| header.starts_with(magic))
+}
+
+/// Runs `path` with `args` in `env`, returning the trimmed stdout, or the
+/// kind of failure and what explains it.
+fn run(path: &Path, args: &[&str], env: Option<&Env>) -> Result<String, (ErrorKind, String)> {
+    let mut cmd = interpreter::command(path);
+    if let Some(env) = env {
+        cmd.env_clear().envs(env.vars());
+        if let Some(cwd) = env.cwd() {
+            cmd.current_dir(cwd);
+        }
+    }
+    recursion::mark(&mut cmd);
+    cmd.args(args).stdin(Stdio::null());
+    crate::registry::count_spawn();
+    let output = execution_environment()
+        .output(&mut cmd)
+        .map_err(|err| (ErrorKind::SpawnFailed, err.to_string()))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        let detail = if stderr.is_empty() { output.status.to_string() } else { stderr };
+        return Err((ErrorKind::BadExitStatus, detail));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+#[cfg(unix)]
+pub(crate) fn is_executable(metadata: &fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o111 != 0
+}
+
+#[cfg(not(unix))]
+pub(crate) fn is_executable(_metadata: &fs::Metadata) -> bool {
+    true
+}
+
+/// Resolves the value of an override variable like `$CARGO`: it has to be a
+/// path to an existing file, or a bare name found in `PATH`.
+pub(crate) fn resolve_override(
+    env: &Env,
+    tool: &str,
+    var: &str,
+    value: PathBuf,
+) -> Result<PathBuf, DiscoveryError> {
+    let is_bare_name = value.components().count() == 1 && !value.is_absolute();
+    let path = if is_bare_name {
+        crate::locate::lookup_in_path(env, &value.to_string_lossy())
+    } else {
+        Some(env.absolute(value.clone())).filter(|it| execution_environment().is_file(it))
+    };
+    path.ok_or_else(|| {
+        DiscoveryError::new(ErrorKind::EnvVarNotExecutable, tool)
+            .with_var(var.to_string())
+            .with_path(value)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testdir::TestDir;
+
+    use super::*;
+
+    fn validate(
+        tool: &str,
+        path: &Path,
+        validation: Validation,
+    ) -> Result<Option<Version>, DiscoveryError> {
+        let expectations = Expectations { validation: Some(validation), ..Expectations::new(tool) };
+        validate_executable(path, &expectations).into_result()
+    }
+
+    #[test]
+    fn rejects_non_executables() {
+        let err = is_valid_executable(None, "cargo", Path::new("/surely/not/cargo")).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::SpawnFailed);
+
+        let err = resolve_override(&Env::current(), "cargo", "CARGO", "/surely/not/cargo".into())
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::EnvVarNotExecutable);
+        assert_eq!(err.var(), Some("CARGO"));
+    }
+
+    #[test]
+    fn accepts_rustc() {
+        let rustc = crate::rustc();
+        assert!(is_valid_executable(None, "rustc", &rustc).unwrap().is_some());
+        assert_eq!(validate("rustc", &rustc, Validation::Help), Ok(None));
+        assert_eq!(validate("rustc", &rustc, Validation::MetadataOnly), Ok(None));
+        assert_eq!(validate("rustc", &rustc, Validation::Header), Ok(None));
+
+        let outcome = validate_executable(&rustc, &Expectations::new("rustc"));
+        assert!(outcome.is_valid());
+        assert_eq!(outcome.checks, ["not leading back to discovery", "`--version`"]);
+    }
+
+    #[test]
+    fn reports_wrong_tool() {
+        let rustc = crate::rustc();
+        let expectations = Expectations { check_name: true, ..Expectations::new("cargo") };
+        let outcome = validate_executable(&rustc, &expectations);
+        assert_eq!(outcome.error.unwrap().kind(), ErrorKind::WrongTool);
+        assert_eq!(outcome.checks.last().unwrap(), "the name in `--version`");
+
+        assert!(names_tool("clippy 0.1.52 (9bc8c42b 2021-05-09)", "cargo-clippy"));
+        assert!(names_tool("rust-analyzer 2021-06-07", "rust-analyzer"));
+        assert!(!names_tool("rustc 1.52.0", "cargo"));
+    }
+
+    #[test]
+    fn recognizes_executable_headers() {
+        assert!(is_executable_header(b"\x7fELF"));
+        assert!(is_executable_header(b"MZ\x90\0"));
+        assert!(is_executable_header(&[0xcf, 0xfa, 0xed, 0xfe]));
+        assert!(is_executable_header(b"#!/b"));
+        assert!(!is_executable_header(b""));
+        assert!(!is_executable_header(b"fn m"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn tools_without_version_fall_back() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let tmp = TestDir::new("validate");
+        let dir = tmp.path().to_path_buf();
+        fs::create_dir_all(&dir).unwrap();
+        let linker = dir.join("my-linker");
+        fs::write(&linker, "#!/bin/sh\ncase \"$1\" in --help) exit 0;; *) exit 1;; esac\n")
+            .unwrap();
+        fs::set_permissions(&linker, fs::Permissions::from_mode(0o755)).unwrap();
+
+        assert_eq!(validate("my-linker", &linker, Validation::Version), Ok(None));
+        assert_eq!(validate("my-linker", &linker, Validation::NoArgs), Ok(None));
+        assert_eq!(
+            validate("dir", &dir, Validation::MetadataOnly).unwrap_err().kind(),
+            ErrorKind::SpawnFailed
+        );
+
+        set_validation("my-linker", Validation::NoArgs);
+        assert_eq!(validation_for("my-linker"), Validation::NoArgs);
+        assert_eq!(validation_for("rust-lld"), Validation::MetadataOnly);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn only_successes_are_cached() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let tmp = TestDir::new("validate-cache");
+        let (cargo, ready) = (tmp.path().join("cargo"), tmp.path().join("ready"));
+        let script = format!("#!/bin/sh\n[ -e '{}' ] && echo cargo 1.52.0\n", ready.display());
+        fs::write(&cargo, script).unwrap();
+        fs::set_permissions(&cargo, fs::Permissions::from_mode(0o755)).unwrap();
+
+        assert!(is_valid_executable(None, "cargo", &cargo).is_err());
+        fs::write(&ready, "").unwrap();
+        assert!(is_valid_executable(None, "cargo", &cargo).unwrap().is_some());
+        fs::remove_file(&ready).unwrap();
+        assert!(is_valid_executable(None, "cargo", &cargo).unwrap().is_some());
+        assert!(is_valid_executable(Some(&Env::default()), "cargo", &cargo).is_err());
+    }
+}
@@ -0,0 +1,190 @@
+//! Parsing of `--version` output of Rust tools.
+
+use std::fmt;
+
+use stdx::split_once;
+
+/// A `major.minor.patch[-pre]` version, as printed by `cargo --version`,
+/// `rustc --version` and friends.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Version {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+    /// The part after `-`, like `nightly` or `beta.3`.
+    pub pre: Option<String>,
+}
+
+impl Version {
+    /// Extracts the version from the output of `<tool> --version`, like
+    /// `cargo 1.52.0 (69767412a 2021-04-21)`.
+    ///
+    /// The output is often less tidy than that: wrappers print warnings
+    /// before it, some tools print a multi-line banner, vendored toolchains
+    /// have custom suffixes and the words around the version may be
+    /// translated. So, we prefer the `name version ...` shape on a line that
+    /// isn't a diagnostic, but accept a version anywhere in the output.
+    pub fn parse_output(output: &str) -> Option<Version> {
+        let output = output.trim_start_matches('\u{feff}');
+        let is_diagnostic = |line: &str| {
+            let line = line.trim_start().to_ascii_lowercase();
+            ["warning", "error", "note", "info"].iter().any(|it| line.starts_with(it))
+        };
+        let canonical = output
+            .lines()
+            .filter(|line| !is_diagnostic(line))
+            .find_map(|line| Version::parse_word(line.split_whitespace().nth(1)?));
+        canonical.or_else(|| {
+            output.lines().flat_map(|line| line.split_whitespace()).find_map(Version::parse_word)
+        })
+    }
+
+    /// Parses a bare `1.52.0` or `1.54.0-nightly`.
+    pub fn parse(text: &str) -> Option<Version> {
+        let (text, _build_metadata) = split_once(text, '+').unwrap_or((text, ""));
+        let (numbers, pre) = match split_once(text, '-') {
+            Some((_, "")) => return None,
+            Some((numbers, pre)) => (numbers, Some(pre.to_string())),
+            None => (text, None),
+        };
+        let mut numbers = numbers.split('.').map(|it| {
+            if it.is_empty() || !it.bytes().all(|b| b.is_ascii_digit()) {
+                return None;
+            }
+            it.parse::<u32>().ok()
+        });
+        let major = numbers.next()??;
+        let minor = numbers.next()??;
+        let patch = numbers.next()??;
+        if numbers.next().is_some() {
+            return None;
+        }
+        Some(Version { major, minor, patch, pre })
+    }
+
+    /// Parses a version surrounded by the kind of punctuation version lines
+    /// have, like `v1.52.0,` or `(1.52.0)`.
+    fn parse_word(word: &str) -> Option<Version> {
+        let word = word.trim_matches(|c: char| !c.is_ascii_alphanumeric());
+        let word = word.strip_prefix('v').unwrap_or(word);
+        if !word.starts_with(|c: char| c.is_ascii_digit()) {
+            return None;
+        }
+        Version::parse(word)
+    }
+
+    /// Whether this is at least `major.minor.patch`.
+    ///
+    /// Pre-releases count as the release they precede: a `1.74.0-nightly`
+    /// has what `1.74.0` stabilized, as far as we are concerned.
+    pub fn at_least(&self, major: u32, minor: u32, patch: u32) -> bool {
+        (major, minor, patch) <= (self.major, self.minor, self.patch)
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)?;
+        if let Some(pre) = &self.pre {
+            write!(f, "-{}", pre)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_version_output() {
+        let version = Version::parse_output("cargo 1.52.0 (69767412a 2021-04-21)\n").unwrap();
+        assert_eq!(version, Version { major: 1, minor: 52, patch: 0, pre: None });
+
+        let version = Version::parse_output("rustc 1.54.0-nightly (ed597e7e1 2021-06-08)").unwrap();
+        assert_eq!(version.pre.as_deref(), Some("nightly"));
+        assert_eq!(version.to_string(), "1.54.0-nightly");
+
+        assert_eq!(Version::parse_output("cargo"), None);
+        assert_eq!(Version::parse_output("rustc 1.54"), None);
+        assert_eq!(Version::parse_output(""), None);
+    }
+
+    #[test]
+    fn parses_untidy_version_output() {
+        let check = |output: &str, expected: &str| {
+            let version = Version::parse_output(output);
+            assert_eq!(version.map(|it| it.to_string()).as_deref(), Some(expected), "{:?}", output);
+        };
+        check("cargo 1.52.0 (69767412a 2021-04-21)\r\n", "1.52.0");
+        check("\u{feff}rustc 1.52.0\r\n", "1.52.0");
+        check(
+            "warning: wrapper 2.0.0 is deprecated\nrustc 1.52.1 (9bc8c42bb 2021-05-09)",
+            "1.52.1",
+        );
+        check("rustc 1.75.0-custom (xyz)", "1.75.0-custom");
+        check("rustc 1.49.0-beta.3 (1.49.0-beta.3+abc)", "1.49.0-beta.3");
+        check("rustfmt 1.4.37-stable (1a2b3c 2021-05-10)", "1.4.37-stable");
+        check("Welcome to the frobnicator!\n\nfrobnicator, Version v0.3.1,\n", "0.3.1");
+        check("cargo Versión 1.52.0", "1.52.0");
+        check("wasm-bindgen 0.2.74+gitabc", "0.2.74");
+    }
+
+    #[test]
+    fn rejects_non_versions() {
+        for text in
+            ["1.2", "1.2.3.4", "1..3", "1.2.x", "1.2.3-", "-1.2.3", "+1.2.3", "1.2.99999999999"]
+        {
+            assert_eq!(Version::parse(text), None, "{:?}", text);
+        }
+    }
+
+    /// Throws random garbage at the parser. It must not panic, anything it
+    /// returns must survive a round trip through `Display`, and a version
+    /// line hidden in noise without digits must be found.
+    #[test]
+    fn fuzz_parse_output() {
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        const ALPHABET: &[char] = &[
+            '0', '1', '9', '.', '-', '+', ' ', '\n', '\r', 'v', 'a', '(', ')', ',', 'é', '\u{feff}',
+        ];
+        const NOISE: &[char] = &[' ', '\n', '\r', 'a', 'v', '-', '.', '(', ':', 'ß'];
+
+        for _ in 0..10_000 {
+            let len = next() % 32;
+            let garbage: String =
+                (0..len).map(|_| ALPHABET[next() as usize % ALPHABET.len()]).collect();
+            if let Some(version) = Version::parse_output(&garbage) {
+                assert_eq!(Version::parse(&version.to_string()), Some(version), "{:?}", garbage);
+            }
+
+            let noise = |next: &mut dyn FnMut() -> u64| -> String {
+                (0..next() % 40).map(|_| NOISE[next() as usize % NOISE.len()]).collect()
+            };
+            let version = Version {
+                major: (next() % 3) as u32,
+                minor: (next() % 100) as u32,
+                patch: (next() % 10) as u32,
+                pre: None,
+            };
+            let output =
+                format!("{}\ntool {} (abc)\n{}", noise(&mut next), version, noise(&mut next));
+            assert_eq!(Version::parse_output(&output), Some(version), "{:?}", output);
+        }
+    }
+
+    #[test]
+    fn pre_release_counts_as_release() {
+        let version = Version::parse("1.74.0-nightly").unwrap();
+        assert!(version.at_least(1, 74, 0));
+        assert!(version.at_least(1, 9, 9));
+        assert!(!version.at_least(1, 74, 1));
+        assert!(!version.at_least(2, 0, 0));
+    }
+}
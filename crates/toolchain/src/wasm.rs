@@ -0,0 +1,38 @@
+//! Checks whether a toolchain can build for WebAssembly.
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use crate::sysroot::{has_target_libs, sysroot};
+
+/// What is (and isn't) installed for building a wasm target.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WasmReadiness {
+    pub target: String,
+    /// Whether `rustup target add <target>` was done.
+    pub std_installed: bool,
+    pub wasm_bindgen: Option<PathBuf>,
+    pub wasm_pack: Option<PathBuf>,
+}
+
+impl WasmReadiness {
+    /// Whether `cargo check` for the target will work. `wasm-bindgen` and
+    /// `wasm-pack` are only needed later, to package the build.
+    pub fn can_check(&self) -> bool {
+        self.std_installed
+    }
+}
+
+/// Inspects `rustc`'s sysroot for `target` (usually
+/// `wasm32-unknown-unknown`) and looks for the usual packaging tools.
+pub fn wasm_readiness(rustc: &Path, target: &str) -> io::Result<WasmReadiness> {
+    let sysroot = sysroot(rustc)?;
+    Ok(WasmReadiness {
+        target: target.to_string(),
+        std_installed: has_target_libs(&sysroot, target),
+        wasm_bindgen: crate::locate::lookup("wasm-bindgen"),
+        wasm_pack: crate::locate::lookup("wasm-pack"),
+    })
+}
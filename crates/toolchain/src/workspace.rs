@@ -0,0 +1,76 @@
+//! Finds the root manifest of the Cargo workspace enclosing a directory.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::utf8_stdout;
+
+/// Returns the path to the `Cargo.toml` of the workspace which contains `dir`.
+///
+/// The answer comes from `cargo locate-project --workspace`, run with the
+/// discovered cargo. If cargo can't be run (or is too old to know the
+/// `--workspace` flag), we walk up the directory tree looking at manifests
+/// ourselves, which handles everything but `package.workspace` keys and
+/// `exclude`d members.
+pub fn locate_workspace_root(dir: &Path) -> io::Result<PathBuf> {
+    match locate_project(dir) {
+        Ok(it) => Ok(it),
+        Err(err) => walk_manifests(dir).ok_or(err),
+    }
+}
+
+fn locate_project(dir: &Path) -> io::Result<PathBuf> {
+    let mut cmd = crate::interpreter::command(&crate::cargo());
+    cmd.current_dir(dir).args(["locate-project", "--workspace", "--message-format", "plain"]);
+    let stdout = utf8_stdout(cmd)?;
+    if stdout.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "`cargo locate-project` printed nothing",
+        ));
+    }
+    Ok(PathBuf::from(stdout))
+}
+
+/// The nearest ancestor manifest with a `[workspace]` section, or, failing
+/// that, the nearest manifest at all.
+fn walk_manifests(dir: &Path) -> Option<PathBuf> {
+    let mut manifests = dir.ancestors().map(|it| it.join("Cargo.toml")).filter(|it| it.is_file());
+    let nearest = manifests.next()?;
+    if is_workspace_manifest(&nearest) {
+        return Some(nearest);
+    }
+    manifests.find(|it| is_workspace_manifest(it)).or(Some(nearest))
+}
+
+fn is_workspace_manifest(manifest: &Path) -> bool {
+    match fs::read_to_string(manifest) {
+        Ok(text) => text.lines().any(|line| line.trim() == "[workspace]"),
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn workspace_manifest() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("../../Cargo.toml").canonicalize().unwrap()
+    }
+
+    #[test]
+    fn finds_workspace_of_member_crate() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("src");
+        let root = locate_workspace_root(&dir).unwrap();
+        assert_eq!(root.canonicalize().unwrap(), workspace_manifest());
+    }
+
+    #[test]
+    fn manifest_walk_skips_member_manifest() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("src");
+        let root = walk_manifests(&dir).unwrap();
+        assert_eq!(root.canonicalize().unwrap(), workspace_manifest());
+    }
+}
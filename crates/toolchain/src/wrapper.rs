@@ -0,0 +1,110 @@
+//! The wrappers cargo runs `rustc` through.
+//!
+//! Cargo composes two of them: `RUSTC_WRAPPER` (or `build.rustc-wrapper`)
+//! wraps every `rustc` invocation, and `RUSTC_WORKSPACE_WRAPPER` (or
+//! `build.rustc-workspace-wrapper`) additionally wraps the invocations for
+//! workspace members. The command line is then
+//! `$RUSTC_WRAPPER $RUSTC_WORKSPACE_WRAPPER rustc ...`.
+
+use std::path::{Path, PathBuf};
+
+use crate::{
+    cargo_config::{config_files, config_value, unquote},
+    Env,
+};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RustcWrappers {
+    pub wrapper: Option<PathBuf>,
+    pub workspace_wrapper: Option<PathBuf>,
+}
+
+impl RustcWrappers {
+    /// The wrappers cargo would use in the working directory of `env`.
+    ///
+    /// Like for cargo, the environment takes precedence over configuration
+    /// files, and an empty value means no wrapper.
+    pub fn from_env(env: &Env) -> RustcWrappers {
+        let configs: Vec<(PathBuf, String)> = match env.cwd() {
+            Some(dir) => config_files(dir)
+                .into_iter()
+                .filter_map(|file| Some((file.clone(), std::fs::read_to_string(file).ok()?)))
+                .collect(),
+            None => Vec::new(),
+        };
+        let lookup = |var: &str, key: &str| -> Option<PathBuf> {
+            if let Some(value) = env.var_os(var) {
+                return Some(PathBuf::from(value)).filter(|it| !it.as_os_str().is_empty());
+            }
+            configs.iter().find_map(|(file, text)| {
+                let value = unquote(config_value(text, key)?);
+                Some(config_path(file, value))
+            })?
+        };
+        RustcWrappers {
+            wrapper: lookup("RUSTC_WRAPPER", "build.rustc-wrapper"),
+            workspace_wrapper: lookup("RUSTC_WORKSPACE_WRAPPER", "build.rustc-workspace-wrapper"),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.wrapper.is_none() && self.workspace_wrapper.is_none()
+    }
+
+    /// The wrappers around `rustc`, outermost first. The workspace wrapper
+    /// only applies to workspace members.
+    pub fn chain(&self, is_workspace_member: bool) -> Vec<&Path> {
+        let workspace_wrapper = self.workspace_wrapper.as_deref().filter(|_| is_workspace_member);
+        self.wrapper.as_deref().into_iter().chain(workspace_wrapper).collect()
+    }
+}
+
+/// A path from a configuration file: relative paths with a slash are
+/// relative to the directory containing `.cargo`, bare names are looked up
+/// in `PATH` when run. `None` for an empty value.
+fn config_path(file: &Path, value: &str) -> Option<PathBuf> {
+    if value.is_empty() {
+        return None;
+    }
+    let path = PathBuf::from(value);
+    if path.is_absolute() || path.components().count() == 1 {
+        return Some(path);
+    }
+    let root = file.parent().and_then(Path::parent)?;
+    Some(root.join(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::testdir::TestDir;
+
+    use super::*;
+
+    #[test]
+    fn env_overrides_config() {
+        let tmp = TestDir::new("wrappers");
+        let root = tmp.path().to_path_buf();
+        fs::create_dir_all(root.join(".cargo")).unwrap();
+        fs::write(
+            root.join(".cargo/config.toml"),
+            "[build]\nrustc-wrapper = \"sccache\"\nrustc-workspace-wrapper = \"tools/clippy\"\n",
+        )
+        .unwrap();
+        let mut env = Env::current();
+        env.remove_var("RUSTC_WRAPPER");
+        env.remove_var("RUSTC_WORKSPACE_WRAPPER");
+        env.set_cwd(root.clone());
+
+        let wrappers = RustcWrappers::from_env(&env);
+        assert_eq!(wrappers.chain(true), [Path::new("sccache"), &root.join("tools/clippy")]);
+        assert_eq!(wrappers.chain(false), [Path::new("sccache")]);
+
+        env.set_var("RUSTC_WRAPPER", "".into());
+        env.set_var("RUSTC_WORKSPACE_WRAPPER", "/opt/wrap".into());
+        let wrappers = RustcWrappers::from_env(&env);
+        assert_eq!(wrappers.chain(true), [Path::new("/opt/wrap")]);
+        assert!(wrappers.chain(false).is_empty());
+    }
+}
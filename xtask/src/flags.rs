@@ -43,6 +43,8 @@ xflags::xflags! {
         cmd metrics {
             optional --dry-run
         }
+        /// Runs toolchain discovery in simulated environments and checks the results.
+        cmd env-check {}
         /// Builds a benchmark version of rust-analyzer and puts it into `./target`.
         cmd bb
             required suffix: String
@@ -68,6 +70,7 @@ pub enum XtaskCmd {
     Promote(Promote),
     Dist(Dist),
     Metrics(Metrics),
+    EnvCheck(EnvCheck),
     Bb(Bb),
 }
 
@@ -85,9 +88,6 @@ pub struct Install {
     pub jemalloc: bool,
 }
 
-#[derive(Debug)]
-pub struct Lint;
-
 #[derive(Debug)]
 pub struct FuzzTests;
 
@@ -115,6 +115,9 @@ pub struct Metrics {
     pub dry_run: bool,
 }
 
+#[derive(Debug)]
+pub struct EnvCheck;
+
 #[derive(Debug)]
 pub struct Bb {
     pub suffix: String,
@@ -126,6 +129,10 @@ impl Xtask {
     pub fn from_env() -> xflags::Result<Self> {
         Self::from_env_()
     }
+
+    pub fn from_vec(args: Vec<std::ffi::OsString>) -> xflags::Result<Self> {
+        Self::from_vec_(args)
+    }
 }
 // generated end
 
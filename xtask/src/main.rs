@@ -48,6 +48,10 @@ fn main() -> Result<()> {
             DistCmd { nightly: flags.nightly, client_version: flags.client }.run()
         }
         flags::XtaskCmd::Metrics(cmd) => cmd.run(),
+        flags::XtaskCmd::EnvCheck(_) => {
+            cmd!("cargo run --quiet --package toolchain --example env_check").run()?;
+            Ok(())
+        }
         flags::XtaskCmd::Bb(cmd) => {
             {
                 let _d = pushd("./crates/rust-analyzer")?;